@@ -0,0 +1,40 @@
+#[cfg(target_os = "linux")]
+use std::env;
+#[cfg(target_os = "linux")]
+use fuse::{CharDevice, CuseSession, Request, ReplyData, ReplyWrite};
+
+#[cfg(target_os = "linux")]
+const HELLO_CONTENT: &[u8] = b"Hello World!\n";
+
+/// Device that reads as an endless stream of greetings and discards everything written
+#[cfg(target_os = "linux")]
+struct HelloDev;
+
+#[cfg(target_os = "linux")]
+impl CharDevice for HelloDev {
+    fn read(&mut self, _req: &Request<'_>, _fh: u64, offset: i64, size: u32, reply: ReplyData) {
+        let data: Vec<u8> = HELLO_CONTENT.iter().cycle()
+            .skip(offset as usize % HELLO_CONTENT.len())
+            .take(size as usize)
+            .cloned()
+            .collect();
+        reply.data(&data);
+    }
+
+    fn write(&mut self, _req: &Request<'_>, _fh: u64, _offset: i64, data: &[u8], _flags: u32, reply: ReplyWrite) {
+        reply.written(data.len() as u32);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    env_logger::init();
+    let devname = env::args_os().nth(1).unwrap();
+    let mut se = CuseSession::new(HelloDev, &devname, 0, 0, 0).unwrap();
+    se.run().unwrap();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("CUSE is only available on Linux");
+}
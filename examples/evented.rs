@@ -2,7 +2,7 @@ use std::env;
 use std::ffi::OsStr;
 use std::time::{Duration, UNIX_EPOCH};
 use libc::ENOENT;
-use fuse::{FileType, FileAttr, Filesystem, Request, ReplyData, ReplyEntry, ReplyAttr, ReplyDirectory};
+use fuse::{FileType, FileAttr, Filesystem, HandleStatus, Request, ReplyData, ReplyEntry, ReplyAttr, ReplyDirectory};
 use mio::{Events, Poll, PollOpt, Ready, Token};
 
 const TTL: Duration = Duration::from_secs(1);           // 1 second
@@ -99,14 +99,14 @@ fn main() {
         .collect::<Vec<&OsStr>>();
     let mut session = fuse::evented(HelloFS, mountpoint, &options).unwrap();
     let poll = Poll::new().unwrap();
-    let mut buf: Vec<u8> = Vec::with_capacity((16 * 1024 * 1024) + 4096);
     let mut events = Events::with_capacity(1024);
     poll.register(&session, Token(1), Ready::readable(), PollOpt::level()).unwrap();
-    loop {
+    while !session.is_ended() {
         poll.poll(&mut events, None).unwrap();
         for e in events.iter().filter(|evt|!evt.readiness().is_empty()) {
             dbg!(e);
-            session.try_handle(&mut buf).unwrap();
+            // Handle all available requests until the session would block
+            while session.try_handle().unwrap() == HandleStatus::Handled {}
         }
     }
 }
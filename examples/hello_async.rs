@@ -0,0 +1,101 @@
+use std::env;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+use libc::{c_int, ENOENT};
+use fuse::{AsyncFilesystem, AsyncSession, Attr, DirEntry, Entry, FileType, FileAttr, RequestInfo};
+
+const TTL: Duration = Duration::from_secs(1);           // 1 second
+
+const HELLO_DIR_ATTR: FileAttr = FileAttr {
+    ino: 1,
+    size: 0,
+    blocks: 0,
+    atime: UNIX_EPOCH,                                  // 1970-01-01 00:00:00
+    mtime: UNIX_EPOCH,
+    ctime: UNIX_EPOCH,
+    crtime: UNIX_EPOCH,
+    kind: FileType::Directory,
+    perm: 0o755,
+    nlink: 2,
+    uid: 501,
+    gid: 20,
+    rdev: 0,
+    flags: 0,
+};
+
+const HELLO_TXT_CONTENT: &str = "Hello World!\n";
+
+const HELLO_TXT_ATTR: FileAttr = FileAttr {
+    ino: 2,
+    size: 13,
+    blocks: 1,
+    atime: UNIX_EPOCH,                                  // 1970-01-01 00:00:00
+    mtime: UNIX_EPOCH,
+    ctime: UNIX_EPOCH,
+    crtime: UNIX_EPOCH,
+    kind: FileType::RegularFile,
+    perm: 0o644,
+    nlink: 1,
+    uid: 501,
+    gid: 20,
+    rdev: 0,
+    flags: 0,
+};
+
+struct HelloFS;
+
+impl AsyncFilesystem for HelloFS {
+    async fn lookup(&self, _req: &RequestInfo, parent: u64, name: &OsStr) -> Result<Entry, c_int> {
+        if parent == 1 && name.to_str() == Some("hello.txt") {
+            Ok(Entry { ttl: TTL, attr: HELLO_TXT_ATTR, generation: 0 })
+        } else {
+            Err(ENOENT)
+        }
+    }
+
+    async fn getattr(&self, _req: &RequestInfo, ino: u64) -> Result<Attr, c_int> {
+        match ino {
+            1 => Ok(Attr { ttl: TTL, attr: HELLO_DIR_ATTR }),
+            2 => Ok(Attr { ttl: TTL, attr: HELLO_TXT_ATTR }),
+            _ => Err(ENOENT),
+        }
+    }
+
+    async fn read(&self, _req: &RequestInfo, ino: u64, _fh: u64, offset: i64, _size: u32) -> Result<Vec<u8>, c_int> {
+        if ino == 2 {
+            Ok(HELLO_TXT_CONTENT.as_bytes()[offset as usize..].to_vec())
+        } else {
+            Err(ENOENT)
+        }
+    }
+
+    async fn readdir(&self, _req: &RequestInfo, ino: u64, _fh: u64, offset: i64) -> Result<Vec<DirEntry>, c_int> {
+        if ino != 1 {
+            return Err(ENOENT);
+        }
+
+        let entries = vec![
+            (1, FileType::Directory, "."),
+            (1, FileType::Directory, ".."),
+            (2, FileType::RegularFile, "hello.txt"),
+        ];
+
+        Ok(entries.into_iter().enumerate().skip(offset as usize).map(|(i, entry)| {
+            // i + 1 means the index of the next entry
+            DirEntry { ino: entry.0, offset: (i + 1) as i64, kind: entry.1, name: entry.2.into() }
+        }).collect())
+    }
+}
+
+fn main() {
+    env_logger::init();
+    let mountpoint = env::args_os().nth(1).unwrap();
+    let options = ["-o", "ro", "-o", "fsname=hello"]
+        .iter()
+        .map(|o| o.as_ref())
+        .collect::<Vec<&OsStr>>();
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+    let mut se = AsyncSession::new(HelloFS, Path::new(&mountpoint), &options).unwrap();
+    runtime.block_on(se.run()).unwrap();
+}
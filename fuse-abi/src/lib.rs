@@ -16,6 +16,8 @@
 //! - supports ABI 7.19 since FUSE 2.9.1
 //! - supports ABI 7.26 since FUSE 3.0.0
 //!
+//! Linux kernel: <https://github.com/torvalds/linux/blob/master/include/uapi/linux/fuse.h>
+//!
 //! Items without a version annotation are valid with ABI 7.8 and later
 
 #![warn(missing_debug_implementations, rust_2018_idioms)]
@@ -47,8 +49,60 @@ pub const FUSE_KERNEL_MINOR_VERSION: u32 = 16;
 pub const FUSE_KERNEL_MINOR_VERSION: u32 = 17;
 #[cfg(all(feature = "abi-7-18", not(feature = "abi-7-19")))]
 pub const FUSE_KERNEL_MINOR_VERSION: u32 = 18;
-#[cfg(feature = "abi-7-19")]
+#[cfg(all(feature = "abi-7-19", not(feature = "abi-7-20")))]
 pub const FUSE_KERNEL_MINOR_VERSION: u32 = 19;
+#[cfg(all(feature = "abi-7-20", not(feature = "abi-7-21")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 20;
+#[cfg(all(feature = "abi-7-21", not(feature = "abi-7-22")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 21;
+#[cfg(all(feature = "abi-7-22", not(feature = "abi-7-23")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 22;
+#[cfg(all(feature = "abi-7-23", not(feature = "abi-7-24")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 23;
+#[cfg(all(feature = "abi-7-24", not(feature = "abi-7-25")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 24;
+#[cfg(all(feature = "abi-7-25", not(feature = "abi-7-26")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 25;
+#[cfg(all(feature = "abi-7-26", not(feature = "abi-7-27")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 26;
+#[cfg(all(feature = "abi-7-27", not(feature = "abi-7-28")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 27;
+#[cfg(all(feature = "abi-7-28", not(feature = "abi-7-29")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 28;
+#[cfg(all(feature = "abi-7-29", not(feature = "abi-7-30")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 29;
+#[cfg(all(feature = "abi-7-30", not(feature = "abi-7-31")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 30;
+#[cfg(all(feature = "abi-7-31", not(feature = "abi-7-32")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 31;
+#[cfg(all(feature = "abi-7-32", not(feature = "abi-7-33")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 32;
+#[cfg(all(feature = "abi-7-33", not(feature = "abi-7-34")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 33;
+#[cfg(all(feature = "abi-7-34", not(feature = "abi-7-35")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 34;
+#[cfg(all(feature = "abi-7-35", not(feature = "abi-7-36")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 35;
+#[cfg(all(feature = "abi-7-36", not(feature = "abi-7-37")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 36;
+#[cfg(all(feature = "abi-7-37", not(feature = "abi-7-38")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 37;
+#[cfg(all(feature = "abi-7-38", not(feature = "abi-7-39")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 38;
+#[cfg(all(feature = "abi-7-39", not(feature = "abi-7-40")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 39;
+#[cfg(all(feature = "abi-7-40", not(feature = "abi-7-41")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 40;
+#[cfg(all(feature = "abi-7-41", not(feature = "abi-7-42")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 41;
+#[cfg(all(feature = "abi-7-42", not(feature = "abi-7-43")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 42;
+#[cfg(all(feature = "abi-7-43", not(feature = "abi-7-44")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 43;
+#[cfg(all(feature = "abi-7-44", not(feature = "abi-7-45")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 44;
+#[cfg(feature = "abi-7-45")]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 45;
 
 pub const FUSE_ROOT_ID: u64 = 1;
 
@@ -77,8 +131,10 @@ pub struct fuse_attr {
     pub flags: u32,                                     // see chflags(2)
     #[cfg(feature = "abi-7-9")]
     pub blksize: u32,
-    #[cfg(feature = "abi-7-9")]
+    #[cfg(all(feature = "abi-7-9", any(not(feature = "abi-7-32"), target_os = "macos")))]
     pub padding: u32,
+    #[cfg(all(feature = "abi-7-32", not(target_os = "macos")))]
+    pub flags: u32,                                     // FUSE_ATTR_* flags
 }
 
 #[repr(C)]
@@ -120,6 +176,10 @@ pub mod consts {
     pub const FATTR_MTIME_NOW: u32          = 1 << 8;
     #[cfg(feature = "abi-7-9")]
     pub const FATTR_LOCKOWNER: u32          = 1 << 9;
+    #[cfg(feature = "abi-7-23")]
+    pub const FATTR_CTIME: u32              = 1 << 10;
+    #[cfg(feature = "abi-7-33")]
+    pub const FATTR_KILL_SUIDGID: u32       = 1 << 11;
 
     #[cfg(target_os = "macos")]
     pub const FATTR_CRTIME: u32             = 1 << 28;
@@ -135,6 +195,16 @@ pub mod consts {
     pub const FOPEN_KEEP_CACHE: u32         = 1 << 1;   // don't invalidate the data cache on open
     #[cfg(feature = "abi-7-10")]
     pub const FOPEN_NONSEEKABLE: u32        = 1 << 2;   // the file is not seekable
+    #[cfg(feature = "abi-7-28")]
+    pub const FOPEN_CACHE_DIR: u32          = 1 << 3;   // allow caching this directory
+    #[cfg(feature = "abi-7-30")]
+    pub const FOPEN_STREAM: u32             = 1 << 4;   // the file is stream-like (no file position at all)
+    #[cfg(feature = "abi-7-35")]
+    pub const FOPEN_NOFLUSH: u32            = 1 << 5;   // don't flush data cache on close (unless FUSE_WRITEBACK_CACHE)
+    #[cfg(feature = "abi-7-38")]
+    pub const FOPEN_PARALLEL_DIRECT_WRITES: u32 = 1 << 6; // allow concurrent direct writes on the same inode
+    #[cfg(feature = "abi-7-40")]
+    pub const FOPEN_PASSTHROUGH: u32        = 1 << 7;   // passthrough read/write io for this open file
 
     #[cfg(target_os = "macos")]
     pub const FOPEN_PURGE_ATTR: u32         = 1 << 30;
@@ -142,42 +212,102 @@ pub mod consts {
     pub const FOPEN_PURGE_UBC: u32          = 1 << 31;
 
     // Init request/reply flags
-    pub const FUSE_ASYNC_READ: u32          = 1 << 0;   // asynchronous read requests
-    pub const FUSE_POSIX_LOCKS: u32         = 1 << 1;   // remote locking for POSIX file locks
+    pub const FUSE_ASYNC_READ: u64          = 1 << 0;   // asynchronous read requests
+    pub const FUSE_POSIX_LOCKS: u64         = 1 << 1;   // remote locking for POSIX file locks
     #[cfg(feature = "abi-7-9")]
-    pub const FUSE_FILE_OPS: u32            = 1 << 2;   // kernel sends file handle for fstat, etc...
+    pub const FUSE_FILE_OPS: u64            = 1 << 2;   // kernel sends file handle for fstat, etc...
     #[cfg(feature = "abi-7-9")]
-    pub const FUSE_ATOMIC_O_TRUNC: u32      = 1 << 3;   // handles the O_TRUNC open flag in the filesystem
+    pub const FUSE_ATOMIC_O_TRUNC: u64      = 1 << 3;   // handles the O_TRUNC open flag in the filesystem
     #[cfg(feature = "abi-7-10")]
-    pub const FUSE_EXPORT_SUPPORT: u32      = 1 << 4;   // filesystem handles lookups of "." and ".."
+    pub const FUSE_EXPORT_SUPPORT: u64      = 1 << 4;   // filesystem handles lookups of "." and ".."
     #[cfg(feature = "abi-7-9")]
-    pub const FUSE_BIG_WRITES: u32          = 1 << 5;   // filesystem can handle write size larger than 4kB
+    pub const FUSE_BIG_WRITES: u64          = 1 << 5;   // filesystem can handle write size larger than 4kB
     #[cfg(feature = "abi-7-12")]
-    pub const FUSE_DONT_MASK: u32           = 1 << 6;   // don't apply umask to file mode on create operations
+    pub const FUSE_DONT_MASK: u64           = 1 << 6;   // don't apply umask to file mode on create operations
 
     #[cfg(all(feature = "abi-7-14", not(target_os = "macos")))]
-    pub const FUSE_SPLICE_WRITE: u32        = 1 << 7;   // kernel supports splice write on the device
+    pub const FUSE_SPLICE_WRITE: u64        = 1 << 7;   // kernel supports splice write on the device
     #[cfg(all(feature = "abi-7-14", not(target_os = "macos")))]
-    pub const FUSE_SPLICE_MOVE: u32         = 1 << 8;   // kernel supports splice move on the device
-    #[cfg(not(target_os = "macos"))]
-
-    #[cfg(feature = "abi-7-14")]
-    pub const FUSE_SPLICE_READ: u32         = 1 << 9;   // kernel supports splice read on the device
+    pub const FUSE_SPLICE_MOVE: u64         = 1 << 8;   // kernel supports splice move on the device
+    #[cfg(all(feature = "abi-7-14", not(target_os = "macos")))]
+    pub const FUSE_SPLICE_READ: u64         = 1 << 9;   // kernel supports splice read on the device
     #[cfg(feature = "abi-7-17")]
-    pub const FUSE_FLOCK_LOCKS: u32         = 1 << 10;  // remote locking for BSD style file locks
+    pub const FUSE_FLOCK_LOCKS: u64         = 1 << 10;  // remote locking for BSD style file locks
     #[cfg(feature = "abi-7-18")]
-    pub const FUSE_HAS_IOCTL_DIR: u32       = 1 << 11;  // kernel supports ioctl on directories
+    pub const FUSE_HAS_IOCTL_DIR: u64       = 1 << 11;  // kernel supports ioctl on directories
+    #[cfg(feature = "abi-7-20")]
+    pub const FUSE_AUTO_INVAL_DATA: u64     = 1 << 12;  // automatically invalidate cached pages
+    #[cfg(feature = "abi-7-21")]
+    pub const FUSE_DO_READDIRPLUS: u64      = 1 << 13;  // do READDIRPLUS (READDIR+LOOKUP in one)
+    #[cfg(feature = "abi-7-21")]
+    pub const FUSE_READDIRPLUS_AUTO: u64    = 1 << 14;  // adaptive readdirplus
+    #[cfg(feature = "abi-7-22")]
+    pub const FUSE_ASYNC_DIO: u64           = 1 << 15;  // asynchronous direct I/O submission
+    #[cfg(feature = "abi-7-23")]
+    pub const FUSE_WRITEBACK_CACHE: u64     = 1 << 16;  // use writeback cache for buffered writes
+    #[cfg(feature = "abi-7-23")]
+    pub const FUSE_NO_OPEN_SUPPORT: u64     = 1 << 17;  // kernel supports zero-message opens
+    #[cfg(feature = "abi-7-25")]
+    pub const FUSE_PARALLEL_DIROPS: u64     = 1 << 18;  // allow parallel lookups and readdir
+    #[cfg(feature = "abi-7-26")]
+    pub const FUSE_HANDLE_KILLPRIV: u64     = 1 << 19;  // filesystem resets suid/sgid/caps on write, chown and trunc
+    #[cfg(feature = "abi-7-26")]
+    pub const FUSE_POSIX_ACL: u64           = 1 << 20;  // filesystem supports posix acls
+    #[cfg(feature = "abi-7-27")]
+    pub const FUSE_ABORT_ERROR: u64         = 1 << 21;  // reading the device after abort returns ECONNABORTED
+    #[cfg(feature = "abi-7-28")]
+    pub const FUSE_MAX_PAGES: u64           = 1 << 22;  // init_out.max_pages contains the max number of req pages
+    #[cfg(feature = "abi-7-28")]
+    pub const FUSE_CACHE_SYMLINKS: u64      = 1 << 23;  // cache READLINK responses
+    #[cfg(feature = "abi-7-29")]
+    pub const FUSE_NO_OPENDIR_SUPPORT: u64  = 1 << 24;  // kernel supports zero-message opendir
+    #[cfg(feature = "abi-7-30")]
+    pub const FUSE_EXPLICIT_INVAL_DATA: u64 = 1 << 25;  // only invalidate cached pages on explicit request
+    #[cfg(feature = "abi-7-31")]
+    pub const FUSE_MAP_ALIGNMENT: u64       = 1 << 26;  // init_out.map_alignment contains log2(byte alignment)
+    #[cfg(all(feature = "abi-7-32", not(target_os = "macos")))]
+    pub const FUSE_SUBMOUNTS: u64           = 1 << 27;  // kernel supports auto-mounting directory submounts
+    #[cfg(all(feature = "abi-7-33", not(target_os = "macos")))]
+    pub const FUSE_HANDLE_KILLPRIV_V2: u64  = 1 << 28;  // filesystem kills suid/sgid/caps on write, chown and trunc
+    #[cfg(all(feature = "abi-7-33", not(target_os = "macos")))]
+    pub const FUSE_SETXATTR_EXT: u64        = 1 << 29;  // server supports extended struct fuse_setxattr_in
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    pub const FUSE_INIT_EXT: u64            = 1 << 30;  // extended fuse_init_in request
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    pub const FUSE_INIT_RESERVED: u64       = 1 << 31;  // reserved, do not use
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    pub const FUSE_SECURITY_CTX: u64        = 1 << 32;  // add security context to create, mkdir, symlink and mknod
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    pub const FUSE_HAS_INODE_DAX: u64       = 1 << 33;  // use per inode DAX
+    #[cfg(all(feature = "abi-7-38", not(target_os = "macos")))]
+    pub const FUSE_CREATE_SUPP_GROUP: u64   = 1 << 34;  // add supplementary group info to create, mkdir, symlink and mknod
+    #[cfg(all(feature = "abi-7-38", not(target_os = "macos")))]
+    pub const FUSE_HAS_EXPIRE_ONLY: u64     = 1 << 35;  // kernel supports expiry-only entry invalidation
+    #[cfg(all(feature = "abi-7-39", not(target_os = "macos")))]
+    pub const FUSE_DIRECT_IO_ALLOW_MMAP: u64= 1 << 36;  // allow shared mmap in FOPEN_DIRECT_IO mode
+    #[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+    pub const FUSE_PASSTHROUGH: u64         = 1 << 37;  // passthrough mode for read/write io
+    #[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+    pub const FUSE_NO_EXPORT_SUPPORT: u64   = 1 << 38;  // explicitly disable export support
+    #[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+    pub const FUSE_HAS_RESEND: u64          = 1 << 39;  // kernel supports resending pending requests
+    #[cfg(all(feature = "abi-7-41", not(target_os = "macos")))]
+    pub const FUSE_ALLOW_IDMAP: u64         = 1 << 40;  // allow creation of idmapped mounts
+    #[cfg(all(feature = "abi-7-42", not(target_os = "macos")))]
+    pub const FUSE_OVER_IO_URING: u64       = 1 << 41;  // indicate that client supports io-uring
+    #[cfg(all(feature = "abi-7-43", not(target_os = "macos")))]
+    pub const FUSE_REQUEST_TIMEOUT: u64     = 1 << 42;  // kernel supports timing out requests
 
     #[cfg(target_os = "macos")]
-    pub const FUSE_ALLOCATE: u32            = 1 << 27;
+    pub const FUSE_ALLOCATE: u64            = 1 << 27;
     #[cfg(target_os = "macos")]
-    pub const FUSE_EXCHANGE_DATA: u32       = 1 << 28;
+    pub const FUSE_EXCHANGE_DATA: u64       = 1 << 28;
     #[cfg(target_os = "macos")]
-    pub const FUSE_CASE_INSENSITIVE: u32    = 1 << 29;
+    pub const FUSE_CASE_INSENSITIVE: u64    = 1 << 29;
     #[cfg(target_os = "macos")]
-    pub const FUSE_VOL_RENAME: u32          = 1 << 30;
+    pub const FUSE_VOL_RENAME: u64          = 1 << 30;
     #[cfg(target_os = "macos")]
-    pub const FUSE_XTIMES: u32              = 1 << 31;
+    pub const FUSE_XTIMES: u64              = 1 << 31;
 
     // CUSE init request/reply flags
     #[cfg(feature = "abi-7-12")]
@@ -201,6 +331,10 @@ pub mod consts {
     pub const FUSE_WRITE_CACHE: u32         = 1 << 0;   // delayed write from page cache, file handle is guessed
     #[cfg(feature = "abi-7-9")]
     pub const FUSE_WRITE_LOCKOWNER: u32     = 1 << 1;   // lock_owner field is valid
+    #[cfg(feature = "abi-7-31")]
+    pub const FUSE_WRITE_KILL_PRIV: u32     = 1 << 2;   // kill suid and sgid bits
+    #[cfg(feature = "abi-7-33")]
+    pub const FUSE_WRITE_KILL_SUIDGID: u32  = FUSE_WRITE_KILL_PRIV;
 
     // Read flags
     #[cfg(feature = "abi-7-9")]
@@ -217,6 +351,8 @@ pub mod consts {
     pub const FUSE_IOCTL_32BIT: u32         = 1 << 3;   // 32bit ioctl
     #[cfg(feature = "abi-7-18")]
     pub const FUSE_IOCTL_DIR: u32           = 1 << 4;   // is a directory
+    #[cfg(feature = "abi-7-30")]
+    pub const FUSE_IOCTL_COMPAT_X32: u32    = 1 << 5;   // x32 compat ioctl on 64bit machine (64bit time_t)
     #[cfg(feature = "abi-7-11")]
     pub const FUSE_IOCTL_MAX_IOV: u32       = 256;      // maximum of in_iovecs + out_iovecs
 
@@ -224,8 +360,95 @@ pub mod consts {
     #[cfg(feature = "abi-7-9")]
     pub const FUSE_POLL_SCHEDULE_NOTIFY: u32= 1 << 0;   // request poll notify
 
+    // Fsync flags
+    pub const FUSE_FSYNC_FDATASYNC: u32     = 1 << 0;   // sync data only, not metadata
+
+    // Attribute flags (fuse_attr.flags)
+    #[cfg(all(feature = "abi-7-32", not(target_os = "macos")))]
+    pub const FUSE_ATTR_SUBMOUNT: u32       = 1 << 0;   // object is a submount root
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    pub const FUSE_ATTR_DAX: u32            = 1 << 1;   // enable DAX for this file in per inode DAX mode
+
+    // Open flags (fuse_open_in.open_flags, fuse_create_in.open_flags)
+    #[cfg(feature = "abi-7-33")]
+    pub const FUSE_OPEN_KILL_SUIDGID: u32   = 1 << 0;   // kill suid and sgid if executable
+
+    // Setxattr flags (fuse_setxattr_in.setxattr_flags)
+    #[cfg(all(feature = "abi-7-33", not(target_os = "macos")))]
+    pub const FUSE_SETXATTR_ACL_KILL_SGID: u32 = 1 << 0; // clear SGID when system.posix_acl_access is set
+
+    // Entry invalidation flags (fuse_notify_inval_entry_out.flags)
+    #[cfg(feature = "abi-7-38")]
+    pub const FUSE_EXPIRE_ONLY: u32         = 1 << 0;   // only expire the entry, don't unhash it
+
+    // Setupmapping flags
+    #[cfg(feature = "abi-7-31")]
+    pub const FUSE_SETUPMAPPING_FLAG_WRITE: u64 = 1 << 0;
+    #[cfg(feature = "abi-7-31")]
+    pub const FUSE_SETUPMAPPING_FLAG_READ: u64 = 1 << 1;
+
+    // Maximum number of security contexts sent with a request
+    #[cfg(feature = "abi-7-36")]
+    pub const FUSE_MAX_NR_SECCTX: u32       = 31;
+
+    // Request extension types (fuse_ext_header.typ)
+    #[cfg(feature = "abi-7-38")]
+    pub const FUSE_EXT_GROUPS: u32          = 32;       // supplementary groups (fuse_supp_groups)
+
+    // Device ioctls (Linux only)
+    #[cfg(target_os = "linux")]
+    pub const FUSE_DEV_IOC_MAGIC: u8        = 229;
+    #[cfg(target_os = "linux")]
+    pub const FUSE_DEV_IOC_CLONE: u64       = ioc(IOC_READ, 0, 4);
+    #[cfg(all(feature = "abi-7-40", target_os = "linux"))]
+    pub const FUSE_DEV_IOC_BACKING_OPEN: u64 = ioc(IOC_WRITE, 1, 16);
+    #[cfg(all(feature = "abi-7-40", target_os = "linux"))]
+    pub const FUSE_DEV_IOC_BACKING_CLOSE: u64 = ioc(IOC_WRITE, 2, 4);
+
     // The read buffer is required to be at least 8k, but may be much larger
     pub const FUSE_MIN_READ_BUFFER: usize   = 8192;
+
+    // Sizes of structures exchanged with kernels that speak an older ABI version
+    #[cfg(not(target_os = "macos"))]
+    pub const FUSE_COMPAT_ENTRY_OUT_SIZE: usize = 120;  // fuse_entry_out before ABI 7.9
+    #[cfg(target_os = "macos")]
+    pub const FUSE_COMPAT_ENTRY_OUT_SIZE: usize = 136;  // fuse_entry_out before ABI 7.9
+    #[cfg(not(target_os = "macos"))]
+    pub const FUSE_COMPAT_ATTR_OUT_SIZE: usize = 96;    // fuse_attr_out before ABI 7.9
+    #[cfg(target_os = "macos")]
+    pub const FUSE_COMPAT_ATTR_OUT_SIZE: usize = 112;   // fuse_attr_out before ABI 7.9
+    pub const FUSE_COMPAT_MKNOD_IN_SIZE: usize = 8;     // fuse_mknod_in before ABI 7.12
+    pub const FUSE_COMPAT_CREATE_IN_SIZE: usize = 8;    // fuse_create_in before ABI 7.12
+    pub const FUSE_COMPAT_READ_IN_SIZE: usize = 24;     // fuse_read_in before ABI 7.9
+    pub const FUSE_COMPAT_WRITE_IN_SIZE: usize = 24;    // fuse_write_in before ABI 7.9
+    pub const FUSE_COMPAT_LK_IN_SIZE: usize = 40;       // fuse_lk_in before ABI 7.9
+    pub const FUSE_COMPAT_INIT_IN_SIZE: usize = 16;     // fuse_init_in before ABI 7.36
+    pub const FUSE_COMPAT_22_INIT_OUT_SIZE: usize = 24; // fuse_init_out before ABI 7.23
+    #[cfg(not(target_os = "macos"))]
+    pub const FUSE_COMPAT_SETXATTR_IN_SIZE: usize = 8;  // fuse_setxattr_in without FUSE_SETXATTR_EXT
+
+    // Linux _IOC encoding of the device ioctl numbers (direction bits already shifted)
+    #[cfg(all(target_os = "linux", any(target_arch = "powerpc", target_arch = "powerpc64",
+                                       target_arch = "mips", target_arch = "mips64",
+                                       target_arch = "sparc", target_arch = "sparc64")))]
+    const IOC_READ: u64 = 2 << 29;
+    #[cfg(all(feature = "abi-7-40", target_os = "linux", any(target_arch = "powerpc", target_arch = "powerpc64",
+                                       target_arch = "mips", target_arch = "mips64",
+                                       target_arch = "sparc", target_arch = "sparc64")))]
+    const IOC_WRITE: u64 = 4 << 29;
+    #[cfg(all(target_os = "linux", not(any(target_arch = "powerpc", target_arch = "powerpc64",
+                                           target_arch = "mips", target_arch = "mips64",
+                                           target_arch = "sparc", target_arch = "sparc64"))))]
+    const IOC_READ: u64 = 2 << 30;
+    #[cfg(all(feature = "abi-7-40", target_os = "linux", not(any(target_arch = "powerpc", target_arch = "powerpc64",
+                                           target_arch = "mips", target_arch = "mips64",
+                                           target_arch = "sparc", target_arch = "sparc64"))))]
+    const IOC_WRITE: u64 = 1 << 30;
+
+    #[cfg(target_os = "linux")]
+    const fn ioc(dir: u64, nr: u64, size: u64) -> u64 {
+        dir | (size << 16) | ((FUSE_DEV_IOC_MAGIC as u64) << 8) | nr
+    }
 }
 
 /// Invalid opcode error.
@@ -282,6 +505,26 @@ pub enum fuse_opcode {
     FUSE_BATCH_FORGET = 42,
     #[cfg(feature = "abi-7-19")]
     FUSE_FALLOCATE = 43,
+    #[cfg(feature = "abi-7-21")]
+    FUSE_READDIRPLUS = 44,
+    #[cfg(feature = "abi-7-23")]
+    FUSE_RENAME2 = 45,
+    #[cfg(feature = "abi-7-24")]
+    FUSE_LSEEK = 46,
+    #[cfg(feature = "abi-7-28")]
+    FUSE_COPY_FILE_RANGE = 47,
+    #[cfg(feature = "abi-7-31")]
+    FUSE_SETUPMAPPING = 48,
+    #[cfg(feature = "abi-7-31")]
+    FUSE_REMOVEMAPPING = 49,
+    #[cfg(feature = "abi-7-34")]
+    FUSE_SYNCFS = 50,
+    #[cfg(feature = "abi-7-37")]
+    FUSE_TMPFILE = 51,
+    #[cfg(feature = "abi-7-39")]
+    FUSE_STATX = 52,
+    #[cfg(feature = "abi-7-45")]
+    FUSE_COPY_FILE_RANGE_64 = 53,
 
     #[cfg(target_os = "macos")]
     FUSE_SETVOLNAME = 61,
@@ -345,6 +588,26 @@ impl TryFrom<u32> for fuse_opcode {
             42 => Ok(fuse_opcode::FUSE_BATCH_FORGET),
             #[cfg(feature = "abi-7-19")]
             43 => Ok(fuse_opcode::FUSE_FALLOCATE),
+            #[cfg(feature = "abi-7-21")]
+            44 => Ok(fuse_opcode::FUSE_READDIRPLUS),
+            #[cfg(feature = "abi-7-23")]
+            45 => Ok(fuse_opcode::FUSE_RENAME2),
+            #[cfg(feature = "abi-7-24")]
+            46 => Ok(fuse_opcode::FUSE_LSEEK),
+            #[cfg(feature = "abi-7-28")]
+            47 => Ok(fuse_opcode::FUSE_COPY_FILE_RANGE),
+            #[cfg(feature = "abi-7-31")]
+            48 => Ok(fuse_opcode::FUSE_SETUPMAPPING),
+            #[cfg(feature = "abi-7-31")]
+            49 => Ok(fuse_opcode::FUSE_REMOVEMAPPING),
+            #[cfg(feature = "abi-7-34")]
+            50 => Ok(fuse_opcode::FUSE_SYNCFS),
+            #[cfg(feature = "abi-7-37")]
+            51 => Ok(fuse_opcode::FUSE_TMPFILE),
+            #[cfg(feature = "abi-7-39")]
+            52 => Ok(fuse_opcode::FUSE_STATX),
+            #[cfg(feature = "abi-7-45")]
+            53 => Ok(fuse_opcode::FUSE_COPY_FILE_RANGE_64),
 
             #[cfg(target_os = "macos")]
             61 => Ok(fuse_opcode::FUSE_SETVOLNAME),
@@ -383,6 +646,12 @@ pub enum fuse_notify_code {
     FUSE_NOTIFY_RETRIEVE = 5,
     #[cfg(feature = "abi-7-18")]
     FUSE_NOTIFY_DELETE = 6,
+    #[cfg(feature = "abi-7-40")]
+    FUSE_NOTIFY_RESEND = 7,
+    #[cfg(feature = "abi-7-44")]
+    FUSE_NOTIFY_INC_EPOCH = 8,
+    #[cfg(feature = "abi-7-45")]
+    FUSE_NOTIFY_PRUNE = 9,
 }
 
 #[cfg(feature = "abi-7-11")]
@@ -403,6 +672,12 @@ impl TryFrom<u32> for fuse_notify_code {
             5 => Ok(fuse_notify_code::FUSE_NOTIFY_RETRIEVE),
             #[cfg(feature = "abi-7-18")]
             6 => Ok(fuse_notify_code::FUSE_NOTIFY_DELETE),
+            #[cfg(feature = "abi-7-40")]
+            7 => Ok(fuse_notify_code::FUSE_NOTIFY_RESEND),
+            #[cfg(feature = "abi-7-44")]
+            8 => Ok(fuse_notify_code::FUSE_NOTIFY_INC_EPOCH),
+            #[cfg(feature = "abi-7-45")]
+            9 => Ok(fuse_notify_code::FUSE_NOTIFY_PRUNE),
 
             _ => Err(InvalidNotifyCodeError),
         }
@@ -498,6 +773,15 @@ pub struct fuse_rename_in {
     pub newdir: u64,
 }
 
+#[cfg(feature = "abi-7-23")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_rename2_in {
+    pub newdir: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
 #[cfg(target_os = "macos")]
 #[repr(C)]
 #[derive(Debug)]
@@ -526,10 +810,16 @@ pub struct fuse_setattr_in {
     pub lock_owner: u64,
     pub atime: u64,
     pub mtime: u64,
+    #[cfg(not(feature = "abi-7-23"))]
     pub unused2: u64,
+    #[cfg(feature = "abi-7-23")]
+    pub ctime: u64,
     pub atimensec: u32,
     pub mtimensec: u32,
+    #[cfg(not(feature = "abi-7-23"))]
     pub unused3: u32,
+    #[cfg(feature = "abi-7-23")]
+    pub ctimensec: u32,
     pub mode: u32,
     pub unused4: u32,
     pub uid: u32,
@@ -555,7 +845,10 @@ pub struct fuse_setattr_in {
 #[derive(Debug)]
 pub struct fuse_open_in {
     pub flags: u32,
+    #[cfg(not(feature = "abi-7-33"))]
     pub unused: u32,
+    #[cfg(feature = "abi-7-33")]
+    pub open_flags: u32,                                // FUSE_OPEN_* flags
 }
 
 #[repr(C)]
@@ -565,8 +858,10 @@ pub struct fuse_create_in {
     pub mode: u32,
     #[cfg(feature = "abi-7-12")]
     pub umask: u32,
-    #[cfg(feature = "abi-7-12")]
+    #[cfg(all(feature = "abi-7-12", not(feature = "abi-7-33")))]
     pub padding: u32,
+    #[cfg(feature = "abi-7-33")]
+    pub open_flags: u32,                                // FUSE_OPEN_* flags
 }
 
 #[repr(C)]
@@ -574,7 +869,10 @@ pub struct fuse_create_in {
 pub struct fuse_open_out {
     pub fh: u64,
     pub open_flags: u32,
+    #[cfg(not(feature = "abi-7-40"))]
     pub padding: u32,
+    #[cfg(feature = "abi-7-40")]
+    pub backing_id: i32,
 }
 
 #[repr(C)]
@@ -652,6 +950,10 @@ pub struct fuse_fsync_in {
 pub struct fuse_setxattr_in {
     pub size: u32,
     pub flags: u32,
+    #[cfg(all(feature = "abi-7-33", not(target_os = "macos")))]
+    pub setxattr_flags: u32,                            // only sent with FUSE_SETXATTR_EXT
+    #[cfg(all(feature = "abi-7-33", not(target_os = "macos")))]
+    pub padding: u32,
     #[cfg(target_os = "macos")]
     pub position: u32,
     #[cfg(target_os = "macos")]
@@ -708,6 +1010,10 @@ pub struct fuse_init_in {
     pub minor: u32,
     pub max_readahead: u32,
     pub flags: u32,
+    #[cfg(feature = "abi-7-36")]
+    pub flags2: u32,                                    // init flags 32..63, only valid with FUSE_INIT_EXT
+    #[cfg(feature = "abi-7-36")]
+    pub unused: [u32; 11],
 }
 
 #[repr(C)]
@@ -724,6 +1030,30 @@ pub struct fuse_init_out {
     #[cfg(feature = "abi-7-13")]
     pub congestion_threshold: u16,
     pub max_write: u32,
+    #[cfg(feature = "abi-7-23")]
+    pub time_gran: u32,
+    #[cfg(all(feature = "abi-7-23", not(feature = "abi-7-28")))]
+    pub unused: [u32; 9],
+    #[cfg(feature = "abi-7-28")]
+    pub max_pages: u16,
+    #[cfg(all(feature = "abi-7-28", not(feature = "abi-7-31")))]
+    pub padding: u16,
+    #[cfg(feature = "abi-7-31")]
+    pub map_alignment: u16,
+    #[cfg(all(feature = "abi-7-28", not(feature = "abi-7-36")))]
+    pub unused: [u32; 8],
+    #[cfg(feature = "abi-7-36")]
+    pub flags2: u32,                                    // init flags 32..63, only valid with FUSE_INIT_EXT
+    #[cfg(all(feature = "abi-7-36", not(feature = "abi-7-40")))]
+    pub unused: [u32; 7],
+    #[cfg(feature = "abi-7-40")]
+    pub max_stack_depth: u32,
+    #[cfg(all(feature = "abi-7-40", not(feature = "abi-7-43")))]
+    pub unused: [u32; 6],
+    #[cfg(feature = "abi-7-43")]
+    pub request_timeout: u16,
+    #[cfg(feature = "abi-7-43")]
+    pub unused: [u16; 11],
 }
 
 #[cfg(feature = "abi-7-12")]
@@ -808,7 +1138,10 @@ pub struct fuse_poll_in {
     pub fh: u64,
     pub kh: u64,
     pub flags: u32,
+    #[cfg(not(feature = "abi-7-21"))]
     pub padding: u32,
+    #[cfg(feature = "abi-7-21")]
+    pub events: u32,
 }
 
 #[cfg(feature = "abi-7-11")]
@@ -830,11 +1163,148 @@ pub struct fuse_notify_poll_wakeup_out {
 #[repr(C)]
 #[derive(Debug)]
 pub struct fuse_fallocate_in {
-    fh: u64,
-    offset: u64,
-    length: u64,
-    mode: u32,
-    padding: u32,
+    pub fh: u64,
+    pub offset: u64,
+    pub length: u64,
+    pub mode: u32,
+    pub padding: u32,
+}
+
+#[cfg(feature = "abi-7-24")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_lseek_in {
+    pub fh: u64,
+    pub offset: u64,
+    pub whence: u32,
+    pub padding: u32,
+}
+
+#[cfg(feature = "abi-7-24")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_lseek_out {
+    pub offset: u64,
+}
+
+#[cfg(feature = "abi-7-28")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_copy_file_range_in {
+    pub fh_in: u64,
+    pub off_in: u64,
+    pub nodeid_out: u64,
+    pub fh_out: u64,
+    pub off_out: u64,
+    pub len: u64,
+    pub flags: u64,
+}
+
+#[cfg(feature = "abi-7-45")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_copy_file_range_out {
+    pub bytes_copied: u64,
+}
+
+#[cfg(feature = "abi-7-31")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_setupmapping_in {
+    pub fh: u64,                                        // an already open handle
+    pub foffset: u64,                                   // offset into the file to start the mapping
+    pub len: u64,                                       // length of mapping required
+    pub flags: u64,                                     // FUSE_SETUPMAPPING_FLAG_* flags
+    pub moffset: u64,                                   // offset in memory window
+}
+
+#[cfg(feature = "abi-7-31")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_removemapping_in {
+    pub count: u32,                                     // number of fuse_removemapping_one following
+}
+
+#[cfg(feature = "abi-7-31")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_removemapping_one {
+    pub moffset: u64,                                   // offset into the dax window start the unmapping
+    pub len: u64,                                       // length of mapping required
+}
+
+#[cfg(feature = "abi-7-34")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_syncfs_in {
+    pub padding: u64,
+}
+
+#[cfg(feature = "abi-7-39")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_sx_time {
+    pub tv_sec: i64,
+    pub tv_nsec: u32,
+    pub reserved: i32,
+}
+
+#[cfg(feature = "abi-7-39")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_statx {
+    pub mask: u32,
+    pub blksize: u32,
+    pub attributes: u64,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u16,
+    pub spare0: [u16; 1],
+    pub ino: u64,
+    pub size: u64,
+    pub blocks: u64,
+    pub attributes_mask: u64,
+    pub atime: fuse_sx_time,
+    pub btime: fuse_sx_time,
+    pub ctime: fuse_sx_time,
+    pub mtime: fuse_sx_time,
+    pub rdev_major: u32,
+    pub rdev_minor: u32,
+    pub dev_major: u32,
+    pub dev_minor: u32,
+    pub spare2: [u64; 14],
+}
+
+#[cfg(feature = "abi-7-39")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_statx_in {
+    pub getattr_flags: u32,
+    pub reserved: u32,
+    pub fh: u64,
+    pub sx_flags: u32,
+    pub sx_mask: u32,
+}
+
+#[cfg(feature = "abi-7-39")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_statx_out {
+    pub attr_valid: u64,                                // cache timeout for the attributes
+    pub attr_valid_nsec: u32,
+    pub flags: u32,
+    pub spare: [u64; 2],
+    pub stat: fuse_statx,
+}
+
+#[cfg(all(feature = "abi-7-40", target_os = "linux"))]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_backing_map {
+    pub fd: i32,
+    pub flags: u32,
+    pub padding: u64,
 }
 
 #[repr(C)]
@@ -847,7 +1317,12 @@ pub struct fuse_in_header {
     pub uid: u32,
     pub gid: u32,
     pub pid: i32,
+    #[cfg(not(feature = "abi-7-38"))]
     pub padding: u32,
+    #[cfg(feature = "abi-7-38")]
+    pub total_extlen: u16,                              // length of extensions in 8byte units
+    #[cfg(feature = "abi-7-38")]
+    pub padding: u16,
 }
 
 #[repr(C)]
@@ -868,6 +1343,14 @@ pub struct fuse_dirent {
     // followed by name of namelen bytes
 }
 
+#[cfg(feature = "abi-7-21")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_direntplus {
+    pub entry_out: fuse_entry_out,
+    pub dirent: fuse_dirent,
+}
+
 #[cfg(feature = "abi-7-12")]
 #[repr(C)]
 #[derive(Debug)]
@@ -883,17 +1366,20 @@ pub struct fuse_notify_inval_inode_out {
 pub struct fuse_notify_inval_entry_out {
     pub parent: u64,
     pub namelen: u32,
+    #[cfg(not(feature = "abi-7-38"))]
     pub padding: u32,
+    #[cfg(feature = "abi-7-38")]
+    pub flags: u32,                                     // FUSE_EXPIRE_ONLY
 }
 
 #[cfg(feature = "abi-7-18")]
 #[repr(C)]
 #[derive(Debug)]
 pub struct fuse_notify_delete_out {
-    parent: u64,
-    child: u64,
-    namelen: u32,
-    padding: u32,
+    pub parent: u64,
+    pub child: u64,
+    pub namelen: u32,
+    pub padding: u32,
 }
 
 #[cfg(feature = "abi-7-15")]
@@ -928,3 +1414,262 @@ pub struct fuse_notify_retrieve_in {                    // matches the size of f
     pub dummy3: u64,
     pub dummy4: u64,
 }
+
+#[cfg(feature = "abi-7-45")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_notify_prune_out {
+    pub count: u32,                                     // number of node ids following
+    pub padding: u32,
+    pub spare: u64,
+}
+
+#[cfg(feature = "abi-7-36")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_secctx {
+    pub size: u32,
+    pub padding: u32,
+    // followed by the security context name and the context itself
+}
+
+#[cfg(feature = "abi-7-36")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_secctx_header {
+    pub size: u32,
+    pub nr_secctx: u32,
+    // followed by nr_secctx entries of fuse_secctx
+}
+
+/// Header of a request extension, appended after the request arguments. Extensions are 8 byte
+/// aligned and their total length is given by fuse_in_header.total_extlen (in 8 byte units).
+#[cfg(feature = "abi-7-38")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_ext_header {
+    pub size: u32,
+    pub typ: u32,                                       // FUSE_EXT_* type
+}
+
+#[cfg(feature = "abi-7-38")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_supp_groups {
+    pub nr_groups: u32,
+    // followed by nr_groups gids (u32 each)
+}
+
+/// Compile-time checks of struct sizes and field offsets against the layout of the kernel
+/// header (`include/uapi/linux/fuse.h`), so a mismatch breaks the build instead of corrupting
+/// requests at runtime.
+#[allow(dead_code)]
+mod layout {
+    use super::*;
+    use std::mem;
+
+    macro_rules! assert_size {
+        ($t:ty, $size:expr) => {
+            const _: () = assert!(mem::size_of::<$t>() == $size, concat!("unexpected size of ", stringify!($t)));
+        };
+    }
+
+    macro_rules! assert_offset {
+        ($t:ident, $field:ident, $offset:expr) => {
+            const _: () = assert!(mem::offset_of!($t, $field) == $offset,
+                concat!("unexpected offset of ", stringify!($t), ".", stringify!($field)));
+        };
+    }
+
+    // Structures that differ on macOS
+    #[cfg(all(not(feature = "abi-7-9"), not(target_os = "macos")))]
+    assert_size!(fuse_attr, 80);
+    #[cfg(all(feature = "abi-7-9", not(target_os = "macos")))]
+    assert_size!(fuse_attr, 88);
+    #[cfg(all(feature = "abi-7-32", not(target_os = "macos")))]
+    assert_offset!(fuse_attr, flags, 84);
+    #[cfg(all(not(feature = "abi-7-9"), target_os = "macos"))]
+    assert_size!(fuse_attr, 96);
+    #[cfg(all(feature = "abi-7-9", target_os = "macos"))]
+    assert_size!(fuse_attr, 104);
+    assert_offset!(fuse_entry_out, attr, 40);
+    assert_offset!(fuse_attr_out, attr, 16);
+    #[cfg(not(feature = "abi-7-9"))]
+    assert_size!(fuse_entry_out, consts::FUSE_COMPAT_ENTRY_OUT_SIZE);
+    #[cfg(not(feature = "abi-7-9"))]
+    assert_size!(fuse_attr_out, consts::FUSE_COMPAT_ATTR_OUT_SIZE);
+    #[cfg(feature = "abi-7-9")]
+    assert_size!(fuse_entry_out, consts::FUSE_COMPAT_ENTRY_OUT_SIZE + 8);
+    #[cfg(feature = "abi-7-9")]
+    assert_size!(fuse_attr_out, consts::FUSE_COMPAT_ATTR_OUT_SIZE + 8);
+    #[cfg(not(target_os = "macos"))]
+    assert_size!(fuse_setattr_in, 88);
+    #[cfg(target_os = "macos")]
+    assert_size!(fuse_setattr_in, 128);
+    assert_offset!(fuse_setattr_in, mode, 68);
+    #[cfg(feature = "abi-7-23")]
+    assert_offset!(fuse_setattr_in, ctime, 48);
+    #[cfg(feature = "abi-7-23")]
+    assert_offset!(fuse_setattr_in, ctimensec, 64);
+    #[cfg(all(not(feature = "abi-7-33"), not(target_os = "macos")))]
+    assert_size!(fuse_setxattr_in, consts::FUSE_COMPAT_SETXATTR_IN_SIZE);
+    #[cfg(any(feature = "abi-7-33", target_os = "macos"))]
+    assert_size!(fuse_setxattr_in, 16);
+    #[cfg(not(target_os = "macos"))]
+    assert_size!(fuse_getxattr_in, 8);
+    #[cfg(target_os = "macos")]
+    assert_size!(fuse_getxattr_in, 16);
+
+    // Structures with a version dependent size
+    #[cfg(not(feature = "abi-7-12"))]
+    assert_size!(fuse_mknod_in, consts::FUSE_COMPAT_MKNOD_IN_SIZE);
+    #[cfg(feature = "abi-7-12")]
+    assert_size!(fuse_mknod_in, 16);
+    #[cfg(not(feature = "abi-7-12"))]
+    assert_size!(fuse_create_in, consts::FUSE_COMPAT_CREATE_IN_SIZE);
+    #[cfg(feature = "abi-7-12")]
+    assert_size!(fuse_create_in, 16);
+    #[cfg(not(feature = "abi-7-9"))]
+    assert_size!(fuse_read_in, consts::FUSE_COMPAT_READ_IN_SIZE);
+    #[cfg(feature = "abi-7-9")]
+    assert_size!(fuse_read_in, 40);
+    #[cfg(not(feature = "abi-7-9"))]
+    assert_size!(fuse_write_in, consts::FUSE_COMPAT_WRITE_IN_SIZE);
+    #[cfg(feature = "abi-7-9")]
+    assert_size!(fuse_write_in, 40);
+    #[cfg(not(feature = "abi-7-9"))]
+    assert_size!(fuse_lk_in, consts::FUSE_COMPAT_LK_IN_SIZE);
+    #[cfg(feature = "abi-7-9")]
+    assert_size!(fuse_lk_in, 48);
+    #[cfg(not(feature = "abi-7-36"))]
+    assert_size!(fuse_init_in, consts::FUSE_COMPAT_INIT_IN_SIZE);
+    #[cfg(feature = "abi-7-36")]
+    assert_size!(fuse_init_in, 64);
+    #[cfg(feature = "abi-7-36")]
+    assert_offset!(fuse_init_in, flags2, 16);
+    #[cfg(not(feature = "abi-7-23"))]
+    assert_size!(fuse_init_out, consts::FUSE_COMPAT_22_INIT_OUT_SIZE);
+    #[cfg(feature = "abi-7-23")]
+    assert_size!(fuse_init_out, 64);
+    assert_offset!(fuse_init_out, max_write, 20);
+    #[cfg(feature = "abi-7-23")]
+    assert_offset!(fuse_init_out, time_gran, 24);
+    #[cfg(feature = "abi-7-28")]
+    assert_offset!(fuse_init_out, max_pages, 28);
+    #[cfg(feature = "abi-7-31")]
+    assert_offset!(fuse_init_out, map_alignment, 30);
+    #[cfg(feature = "abi-7-36")]
+    assert_offset!(fuse_init_out, flags2, 32);
+    #[cfg(feature = "abi-7-40")]
+    assert_offset!(fuse_init_out, max_stack_depth, 36);
+    #[cfg(feature = "abi-7-43")]
+    assert_offset!(fuse_init_out, request_timeout, 40);
+    #[cfg(feature = "abi-7-38")]
+    assert_offset!(fuse_in_header, total_extlen, 36);
+
+    // Structures with a fixed size
+    assert_size!(fuse_kstatfs, 80);
+    assert_size!(fuse_file_lock, 24);
+    assert_size!(fuse_forget_in, 8);
+    assert_size!(fuse_mkdir_in, 8);
+    assert_size!(fuse_rename_in, 8);
+    assert_size!(fuse_link_in, 8);
+    assert_size!(fuse_open_in, 8);
+    assert_size!(fuse_open_out, 16);
+    assert_size!(fuse_release_in, 24);
+    assert_size!(fuse_flush_in, 24);
+    assert_size!(fuse_write_out, 8);
+    assert_size!(fuse_statfs_out, 80);
+    assert_size!(fuse_fsync_in, 16);
+    assert_size!(fuse_getxattr_out, 8);
+    assert_size!(fuse_lk_out, 24);
+    assert_size!(fuse_access_in, 8);
+    assert_size!(fuse_interrupt_in, 8);
+    assert_size!(fuse_bmap_in, 16);
+    assert_size!(fuse_bmap_out, 8);
+    assert_size!(fuse_in_header, 40);
+    assert_size!(fuse_out_header, 16);
+    assert_size!(fuse_dirent, 24);
+    #[cfg(feature = "abi-7-9")]
+    assert_size!(fuse_getattr_in, 16);
+    #[cfg(feature = "abi-7-11")]
+    assert_size!(fuse_ioctl_in, 32);
+    #[cfg(feature = "abi-7-11")]
+    assert_size!(fuse_ioctl_out, 16);
+    #[cfg(feature = "abi-7-11")]
+    assert_size!(fuse_poll_in, 24);
+    #[cfg(feature = "abi-7-11")]
+    assert_size!(fuse_poll_out, 8);
+    #[cfg(feature = "abi-7-11")]
+    assert_size!(fuse_notify_poll_wakeup_out, 8);
+    #[cfg(feature = "abi-7-12")]
+    assert_size!(cuse_init_in, 16);
+    #[cfg(feature = "abi-7-12")]
+    assert_size!(cuse_init_out, 72);
+    #[cfg(feature = "abi-7-12")]
+    assert_size!(fuse_notify_inval_inode_out, 24);
+    #[cfg(feature = "abi-7-12")]
+    assert_size!(fuse_notify_inval_entry_out, 16);
+    #[cfg(feature = "abi-7-15")]
+    assert_size!(fuse_notify_store_out, 24);
+    #[cfg(feature = "abi-7-15")]
+    assert_size!(fuse_notify_retrieve_out, 32);
+    #[cfg(feature = "abi-7-15")]
+    assert_size!(fuse_notify_retrieve_in, 40);
+    #[cfg(feature = "abi-7-16")]
+    assert_size!(fuse_forget_one, 16);
+    #[cfg(feature = "abi-7-16")]
+    assert_size!(fuse_batch_forget_in, 8);
+    #[cfg(feature = "abi-7-16")]
+    assert_size!(fuse_ioctl_iovec, 16);
+    #[cfg(feature = "abi-7-18")]
+    assert_size!(fuse_notify_delete_out, 24);
+    #[cfg(feature = "abi-7-19")]
+    assert_size!(fuse_fallocate_in, 32);
+    #[cfg(feature = "abi-7-21")]
+    assert_offset!(fuse_direntplus, dirent, mem::size_of::<fuse_entry_out>());
+    #[cfg(feature = "abi-7-23")]
+    assert_size!(fuse_rename2_in, 16);
+    #[cfg(feature = "abi-7-24")]
+    assert_size!(fuse_lseek_in, 24);
+    #[cfg(feature = "abi-7-24")]
+    assert_size!(fuse_lseek_out, 8);
+    #[cfg(feature = "abi-7-28")]
+    assert_size!(fuse_copy_file_range_in, 56);
+    #[cfg(feature = "abi-7-31")]
+    assert_size!(fuse_setupmapping_in, 40);
+    #[cfg(feature = "abi-7-31")]
+    assert_size!(fuse_removemapping_in, 4);
+    #[cfg(feature = "abi-7-31")]
+    assert_size!(fuse_removemapping_one, 16);
+    #[cfg(feature = "abi-7-34")]
+    assert_size!(fuse_syncfs_in, 8);
+    #[cfg(feature = "abi-7-36")]
+    assert_size!(fuse_secctx, 8);
+    #[cfg(feature = "abi-7-36")]
+    assert_size!(fuse_secctx_header, 8);
+    #[cfg(feature = "abi-7-38")]
+    assert_size!(fuse_ext_header, 8);
+    #[cfg(feature = "abi-7-38")]
+    assert_size!(fuse_supp_groups, 4);
+    #[cfg(feature = "abi-7-39")]
+    assert_size!(fuse_sx_time, 16);
+    #[cfg(feature = "abi-7-39")]
+    assert_size!(fuse_statx, 256);
+    #[cfg(feature = "abi-7-39")]
+    assert_offset!(fuse_statx, atime, 64);
+    #[cfg(feature = "abi-7-39")]
+    assert_offset!(fuse_statx, rdev_major, 128);
+    #[cfg(feature = "abi-7-39")]
+    assert_size!(fuse_statx_in, 24);
+    #[cfg(feature = "abi-7-39")]
+    assert_size!(fuse_statx_out, 288);
+    #[cfg(feature = "abi-7-39")]
+    assert_offset!(fuse_statx_out, stat, 32);
+    #[cfg(all(feature = "abi-7-40", target_os = "linux"))]
+    assert_size!(fuse_backing_map, 16);
+    #[cfg(feature = "abi-7-45")]
+    assert_size!(fuse_copy_file_range_out, 8);
+    #[cfg(feature = "abi-7-45")]
+    assert_size!(fuse_notify_prune_out, 16);
+}
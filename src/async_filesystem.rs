@@ -0,0 +1,737 @@
+//! Asynchronous filesystems
+//!
+//! An asynchronous filesystem implements its operations as futures that return typed results
+//! instead of replying via callbacks. An `AsyncSession` runs each request as a tokio task, so
+//! that many requests can be outstanding at once while the session keeps receiving requests.
+
+use libc::{c_int, ENOSYS, ERANGE};
+use std::ffi::{OsStr, OsString};
+use std::future::Future;
+use std::path::Path;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::{FallocateFlags, FileAttr, FileType, Filesystem, InterruptToken, KernelConfig};
+use crate::{RenameFlags, Request};
+use crate::{ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry};
+use crate::{ReplyLseek, ReplyOpen, ReplyStatfs, ReplyWrite, ReplyXattr};
+
+/// Request information that can be kept while an asynchronous operation runs
+#[derive(Clone, Debug)]
+pub struct RequestInfo {
+    unique: u64,
+    uid: u32,
+    gid: u32,
+    pid: i32,
+    token: InterruptToken,
+}
+
+impl RequestInfo {
+    fn new(req: &Request<'_>) -> RequestInfo {
+        RequestInfo {
+            unique: req.unique(),
+            uid: req.uid(),
+            gid: req.gid(),
+            pid: req.pid(),
+            token: req.interrupt_token(),
+        }
+    }
+
+    /// Returns the unique identifier of this request
+    pub fn unique(&self) -> u64 {
+        self.unique
+    }
+
+    /// Returns the uid of this request
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Returns the gid of this request
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Returns the pid of this request
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Returns true if the kernel asked to interrupt this request
+    pub fn is_interrupted(&self) -> bool {
+        self.token.is_interrupted()
+    }
+
+    /// Returns a token to check for the interruption of this request
+    pub fn interrupt_token(&self) -> InterruptToken {
+        self.token.clone()
+    }
+}
+
+/// Result of operations that look up or create a directory entry
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    /// Time the kernel may cache the entry and its attributes
+    pub ttl: Duration,
+    /// Attributes of the entry's inode
+    pub attr: FileAttr,
+    /// Generation number of the inode (see `ReplyEntry::entry`)
+    pub generation: u64,
+}
+
+/// Result of operations that return file attributes
+#[derive(Clone, Copy, Debug)]
+pub struct Attr {
+    /// Time the kernel may cache the attributes
+    pub ttl: Duration,
+    /// File attributes
+    pub attr: FileAttr,
+}
+
+/// Result of operations that open a file or directory
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Opened {
+    /// File handle, passed to other operations on the opened file
+    pub fh: u64,
+    /// Open flags (`FOPEN_*`)
+    pub flags: u32,
+}
+
+/// Attributes to change in a setattr operation. Attributes that are `None` stay unchanged.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SetAttr {
+    /// Permissions
+    pub mode: Option<u32>,
+    /// User id
+    pub uid: Option<u32>,
+    /// Group id
+    pub gid: Option<u32>,
+    /// Size in bytes (truncate)
+    pub size: Option<u64>,
+    /// Time of last access
+    pub atime: Option<SystemTime>,
+    /// Time of last modification
+    pub mtime: Option<SystemTime>,
+    /// File handle if the attributes are changed through an open file
+    pub fh: Option<u64>,
+    /// macOS only
+    pub crtime: Option<SystemTime>,
+    /// macOS only
+    pub chgtime: Option<SystemTime>,
+    /// macOS only
+    pub bkuptime: Option<SystemTime>,
+    /// macOS only
+    pub flags: Option<u32>,
+}
+
+/// A directory entry returned by readdir
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    /// Inode number
+    pub ino: u64,
+    /// Offset of the next entry, passed to the readdir that continues after this entry
+    pub offset: i64,
+    /// Kind of file
+    pub kind: FileType,
+    /// File name
+    pub name: OsString,
+}
+
+/// Result of the statfs operation
+#[derive(Clone, Copy, Debug)]
+pub struct Statfs {
+    /// Total data blocks (in units of frsize)
+    pub blocks: u64,
+    /// Free blocks
+    pub bfree: u64,
+    /// Free blocks available to unprivileged users
+    pub bavail: u64,
+    /// Total inodes
+    pub files: u64,
+    /// Free inodes
+    pub ffree: u64,
+    /// Filesystem block size
+    pub bsize: u32,
+    /// Maximum filename length
+    pub namelen: u32,
+    /// Fragment size
+    pub frsize: u32,
+}
+
+impl Default for Statfs {
+    fn default() -> Statfs {
+        Statfs { blocks: 0, bfree: 0, bavail: 0, files: 0, ffree: 0, bsize: 512, namelen: 255, frsize: 0 }
+    }
+}
+
+/// Asynchronous filesystem trait.
+///
+/// The methods correspond to the methods of `Filesystem`, but return futures with the result
+/// of the operation instead of replying via a reply object. Each request is run as a tokio
+/// task of an `AsyncSession`, so methods take `&self` and the returned futures must be `Send`.
+/// Implementations can use `async fn` for the methods. Errors are returned as errno values.
+/// Operations without a method here aren't supported and are replied with `ENOSYS`.
+pub trait AsyncFilesystem: Send + Sync + 'static {
+    /// Initialize filesystem.
+    /// Called before any other filesystem method, see `Filesystem::init`. Other than the
+    /// other methods, it runs synchronously since no request is handled before it returns.
+    fn init(&self, _req: &Request<'_>, _config: &mut KernelConfig) -> Result<(), c_int> {
+        Ok(())
+    }
+
+    /// Clean up filesystem.
+    /// Called on filesystem exit, synchronously like `init`.
+    fn destroy(&self, _req: &Request<'_>) {}
+
+    /// Look up a directory entry by name and get its attributes.
+    fn lookup(&self, _req: &RequestInfo, _parent: u64, _name: &OsStr) -> impl Future<Output = Result<Entry, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Forget about an inode.
+    /// See `Filesystem::forget`. Unlike other operations, forgets don't run as separate
+    /// tasks. The future is run to completion when the request is received, so that forgets
+    /// apply in the order they arrive. It must not wait for other tasks of the runtime.
+    fn forget(&self, _req: &RequestInfo, _ino: u64, _nlookup: u64) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Get file attributes.
+    fn getattr(&self, _req: &RequestInfo, _ino: u64) -> impl Future<Output = Result<Attr, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Set file attributes.
+    fn setattr(&self, _req: &RequestInfo, _ino: u64, _attr: SetAttr) -> impl Future<Output = Result<Attr, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Read symbolic link.
+    fn readlink(&self, _req: &RequestInfo, _ino: u64) -> impl Future<Output = Result<Vec<u8>, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Create file node.
+    fn mknod(&self, _req: &RequestInfo, _parent: u64, _name: &OsStr, _mode: u32, _rdev: u32) -> impl Future<Output = Result<Entry, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Create a directory.
+    fn mkdir(&self, _req: &RequestInfo, _parent: u64, _name: &OsStr, _mode: u32) -> impl Future<Output = Result<Entry, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Remove a file.
+    fn unlink(&self, _req: &RequestInfo, _parent: u64, _name: &OsStr) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Remove a directory.
+    fn rmdir(&self, _req: &RequestInfo, _parent: u64, _name: &OsStr) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Create a symbolic link.
+    fn symlink(&self, _req: &RequestInfo, _parent: u64, _name: &OsStr, _link: &Path) -> impl Future<Output = Result<Entry, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Rename a file.
+    fn rename(&self, _req: &RequestInfo, _parent: u64, _name: &OsStr, _newparent: u64, _newname: &OsStr, _flags: RenameFlags) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Create a hard link.
+    fn link(&self, _req: &RequestInfo, _ino: u64, _newparent: u64, _newname: &OsStr) -> impl Future<Output = Result<Entry, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Open a file.
+    /// See `Filesystem::open`.
+    fn open(&self, _req: &RequestInfo, _ino: u64, _flags: u32) -> impl Future<Output = Result<Opened, c_int>> + Send {
+        async { Ok(Opened::default()) }
+    }
+
+    /// Read data.
+    /// Should return exactly the number of bytes requested except on EOF or error, see
+    /// `Filesystem::read`.
+    fn read(&self, _req: &RequestInfo, _ino: u64, _fh: u64, _offset: i64, _size: u32) -> impl Future<Output = Result<Vec<u8>, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Write data.
+    /// Returns the number of bytes written, see `Filesystem::write`.
+    fn write(&self, _req: &RequestInfo, _ino: u64, _fh: u64, _offset: i64, _data: &[u8], _flags: u32) -> impl Future<Output = Result<u32, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Flush method.
+    /// See `Filesystem::flush`.
+    fn flush(&self, _req: &RequestInfo, _ino: u64, _fh: u64, _lock_owner: u64) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Release an open file.
+    /// See `Filesystem::release`.
+    fn release(&self, _req: &RequestInfo, _ino: u64, _fh: u64, _flags: u32, _lock_owner: u64, _flush: bool) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Synchronize file contents.
+    fn fsync(&self, _req: &RequestInfo, _ino: u64, _fh: u64, _datasync: bool) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Open a directory.
+    fn opendir(&self, _req: &RequestInfo, _ino: u64, _flags: u32) -> impl Future<Output = Result<Opened, c_int>> + Send {
+        async { Ok(Opened::default()) }
+    }
+
+    /// Read directory.
+    /// Returns the entries following the given offset. Entries that don't fit into the
+    /// reply buffer are dropped, the kernel continues at the last returned offset.
+    fn readdir(&self, _req: &RequestInfo, _ino: u64, _fh: u64, _offset: i64) -> impl Future<Output = Result<Vec<DirEntry>, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Release an open directory.
+    fn releasedir(&self, _req: &RequestInfo, _ino: u64, _fh: u64, _flags: u32) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Synchronize directory contents.
+    fn fsyncdir(&self, _req: &RequestInfo, _ino: u64, _fh: u64, _datasync: bool) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Get file system statistics.
+    fn statfs(&self, _req: &RequestInfo, _ino: u64) -> impl Future<Output = Result<Statfs, c_int>> + Send {
+        async { Ok(Statfs::default()) }
+    }
+
+    /// Set an extended attribute.
+    fn setxattr(&self, _req: &RequestInfo, _ino: u64, _name: &OsStr, _value: &[u8], _flags: u32, _position: u32) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Get an extended attribute.
+    /// Returns the whole value. The session replies with its size or `ERANGE` if the
+    /// kernel asked for the size only or provided a buffer that is too small.
+    fn getxattr(&self, _req: &RequestInfo, _ino: u64, _name: &OsStr) -> impl Future<Output = Result<Vec<u8>, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// List extended attribute names.
+    /// Returns the NUL-terminated names, which are replied like `getxattr` values.
+    fn listxattr(&self, _req: &RequestInfo, _ino: u64) -> impl Future<Output = Result<Vec<u8>, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Remove an extended attribute.
+    fn removexattr(&self, _req: &RequestInfo, _ino: u64, _name: &OsStr) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Check file access permissions.
+    fn access(&self, _req: &RequestInfo, _ino: u64, _mask: u32) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Create and open a file.
+    /// See `Filesystem::create`.
+    fn create(&self, _req: &RequestInfo, _parent: u64, _name: &OsStr, _mode: u32, _flags: u32) -> impl Future<Output = Result<(Entry, Opened), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Preallocate or deallocate space to a file.
+    fn fallocate(&self, _req: &RequestInfo, _ino: u64, _fh: u64, _offset: i64, _length: i64, _mode: FallocateFlags) -> impl Future<Output = Result<(), c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+
+    /// Reposition read/write file offset.
+    /// Returns the resulting offset, see `Filesystem::lseek`.
+    fn lseek(&self, _req: &RequestInfo, _ino: u64, _fh: u64, _offset: i64, _whence: i32) -> impl Future<Output = Result<i64, c_int>> + Send {
+        async { Err(ENOSYS) }
+    }
+}
+
+/// Reply with the given value or its size, like the kernel expects for getxattr and listxattr
+fn reply_xattr(reply: ReplyXattr, size: u32, result: Result<Vec<u8>, c_int>) {
+    match result {
+        Ok(ref value) if size == 0 => reply.size(value.len() as u32),
+        Ok(ref value) if value.len() > size as usize => reply.error(ERANGE),
+        Ok(value) => reply.data(&value),
+        Err(err) => reply.error(err),
+    }
+}
+
+/// Reply with an empty result
+fn reply_empty(reply: ReplyEmpty, result: Result<(), c_int>) {
+    match result {
+        Ok(()) => reply.ok(),
+        Err(err) => reply.error(err),
+    }
+}
+
+/// Reply with an entry result
+fn reply_entry(reply: ReplyEntry, result: Result<Entry, c_int>) {
+    match result {
+        Ok(entry) => reply.entry(&entry.ttl, &entry.attr, entry.generation),
+        Err(err) => reply.error(err),
+    }
+}
+
+/// Reply with an attribute result
+fn reply_attr(reply: ReplyAttr, result: Result<Attr, c_int>) {
+    match result {
+        Ok(attr) => reply.attr(&attr.ttl, &attr.attr),
+        Err(err) => reply.error(err),
+    }
+}
+
+/// Reply with an open result
+fn reply_open(reply: ReplyOpen, result: Result<Opened, c_int>) {
+    match result {
+        Ok(opened) => reply.opened(opened.fh, opened.flags),
+        Err(err) => reply.error(err),
+    }
+}
+
+/// Reply with a data result
+fn reply_data(reply: ReplyData, result: Result<Vec<u8>, c_int>) {
+    match result {
+        Ok(data) => reply.data(&data),
+        Err(err) => reply.error(err),
+    }
+}
+
+/// Run a future to completion on the current thread
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Filesystem that runs the operations of an asynchronous filesystem as tokio tasks, which
+/// reply once the operation completes. Its methods must be called within a tokio runtime.
+#[derive(Debug)]
+pub(crate) struct AsyncDispatcher<FS> {
+    fs: Arc<FS>,
+}
+
+impl<FS: AsyncFilesystem> AsyncDispatcher<FS> {
+    pub(crate) fn new(fs: FS) -> AsyncDispatcher<FS> {
+        AsyncDispatcher { fs: Arc::new(fs) }
+    }
+}
+
+impl<FS: AsyncFilesystem> Filesystem for AsyncDispatcher<FS> {
+    fn init(&mut self, req: &Request<'_>, config: &mut KernelConfig) -> Result<(), c_int> {
+        self.fs.init(req, config)
+    }
+
+    fn destroy(&mut self, req: &Request<'_>) {
+        self.fs.destroy(req);
+    }
+
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let (fs, req, name) = (self.fs.clone(), RequestInfo::new(req), name.to_owned());
+        tokio::spawn(async move {
+            reply_entry(reply, fs.lookup(&req, parent, &name).await);
+        });
+    }
+
+    fn forget(&mut self, req: &Request<'_>, ino: u64, nlookup: u64) {
+        // A forget spawned as a task could run before an earlier request of the inode
+        block_on(self.fs.forget(&RequestInfo::new(req), ino, nlookup));
+    }
+
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_attr(reply, fs.getattr(&req, ino).await);
+        });
+    }
+
+    fn setattr(&mut self, req: &Request<'_>, ino: u64, mode: Option<u32>, uid: Option<u32>, gid: Option<u32>, size: Option<u64>, atime: Option<SystemTime>, mtime: Option<SystemTime>, fh: Option<u64>, crtime: Option<SystemTime>, chgtime: Option<SystemTime>, bkuptime: Option<SystemTime>, flags: Option<u32>, reply: ReplyAttr) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        let attr = SetAttr { mode, uid, gid, size, atime, mtime, fh, crtime, chgtime, bkuptime, flags };
+        tokio::spawn(async move {
+            reply_attr(reply, fs.setattr(&req, ino, attr).await);
+        });
+    }
+
+    fn readlink(&mut self, req: &Request<'_>, ino: u64, reply: ReplyData) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_data(reply, fs.readlink(&req, ino).await);
+        });
+    }
+
+    fn mknod(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, rdev: u32, reply: ReplyEntry) {
+        let (fs, req, name) = (self.fs.clone(), RequestInfo::new(req), name.to_owned());
+        tokio::spawn(async move {
+            reply_entry(reply, fs.mknod(&req, parent, &name, mode, rdev).await);
+        });
+    }
+
+    fn mkdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, reply: ReplyEntry) {
+        let (fs, req, name) = (self.fs.clone(), RequestInfo::new(req), name.to_owned());
+        tokio::spawn(async move {
+            reply_entry(reply, fs.mkdir(&req, parent, &name, mode).await);
+        });
+    }
+
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let (fs, req, name) = (self.fs.clone(), RequestInfo::new(req), name.to_owned());
+        tokio::spawn(async move {
+            reply_empty(reply, fs.unlink(&req, parent, &name).await);
+        });
+    }
+
+    fn rmdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let (fs, req, name) = (self.fs.clone(), RequestInfo::new(req), name.to_owned());
+        tokio::spawn(async move {
+            reply_empty(reply, fs.rmdir(&req, parent, &name).await);
+        });
+    }
+
+    fn symlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, link: &Path, reply: ReplyEntry) {
+        let (fs, req, name, link) = (self.fs.clone(), RequestInfo::new(req), name.to_owned(), link.to_owned());
+        tokio::spawn(async move {
+            reply_entry(reply, fs.symlink(&req, parent, &name, &link).await);
+        });
+    }
+
+    fn rename(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr, flags: RenameFlags, reply: ReplyEmpty) {
+        let (fs, req, name, newname) = (self.fs.clone(), RequestInfo::new(req), name.to_owned(), newname.to_owned());
+        tokio::spawn(async move {
+            reply_empty(reply, fs.rename(&req, parent, &name, newparent, &newname, flags).await);
+        });
+    }
+
+    fn link(&mut self, req: &Request<'_>, ino: u64, newparent: u64, newname: &OsStr, reply: ReplyEntry) {
+        let (fs, req, newname) = (self.fs.clone(), RequestInfo::new(req), newname.to_owned());
+        tokio::spawn(async move {
+            reply_entry(reply, fs.link(&req, ino, newparent, &newname).await);
+        });
+    }
+
+    fn open(&mut self, req: &Request<'_>, ino: u64, flags: u32, reply: ReplyOpen) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_open(reply, fs.open(&req, ino, flags).await);
+        });
+    }
+
+    fn read(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, size: u32, reply: ReplyData) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_data(reply, fs.read(&req, ino, fh, offset, size).await);
+        });
+    }
+
+    fn write(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, data: &[u8], flags: u32, reply: ReplyWrite) {
+        let (fs, req, data) = (self.fs.clone(), RequestInfo::new(req), data.to_vec());
+        tokio::spawn(async move {
+            match fs.write(&req, ino, fh, offset, &data, flags).await {
+                Ok(size) => reply.written(size),
+                Err(err) => reply.error(err),
+            }
+        });
+    }
+
+    fn flush(&mut self, req: &Request<'_>, ino: u64, fh: u64, lock_owner: u64, reply: ReplyEmpty) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_empty(reply, fs.flush(&req, ino, fh, lock_owner).await);
+        });
+    }
+
+    fn release(&mut self, req: &Request<'_>, ino: u64, fh: u64, flags: u32, lock_owner: u64, flush: bool, reply: ReplyEmpty) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_empty(reply, fs.release(&req, ino, fh, flags, lock_owner, flush).await);
+        });
+    }
+
+    fn fsync(&mut self, req: &Request<'_>, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_empty(reply, fs.fsync(&req, ino, fh, datasync).await);
+        });
+    }
+
+    fn opendir(&mut self, req: &Request<'_>, ino: u64, flags: u32, reply: ReplyOpen) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_open(reply, fs.opendir(&req, ino, flags).await);
+        });
+    }
+
+    fn readdir(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            match fs.readdir(&req, ino, fh, offset).await {
+                Ok(entries) => {
+                    for entry in entries {
+                        if reply.add(entry.ino, entry.offset, entry.kind, &entry.name) {
+                            break;
+                        }
+                    }
+                    reply.ok();
+                }
+                Err(err) => reply.error(err),
+            }
+        });
+    }
+
+    fn releasedir(&mut self, req: &Request<'_>, ino: u64, fh: u64, flags: u32, reply: ReplyEmpty) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_empty(reply, fs.releasedir(&req, ino, fh, flags).await);
+        });
+    }
+
+    fn fsyncdir(&mut self, req: &Request<'_>, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_empty(reply, fs.fsyncdir(&req, ino, fh, datasync).await);
+        });
+    }
+
+    fn statfs(&mut self, req: &Request<'_>, ino: u64, reply: ReplyStatfs) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            match fs.statfs(&req, ino).await {
+                Ok(st) => reply.statfs(st.blocks, st.bfree, st.bavail, st.files, st.ffree, st.bsize, st.namelen, st.frsize),
+                Err(err) => reply.error(err),
+            }
+        });
+    }
+
+    fn setxattr(&mut self, req: &Request<'_>, ino: u64, name: &OsStr, value: &[u8], flags: u32, position: u32, reply: ReplyEmpty) {
+        let (fs, req, name, value) = (self.fs.clone(), RequestInfo::new(req), name.to_owned(), value.to_vec());
+        tokio::spawn(async move {
+            reply_empty(reply, fs.setxattr(&req, ino, &name, &value, flags, position).await);
+        });
+    }
+
+    fn getxattr(&mut self, req: &Request<'_>, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        let (fs, req, name) = (self.fs.clone(), RequestInfo::new(req), name.to_owned());
+        tokio::spawn(async move {
+            reply_xattr(reply, size, fs.getxattr(&req, ino, &name).await);
+        });
+    }
+
+    fn listxattr(&mut self, req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_xattr(reply, size, fs.listxattr(&req, ino).await);
+        });
+    }
+
+    fn removexattr(&mut self, req: &Request<'_>, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        let (fs, req, name) = (self.fs.clone(), RequestInfo::new(req), name.to_owned());
+        tokio::spawn(async move {
+            reply_empty(reply, fs.removexattr(&req, ino, &name).await);
+        });
+    }
+
+    fn access(&mut self, req: &Request<'_>, ino: u64, mask: u32, reply: ReplyEmpty) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_empty(reply, fs.access(&req, ino, mask).await);
+        });
+    }
+
+    fn create(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, flags: u32, reply: ReplyCreate) {
+        let (fs, req, name) = (self.fs.clone(), RequestInfo::new(req), name.to_owned());
+        tokio::spawn(async move {
+            match fs.create(&req, parent, &name, mode, flags).await {
+                Ok((entry, opened)) => reply.created(&entry.ttl, &entry.attr, entry.generation, opened.fh, opened.flags),
+                Err(err) => reply.error(err),
+            }
+        });
+    }
+
+    fn fallocate(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, length: i64, mode: FallocateFlags, reply: ReplyEmpty) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            reply_empty(reply, fs.fallocate(&req, ino, fh, offset, length, mode).await);
+        });
+    }
+
+    fn lseek(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, whence: i32, reply: ReplyLseek) {
+        let (fs, req) = (self.fs.clone(), RequestInfo::new(req));
+        tokio::spawn(async move {
+            match fs.lseek(&req, ino, fh, offset, whence).await {
+                Ok(offset) => reply.offset(offset),
+                Err(err) => reply.error(err),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{block_on, AsyncFilesystem};
+    use crate::AsyncSession;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    struct NullFS;
+
+    impl AsyncFilesystem for NullFS {}
+
+    fn assert_send<T: Send>(_: T) {}
+
+    #[test]
+    fn session_is_send() {
+        // Compile-time check that the session loop can be spawned as a task
+        #[allow(dead_code)]
+        fn check(se: &mut AsyncSession<NullFS>) {
+            assert_send(se.run());
+        }
+    }
+
+    /// Future that is pending on the first poll
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = u32;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            if self.0 {
+                Poll::Ready(42)
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn block_on_pending_future() {
+        assert_eq!(block_on(YieldOnce(false)), 42);
+    }
+}
@@ -0,0 +1,157 @@
+//! Reusable buffers
+//!
+//! Large buffers (e.g. for reading file data to reply with) are taken from a pool and given
+//! back when they're dropped, so that they don't need to be allocated for every request.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use std::fmt;
+
+/// A pool of reusable buffers. Unused buffers are kept up to a total capacity of `max_bytes`.
+pub(crate) struct BufferPool {
+    buffers: Mutex<Buffers>,
+    max_bytes: usize,
+}
+
+/// Unused buffers of a pool and their total capacity
+struct Buffers {
+    buffers: Vec<Vec<u8>>,
+    bytes: usize,
+}
+
+impl BufferPool {
+    /// Create a new empty pool that keeps unused buffers up to the given total capacity
+    pub(crate) const fn new(max_bytes: usize) -> BufferPool {
+        BufferPool { buffers: Mutex::new(Buffers { buffers: Vec::new(), bytes: 0 }), max_bytes }
+    }
+
+    /// Take an empty buffer with at least the given capacity from the pool. The smallest
+    /// unused buffer that fits is taken, smaller ones stay in the pool. A new buffer is
+    /// allocated if no unused buffer is large enough.
+    pub(crate) fn take(&self, capacity: usize) -> PooledBuffer<'_> {
+        let mut pool = self.buffers.lock().unwrap();
+        let fitting = pool
+            .buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.capacity() >= capacity)
+            .min_by_key(|(_, buffer)| buffer.capacity())
+            .map(|(index, _)| index);
+        let buffer = match fitting {
+            Some(index) => {
+                let buffer = pool.buffers.swap_remove(index);
+                pool.bytes -= buffer.capacity();
+                buffer
+            }
+            None => Vec::with_capacity(capacity),
+        };
+        PooledBuffer { buffer, pool: self }
+    }
+
+    /// Give a buffer back to the pool. To make room for it, the smallest unused buffers are
+    /// dropped. The buffer itself is dropped if it's larger than the pool's total capacity.
+    fn put(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() > self.max_bytes {
+            return;
+        }
+        let mut pool = self.buffers.lock().unwrap();
+        while pool.bytes + buffer.capacity() > self.max_bytes {
+            let (index, _) = pool.buffers.iter().enumerate().min_by_key(|(_, buffer)| buffer.capacity()).unwrap();
+            let smallest = pool.buffers.swap_remove(index);
+            pool.bytes -= smallest.capacity();
+        }
+        buffer.clear();
+        pool.bytes += buffer.capacity();
+        pool.buffers.push(buffer);
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool").field("max_bytes", &self.max_bytes).finish()
+    }
+}
+
+/// A buffer that is given back to its pool when dropped
+pub(crate) struct PooledBuffer<'a> {
+    buffer: Vec<u8>,
+    pool: &'a BufferPool,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+}
+
+impl fmt::Debug for PooledBuffer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledBuffer").field("capacity", &self.buffer.capacity()).finish()
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buffer));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BufferPool;
+
+    #[test]
+    fn reuse_buffer() {
+        let pool = BufferPool::new(64);
+        let mut buffer = pool.take(64);
+        assert!(buffer.capacity() >= 64);
+        buffer.extend(b"foo");
+        let ptr = buffer.as_ptr();
+        drop(buffer);
+        let buffer = pool.take(32);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn keep_max_bytes() {
+        let pool = BufferPool::new(16);
+        let first = pool.take(16);
+        let second = pool.take(16);
+        drop(first);
+        drop(second);
+        assert_eq!(pool.buffers.lock().unwrap().buffers.len(), 1);
+        drop(pool.take(17));
+        assert_eq!(pool.buffers.lock().unwrap().bytes, 16);
+    }
+
+    #[test]
+    fn mixed_capacities() {
+        let pool = BufferPool::new(4096);
+        let small = pool.take(64);
+        let large = pool.take(1024);
+        let (small_ptr, large_ptr) = (small.as_ptr(), large.as_ptr());
+        drop(large);
+        drop(small);
+        // A large buffer is found behind the smaller one, which stays in the pool
+        let buffer = pool.take(512);
+        assert_eq!(buffer.as_ptr(), large_ptr);
+        assert_eq!(pool.buffers.lock().unwrap().buffers.len(), 1);
+        let other = pool.take(32);
+        assert_eq!(other.as_ptr(), small_ptr);
+        drop((buffer, other));
+        // Smaller buffers are dropped to make room for a larger one
+        drop(pool.take(4096 - 64));
+        let pool = pool.buffers.lock().unwrap();
+        assert!(pool.bytes <= 4096);
+        assert!(pool.buffers.iter().any(|buffer| buffer.capacity() >= 4096 - 64));
+    }
+}
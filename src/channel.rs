@@ -6,12 +6,26 @@ use std::io;
 use std::ffi::{CString, CStr, OsStr};
 use std::os::unix::ffi::OsStrExt;
 use std::path::{PathBuf, Path};
+use std::ptr;
+use std::sync::{Arc, RwLock};
 use fuse_sys::{fuse_args, fuse_mount_compat25};
 use libc::{self, c_int, c_void, size_t};
+use std::os::unix::io::RawFd;
 use log::error;
+#[cfg(target_os = "linux")]
+use fuse_abi::consts::{FUSE_DEV_IOC_CLONE, FUSE_SPLICE_MOVE, FUSE_SPLICE_WRITE};
 use super::ll::channel;
 
 use crate::reply::ReplySender;
+#[cfg(target_os = "linux")]
+use crate::splice::{self, RequestPipe};
+
+/// Maximum number of slices that can be sent in a single writev call (IOV_MAX on Linux,
+/// macOS and the BSDs)
+const MAX_IOVECS: usize = 1024;
+
+/// Number of slices that can be sent without allocating the iovecs
+const INLINE_IOVECS: usize = 8;
 
 /// Helper function to provide options as a fuse_args struct
 /// (which contains an argc count and an argv pointer)
@@ -25,8 +39,10 @@ fn with_fuse_args<T, F: FnOnce(&fuse_args) -> T>(options: &[&OsStr], f: F) -> T
 /// A raw communication channel to the FUSE kernel driver
 #[derive(Debug)]
 pub struct Channel {
-    mountpoint: PathBuf,
+    mountpoint: Option<PathBuf>,
     fd: c_int,
+    /// Fd shared with notifiers, which is set to -1 when the channel is closed
+    shared_fd: Arc<RwLock<c_int>>,
 }
 
 impl Channel {
@@ -42,14 +58,72 @@ impl Channel {
             if fd < 0 {
                 Err(io::Error::last_os_error())
             } else {
-                Ok(Channel { mountpoint: mountpoint, fd: fd })
+                Ok(Channel::with_fd(Some(mountpoint), fd))
             }
         })
     }
 
-    /// Return path of the mounted filesystem
-    pub fn mountpoint(&self) -> &Path {
-        &self.mountpoint
+    /// Create a new communication channel to the CUSE kernel driver by opening the
+    /// CUSE device. The kernel driver creates a character device once the channel is
+    /// initialized and removes it if the channel is dropped.
+    #[cfg(target_os = "linux")]
+    pub fn cuse() -> io::Result<Channel> {
+        let path = CString::new("/dev/cuse").unwrap();
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC) };
+        if fd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Channel::with_fd(None, fd))
+        }
+    }
+
+    /// Create another channel to the same connection, e.g. for a worker thread of a
+    /// multi-threaded session. On Linux, the kernel driver queues replies per channel if
+    /// `clone` is set (using the FUSE_DEV_IOC_CLONE ioctl on a new /dev/fuse fd). Otherwise
+    /// the fd is duplicated and all channels share one queue. The new channel doesn't own
+    /// the mount point, so dropping it doesn't unmount.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub fn clone_fd(&self, clone: bool) -> io::Result<Channel> {
+        #[cfg(target_os = "linux")]
+        {
+            if clone {
+                let path = CString::new("/dev/fuse").unwrap();
+                let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC) };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let master_fd = self.fd as u32;
+                let rc = unsafe { libc::ioctl(fd, FUSE_DEV_IOC_CLONE as _, &master_fd) };
+                if rc < 0 {
+                    let err = io::Error::last_os_error();
+                    unsafe { libc::close(fd); }
+                    return Err(err);
+                }
+                return Ok(Channel::with_fd(None, fd));
+            }
+        }
+        let fd = unsafe { libc::fcntl(self.fd, libc::F_DUPFD_CLOEXEC, 0) };
+        if fd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Channel::with_fd(None, fd))
+        }
+    }
+
+    /// Create a channel for the given raw fd (for testing without a mounted channel)
+    #[cfg(test)]
+    pub(crate) fn from_raw_fd(fd: c_int) -> Channel {
+        Channel::with_fd(None, fd)
+    }
+
+    /// Create a channel for the given fd and mount point
+    fn with_fd(mountpoint: Option<PathBuf>, fd: c_int) -> Channel {
+        Channel { mountpoint, fd, shared_fd: Arc::new(RwLock::new(fd)) }
+    }
+
+    /// Return path of the mounted filesystem (none for CUSE channels)
+    pub fn mountpoint(&self) -> Option<&Path> {
+        self.mountpoint.as_deref()
     }
 
     /// Receives data up to the capacity of the given buffer (can block).
@@ -63,6 +137,13 @@ impl Channel {
         }
     }
 
+    /// Receives a request through the given pipe (can block), leaving the data of a write
+    /// request in the pipe. See `RequestPipe::receive`.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn receive_splice(&self, rx: &mut RequestPipe, buffer: &mut Vec<u8>, proto_minor: u32) -> io::Result<()> {
+        rx.receive(self.fd, buffer, proto_minor)
+    }
+
     /// Returns a sender object for this channel. The sender object can be
     /// used to send to the channel. Multiple sender objects can be used
     /// and they can safely be sent to other threads.
@@ -71,7 +152,13 @@ impl Channel {
         // a sender by using the same fd and use it in other threads. Only
         // the channel closes the fd when dropped. If any sender is used after
         // dropping the channel, it'll return an EBADF error.
-        ChannelSender { fd: self.fd }
+        ChannelSender { fd: self.fd, flags: 0 }
+    }
+
+    /// Returns a sender that shares the fd of this channel and stops sending once the
+    /// channel is closed, so that it can be kept beyond the lifetime of the channel
+    pub(crate) fn shared_sender(&self) -> SharedSender {
+        SharedSender { fd: self.shared_fd.clone() }
     }
 
     ///
@@ -87,30 +174,77 @@ impl Channel {
     pub fn evented(&mut self) -> io::Result<()> {
         channel::set_nonblocking(self.fd, true)
     }
-}
 
-impl Drop for Channel {
-    fn drop(&mut self) {
+    /// Close the channel and unmount its mount point (if it has one). Afterwards, nothing
+    /// can be received or sent anymore, and dropping the channel doesn't unmount again.
+    pub fn unmount(&mut self) -> io::Result<()> {
+        if self.fd < 0 {
+            return Ok(());
+        }
         // TODO: send ioctl FUSEDEVIOCSETDAEMONDEAD on macOS before closing the fd
         // Close the communication channel to the kernel driver
-        // (closing it before unnmount prevents sync unmount deadlock)
+        // (closing it before unnmount prevents sync unmount deadlock). Shared senders
+        // stop sending first, so that they never write to a reused fd.
+        *self.shared_fd.write().unwrap() = -1;
         unsafe { libc::close(self.fd); }
+        self.fd = -1;
         // Unmount this channel's mount point
-        let _ = unmount(&self.mountpoint);
+        match &self.mountpoint {
+            Some(mountpoint) => unmount(mountpoint),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        let _ = self.unmount();
     }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct ChannelSender {
     fd: c_int,
+    /// Capabilities negotiated during init (for splicing replies)
+    flags: u64,
 }
 
 impl ChannelSender {
+    /// Create a sender for the given raw fd (for testing without a mounted channel)
+    #[cfg(test)]
+    pub(crate) fn from_raw_fd(fd: c_int) -> ChannelSender {
+        ChannelSender { fd, flags: 0 }
+    }
+
+    /// Returns a sender that uses the given negotiated capabilities
+    pub(crate) fn with_flags(self, flags: u64) -> ChannelSender {
+        ChannelSender { flags, ..self }
+    }
+
     /// Send all data in the slice of slice of bytes in a single write (can block).
     pub fn send(&self, buffer: &[&[u8]]) -> io::Result<()> {
-        let iovecs: Vec<_> = buffer.iter().map(|d| {
+        if buffer.len() > MAX_IOVECS {
+            // Too many slices for a single writev, so they need to be joined
+            return self.send(&[&buffer.concat()]);
+        }
+        let iovec = |d: &&[u8]| {
             libc::iovec { iov_base: d.as_ptr() as *mut c_void, iov_len: d.len() as size_t }
-        }).collect();
+        };
+        if buffer.len() <= INLINE_IOVECS {
+            // Common case: build the iovecs on the stack
+            let mut iovecs = [libc::iovec { iov_base: ptr::null_mut(), iov_len: 0 }; INLINE_IOVECS];
+            for (iov, d) in iovecs.iter_mut().zip(buffer) {
+                *iov = iovec(d);
+            }
+            self.writev(&iovecs[..buffer.len()])
+        } else {
+            let iovecs: Vec<_> = buffer.iter().map(iovec).collect();
+            self.writev(&iovecs)
+        }
+    }
+
+    /// Write the given iovecs in a single writev call
+    fn writev(&self, iovecs: &[libc::iovec]) -> io::Result<()> {
         let rc = unsafe { libc::writev(self.fd, iovecs.as_ptr(), iovecs.len() as c_int) };
         if rc < 0 {
             Err(io::Error::last_os_error())
@@ -126,6 +260,40 @@ impl ReplySender for ChannelSender {
             error!("Failed to send FUSE reply: {}", err);
         }
     }
+
+    #[cfg(target_os = "linux")]
+    fn send_spliced(&self, header: &[u8], fd: RawFd, offset: i64, len: usize) -> io::Result<bool> {
+        if self.flags & FUSE_SPLICE_WRITE == 0 {
+            return Ok(false);
+        }
+        let flags = if self.flags & FUSE_SPLICE_MOVE != 0 { libc::SPLICE_F_MOVE } else { 0 };
+        splice::send_spliced(self.fd, header, fd, offset, len, flags)
+    }
+}
+
+/// Sender that shares the fd of a channel. Unlike `ChannelSender`, it knows when the
+/// channel is closed and fails with `ENODEV` afterwards instead of writing to the fd.
+#[derive(Clone, Debug)]
+pub(crate) struct SharedSender {
+    fd: Arc<RwLock<c_int>>,
+}
+
+impl SharedSender {
+    /// Create a sender for the given raw fd (for testing without a channel)
+    #[cfg(test)]
+    pub(crate) fn from_raw_fd(fd: c_int) -> SharedSender {
+        SharedSender { fd: Arc::new(RwLock::new(fd)) }
+    }
+
+    /// Send all data in the slice of slice of bytes in a single write (can block). The
+    /// channel can't be closed while sending.
+    pub(crate) fn send(&self, buffer: &[&[u8]]) -> io::Result<()> {
+        let fd = self.fd.read().unwrap();
+        if *fd < 0 {
+            return Err(io::Error::from_raw_os_error(libc::ENODEV));
+        }
+        ChannelSender { fd: *fd, flags: 0 }.send(buffer)
+    }
 }
 
 /// Unmount an arbitrary mount point
@@ -174,8 +342,11 @@ pub fn unmount(mountpoint: &Path) -> io::Result<()> {
 
 #[cfg(test)]
 mod test {
-    use super::with_fuse_args;
+    use super::{with_fuse_args, ChannelSender, MAX_IOVECS};
     use std::ffi::{CStr, OsStr};
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
 
     #[test]
     fn fuse_args() {
@@ -186,4 +357,18 @@ mod test {
             assert_eq!(unsafe { CStr::from_ptr(*args.argv.offset(2)).to_bytes() }, b"bar");
         });
     }
+
+    #[test]
+    fn send_many_slices() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (mut reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let data: Vec<[u8; 1]> = (0..MAX_IOVECS + 10).map(|i| [i as u8]).collect();
+        let slices: Vec<&[u8]> = data.iter().map(|d| &d[..]).collect();
+        ChannelSender::from_raw_fd(fds[1]).send(&slices).unwrap();
+        drop(writer);
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, slices.concat());
+    }
 }
@@ -0,0 +1,303 @@
+//! Character devices in userspace (CUSE)
+//!
+//! CUSE uses the FUSE protocol to implement character devices instead of filesystems. A CUSE
+//! session opens the CUSE kernel driver, which creates a character device with the name and
+//! device numbers given during initialization. Operations on the opened device are then sent
+//! to the session like file operations of a filesystem.
+
+use libc::{c_int, EAGAIN, EINTR, ENODEV, ENOENT, ENOSYS};
+use log::info;
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::sync::Arc;
+
+use crate::channel::Channel;
+use crate::interrupt::Interrupts;
+use crate::kernel_config::KernelConfig;
+use crate::ll::{self, RequestError};
+use crate::notify::{Notifier, PollHandle, Retrievals};
+use crate::reply::{ReplyData, ReplyEmpty, ReplyIoctl, ReplyOpen, ReplyPoll, ReplyWrite};
+use crate::request::Request;
+use crate::session::REQUEST_BUFFERS;
+
+/// Character device trait.
+///
+/// This trait must be implemented to provide a userspace character device via CUSE. The
+/// methods correspond to the operations of fuse_lowlevel_ops in libfuse that CUSE uses.
+/// Other than filesystem operations, device operations don't refer to an inode.
+pub trait CharDevice {
+    /// Initialize device.
+    /// Called before any other device method. The character device is created once this
+    /// method returns successfully.
+    fn init(&mut self, _req: &Request<'_>) -> Result<(), c_int> {
+        Ok(())
+    }
+
+    /// Clean up device.
+    /// Called on device exit.
+    fn destroy(&mut self, _req: &Request<'_>) {}
+
+    /// Open device.
+    /// Open flags (with the exception of O_CREAT, O_EXCL, O_NOCTTY and O_TRUNC) are
+    /// available in flags. The device may store an arbitrary file handle (pointer, index,
+    /// etc) in fh, and use this in other device operations (read, write, release, ioctl,
+    /// poll).
+    fn open(&mut self, _req: &Request<'_>, _flags: u32, reply: ReplyOpen) {
+        reply.opened(0, 0);
+    }
+
+    /// Read data.
+    /// The device may reply with less data than requested, which the read system call
+    /// returns as is. `offset` is the file position of the opened device.
+    fn read(&mut self, _req: &Request<'_>, _fh: u64, _offset: i64, _size: u32, reply: ReplyData) {
+        reply.error(ENOSYS);
+    }
+
+    /// Write data.
+    /// Reply with the number of bytes written, which the write system call returns as is.
+    fn write(&mut self, _req: &Request<'_>, _fh: u64, _offset: i64, _data: &[u8], _flags: u32, reply: ReplyWrite) {
+        reply.error(ENOSYS);
+    }
+
+    /// Release an open device.
+    /// Called when there are no more references to an open device: all file descriptors
+    /// are closed and all memory mappings are unmapped. For every open call there will be
+    /// exactly one release call.
+    fn release(&mut self, _req: &Request<'_>, _fh: u64, _flags: u32, reply: ReplyEmpty) {
+        reply.ok();
+    }
+
+    /// Control device.
+    /// See `Filesystem::ioctl`. If the session was created with `CUSE_UNRESTRICTED_IOCTL`,
+    /// all ioctls are unrestricted and can be retried with the memory areas they need.
+    fn ioctl(&mut self, _req: &Request<'_>, _fh: u64, _flags: u32, _cmd: u32, _arg: u64, _in_data: &[u8], _out_size: u32, reply: ReplyIoctl) {
+        reply.error(ENOSYS);
+    }
+
+    /// Poll for IO readiness events.
+    /// See `Filesystem::poll`.
+    fn poll(&mut self, _req: &Request<'_>, _fh: u64, _ph: Option<PollHandle>, _events: u32, _flags: u32, reply: ReplyPoll) {
+        reply.error(ENOSYS);
+    }
+}
+
+/// The CUSE session data structure
+#[derive(Debug)]
+pub struct CuseSession<D: CharDevice> {
+    /// Device operation implementations
+    pub device: D,
+    /// Communication channel to the kernel driver
+    ch: Channel,
+    /// Name of the device (in /dev)
+    devname: CString,
+    /// Major device number (0 lets the kernel choose one)
+    pub(crate) dev_major: u32,
+    /// Minor device number
+    pub(crate) dev_minor: u32,
+    /// CUSE init flags (`CUSE_*`)
+    pub(crate) flags: u32,
+    /// Connection configuration negotiated during init
+    pub(crate) config: KernelConfig,
+    /// Requests that haven't been replied to yet
+    interrupts: Arc<Interrupts>,
+    /// Retrieve notifications that haven't been replied to yet
+    retrievals: Arc<Retrievals>,
+    /// True if the device is initialized (init operation done)
+    pub initialized: bool,
+    /// True if the device was destroyed (destroy operation done)
+    pub destroyed: bool,
+}
+
+impl<D: CharDevice> CuseSession<D> {
+    /// Create a new session for the given device. Once initialized, the device is
+    /// available as `/dev/<devname>` with the given device numbers. `flags` are CUSE init
+    /// flags like `CUSE_UNRESTRICTED_IOCTL`.
+    pub fn new(device: D, devname: &OsStr, dev_major: u32, dev_minor: u32, flags: u32) -> io::Result<CuseSession<D>> {
+        let devname = CString::new(devname.as_bytes())?;
+        info!("Creating device {:?}", devname);
+        Channel::cuse().map(|ch| CuseSession::with_channel(device, ch, devname, dev_major, dev_minor, flags))
+    }
+
+    /// Create a new session that receives requests through the given channel
+    fn with_channel(device: D, ch: Channel, devname: CString, dev_major: u32, dev_minor: u32, flags: u32) -> CuseSession<D> {
+        CuseSession {
+            device,
+            ch,
+            devname,
+            dev_major,
+            dev_minor,
+            flags,
+            config: KernelConfig::empty(),
+            interrupts: Arc::new(Interrupts::default()),
+            retrievals: Arc::new(Retrievals::default()),
+            initialized: false,
+            destroyed: false,
+        }
+    }
+
+    /// Returns the device info sent to the kernel during init (NUL-separated key=value pairs)
+    pub(crate) fn device_info(&self) -> Vec<u8> {
+        let mut info = b"DEVNAME=".to_vec();
+        info.extend(self.devname.as_bytes_with_nul());
+        info
+    }
+
+    /// Returns a notifier to send notifications to the kernel driver, e.g. poll wakeups
+    pub fn notifier(&self) -> Notifier {
+        Notifier::new(self.ch.shared_sender(), self.retrievals.clone())
+    }
+
+    /// Destroy the device like the destroy request of the kernel driver does, for sessions
+    /// that end before the kernel driver sends it. Does nothing if the device wasn't
+    /// initialized or is destroyed already.
+    fn destroy(&mut self) {
+        if !self.initialized || self.destroyed {
+            return;
+        }
+        let req = Request::synthetic(self.ch.sender(), ll::Operation::Destroy, self.config, &self.interrupts);
+        self.device.destroy(&req);
+        self.destroyed = true;
+    }
+
+    /// Run the session loop that receives kernel requests and dispatches them to method
+    /// calls into the device, like `Session::run`.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut buffer = REQUEST_BUFFERS.take(self.config.buffer_size());
+        loop {
+            // The buffer needs to grow once the max write size is negotiated
+            if buffer.capacity() < self.config.buffer_size() {
+                buffer = REQUEST_BUFFERS.take(self.config.buffer_size());
+            }
+            match self.ch.receive(&mut buffer) {
+                Ok(()) => match Request::new(self.ch.sender(), &buffer, self.config, &self.interrupts) {
+                    Ok(request) => request.dispatch_cuse(self),
+                    Err(RequestError::UnknownOperation(..)) => continue,
+                    Err(_) => return Ok(()),
+                },
+                Err(err) => match err.raw_os_error() {
+                    Some(ENOENT) | Some(EINTR) | Some(EAGAIN) => continue,
+                    // The device was removed
+                    Some(ENODEV) => return Ok(()),
+                    _ => return Err(err),
+                },
+            }
+        }
+    }
+}
+
+impl<D: CharDevice> Drop for CuseSession<D> {
+    fn drop(&mut self) {
+        self.destroy();
+        info!("Removed device {:?}", self.devname);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CharDevice, CuseSession};
+    use crate::channel::Channel;
+    use crate::request::Request;
+    use fuse_abi::{cuse_init_in, cuse_init_out, fuse_in_header, fuse_opcode, fuse_out_header};
+    use std::ffi::CString;
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::{mem, ptr};
+
+    struct Device(Arc<AtomicUsize>);
+
+    impl CharDevice for Device {
+        fn destroy(&mut self, _req: &Request<'_>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Create a session that sends to the given fd and counts device destroy calls
+    fn session(fd: libc::c_int, destroyed: &Arc<AtomicUsize>) -> CuseSession<Device> {
+        let devname = CString::new("fuse-test").unwrap();
+        CuseSession::with_channel(Device(Arc::clone(destroyed)), Channel::from_raw_fd(fd), devname, 0x12, 0x34, 0x1)
+    }
+
+    fn pipe() -> (File, libc::c_int) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        (unsafe { File::from_raw_fd(fds[0]) }, fds[1])
+    }
+
+    #[test]
+    fn device_info() {
+        let (_reader, fd) = pipe();
+        let se = session(fd, &Arc::default());
+        assert_eq!(se.device_info(), b"DEVNAME=fuse-test\0");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn init_reply() {
+        #[repr(C)]
+        struct InitRequest {
+            header: fuse_in_header,
+            arg: cuse_init_in,
+        }
+        let request = InitRequest {
+            header: fuse_in_header {
+                len: mem::size_of::<InitRequest>() as u32,
+                opcode: fuse_opcode::CUSE_INIT as u32,
+                unique: 0xdeadbeef,
+                nodeid: 0,
+                uid: 0,
+                gid: 0,
+                pid: 0,
+                total_extlen: 0,
+                padding: 0,
+            },
+            arg: cuse_init_in { major: 7, minor: 31, unused: 0, flags: 0 },
+        };
+        let data = unsafe { std::slice::from_raw_parts(&request as *const InitRequest as *const u8, mem::size_of::<InitRequest>()) };
+        let (mut reader, fd) = pipe();
+        let mut se = session(fd, &Arc::default());
+        let interrupts = Arc::clone(&se.interrupts);
+        Request::new(se.ch.sender(), data, se.config, &interrupts).unwrap().dispatch_cuse(&mut se);
+        assert!(se.initialized);
+        let max_write = se.config.max_write();
+        drop(se);
+
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        let info = b"DEVNAME=fuse-test\0";
+        let header_size = mem::size_of::<fuse_out_header>();
+        let init_size = mem::size_of::<cuse_init_out>();
+        assert_eq!(bytes.len(), header_size + init_size + info.len());
+        let header: fuse_out_header = unsafe { ptr::read_unaligned(bytes.as_ptr() as *const fuse_out_header) };
+        assert_eq!((header.len as usize, header.error, header.unique), (bytes.len(), 0, 0xdeadbeef));
+        let init: cuse_init_out = unsafe { ptr::read_unaligned(bytes[header_size..].as_ptr() as *const cuse_init_out) };
+        assert_eq!((init.major, init.minor), (fuse_abi::FUSE_KERNEL_VERSION, fuse_abi::FUSE_KERNEL_MINOR_VERSION));
+        assert_eq!(init.flags, 0x1);
+        assert_eq!((init.max_read, init.max_write), (max_write, max_write));
+        assert_eq!((init.dev_major, init.dev_minor), (0x12, 0x34));
+        assert_eq!(&bytes[header_size + init_size..], info);
+    }
+
+    #[test]
+    fn destroy_on_drop() {
+        let destroyed = Arc::default();
+        let (_reader, fd) = pipe();
+        let mut se = session(fd, &destroyed);
+        se.initialized = true;
+        drop(se);
+        assert_eq!(destroyed.load(Ordering::SeqCst), 1);
+
+        // Devices that aren't initialized or are destroyed already aren't destroyed again
+        let (_reader, fd) = pipe();
+        drop(session(fd, &destroyed));
+        let (_reader, fd) = pipe();
+        let mut se = session(fd, &destroyed);
+        se.initialized = true;
+        se.destroyed = true;
+        drop(se);
+        assert_eq!(destroyed.load(Ordering::SeqCst), 1);
+    }
+}
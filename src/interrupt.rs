@@ -0,0 +1,304 @@
+//! Request interruption
+//!
+//! If a process waiting for a filesystem operation receives a signal, the kernel driver sends
+//! an INTERRUPT request that refers to the original request. The session keeps track of the
+//! requests that haven't been replied to yet, so that the filesystem implementation can check
+//! or wait for the interruption of a request, even while processing it in another thread.
+
+use libc::{c_int, EINTR};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::reply::ReplySender;
+
+/// Token to check or wait for the interruption of a request.
+///
+/// A token can be cloned and sent to other threads. An interrupted request should usually be
+/// replied with `EINTR`, which `check` conveniently returns as error.
+#[derive(Clone, Debug)]
+pub struct InterruptToken {
+    state: Arc<InterruptState>,
+}
+
+#[derive(Debug, Default)]
+struct InterruptState {
+    interrupted: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl InterruptToken {
+    /// Create a token for a request that isn't interrupted (yet)
+    pub(crate) fn new() -> InterruptToken {
+        InterruptToken { state: Arc::new(InterruptState::default()) }
+    }
+
+    /// Mark the request as interrupted and wake up all waiters
+    fn interrupt(&self) {
+        *self.state.interrupted.lock().unwrap() = true;
+        self.state.cond.notify_all();
+    }
+
+    /// Returns true if the kernel asked to interrupt the request
+    pub fn is_interrupted(&self) -> bool {
+        *self.state.interrupted.lock().unwrap()
+    }
+
+    /// Returns `EINTR` as error if the request is interrupted, which can be passed on to the
+    /// reply's `error` method
+    pub fn check(&self) -> Result<(), c_int> {
+        if self.is_interrupted() {
+            Err(EINTR)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Block until the request is interrupted
+    pub fn wait(&self) {
+        let mut interrupted = self.state.interrupted.lock().unwrap();
+        while !*interrupted {
+            interrupted = self.state.cond.wait(interrupted).unwrap();
+        }
+    }
+
+    /// Block until the request is interrupted or the given timeout elapsed. Returns true if
+    /// the request is interrupted.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let interrupted = self.state.interrupted.lock().unwrap();
+        let (interrupted, _) = self.state.cond.wait_timeout_while(interrupted, timeout, |i| !*i).unwrap();
+        *interrupted
+    }
+}
+
+/// Number of requests in flight that can be tracked without allocating
+const INITIAL_REQUESTS: usize = 64;
+
+/// Requests of a session that haven't been replied to yet
+#[derive(Debug)]
+pub(crate) struct Interrupts {
+    table: Mutex<InterruptTable>,
+    /// Notified when the last request in flight is completed
+    idle: Condvar,
+}
+
+#[derive(Debug)]
+struct InterruptTable {
+    /// State of requests in flight by their unique id. The map keeps its capacity, so
+    /// tracking a request only allocates if more requests than ever before are in flight.
+    requests: HashMap<u64, RequestState>,
+    /// Interrupts of unknown requests (unique id of the interrupt and the interrupted request)
+    pending: VecDeque<(u64, u64)>,
+}
+
+/// Interruption state of a request in flight. The token is only created once the
+/// filesystem asks for it.
+#[derive(Debug, Default)]
+struct RequestState {
+    interrupted: bool,
+    token: Option<InterruptToken>,
+}
+
+impl Default for Interrupts {
+    fn default() -> Interrupts {
+        let table = InterruptTable { requests: HashMap::with_capacity(INITIAL_REQUESTS), pending: VecDeque::new() };
+        Interrupts { table: Mutex::new(table), idle: Condvar::new() }
+    }
+}
+
+impl Interrupts {
+    /// Start tracking the request with the given unique id. If an interrupt for this
+    /// request arrived before, the request is interrupted right away.
+    pub(crate) fn register(&self, unique: u64) {
+        let mut table = self.table.lock().unwrap();
+        let mut state = RequestState::default();
+        if let Some(pos) = table.pending.iter().position(|&(_, target)| target == unique) {
+            table.pending.remove(pos);
+            state.interrupted = true;
+        }
+        table.requests.insert(unique, state);
+    }
+
+    /// Returns true if the request with the given unique id is in flight and interrupted
+    pub(crate) fn is_interrupted(&self, unique: u64) -> bool {
+        self.table.lock().unwrap().requests.get(&unique).is_some_and(|state| state.interrupted)
+    }
+
+    /// Returns the token of the request with the given unique id, which is created on the
+    /// first call. Requests that aren't in flight get a token that is never interrupted.
+    pub(crate) fn token(&self, unique: u64) -> InterruptToken {
+        let mut table = self.table.lock().unwrap();
+        match table.requests.get_mut(&unique) {
+            Some(state) => {
+                let interrupted = state.interrupted;
+                let token = state.token.get_or_insert_with(|| {
+                    let token = InterruptToken::new();
+                    if interrupted {
+                        token.interrupt();
+                    }
+                    token
+                });
+                token.clone()
+            }
+            None => InterruptToken::new(),
+        }
+    }
+
+    /// Interrupt the request with the given unique id. If the request is unknown, the
+    /// interrupt is kept until the request arrives or `take_stale` hands it out.
+    pub(crate) fn interrupt(&self, unique: u64, target: u64) {
+        let mut table = self.table.lock().unwrap();
+        match table.requests.get_mut(&target) {
+            Some(state) => {
+                state.interrupted = true;
+                if let Some(token) = &state.token {
+                    token.interrupt();
+                }
+            }
+            None => table.pending.push_back((unique, target)),
+        }
+    }
+
+    /// Returns the unique id of the oldest interrupt whose request is unknown. The kernel
+    /// expects such interrupts to be replied with `EAGAIN` after some time, upon which it
+    /// either re-sends the interrupt (if the request is still pending) or drops it (if the
+    /// request is already completed). Waiting for the next request to arrive avoids busy
+    /// looping on interrupts the kernel keeps re-sending.
+    pub(crate) fn take_stale(&self) -> Option<u64> {
+        self.table.lock().unwrap().pending.pop_front().map(|(unique, _)| unique)
+    }
+
+    /// Stop tracking the request with the given unique id
+    pub(crate) fn complete(&self, unique: u64) {
+        let mut table = self.table.lock().unwrap();
+        table.requests.remove(&unique);
+        if table.requests.is_empty() {
+            self.idle.notify_all();
+        }
+    }
+
+    /// Block until all requests in flight are completed or the given timeout elapsed.
+    /// Returns false if requests are still in flight.
+    pub(crate) fn wait_idle(&self, timeout: Duration) -> bool {
+        let table = self.table.lock().unwrap();
+        let (table, _) = self.idle.wait_timeout_while(table, timeout, |t| !t.requests.is_empty()).unwrap();
+        table.requests.is_empty()
+    }
+
+    /// Interrupt all requests in flight
+    pub(crate) fn interrupt_all(&self) {
+        for state in self.table.lock().unwrap().requests.values_mut() {
+            state.interrupted = true;
+            if let Some(token) = &state.token {
+                token.interrupt();
+            }
+        }
+    }
+}
+
+/// Reply sender that stops tracking a request once its reply is sent
+#[derive(Debug)]
+pub(crate) struct TrackingSender<S> {
+    sender: S,
+    unique: u64,
+    interrupts: Arc<Interrupts>,
+}
+
+impl<S> TrackingSender<S> {
+    pub(crate) fn new(sender: S, unique: u64, interrupts: Arc<Interrupts>) -> TrackingSender<S> {
+        TrackingSender { sender, unique, interrupts }
+    }
+}
+
+impl<S: ReplySender> ReplySender for TrackingSender<S> {
+    fn send(&self, data: &[&[u8]]) {
+        self.interrupts.complete(self.unique);
+        self.sender.send(data);
+    }
+
+    fn send_spliced(&self, header: &[u8], fd: RawFd, offset: i64, len: usize) -> io::Result<bool> {
+        let sent = self.sender.send_spliced(header, fd, offset, len)?;
+        if sent {
+            self.interrupts.complete(self.unique);
+        }
+        Ok(sent)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupt_in_flight() {
+        let interrupts = Interrupts::default();
+        interrupts.register(1);
+        let token = interrupts.token(1);
+        assert_eq!(token.check(), Ok(()));
+        interrupts.interrupt(2, 1);
+        assert!(token.is_interrupted());
+        assert_eq!(token.check(), Err(EINTR));
+        assert!(token.wait_timeout(Duration::from_secs(0)));
+        assert_eq!(interrupts.take_stale(), None);
+    }
+
+    #[test]
+    fn interrupt_before_request() {
+        let interrupts = Interrupts::default();
+        interrupts.interrupt(2, 1);
+        interrupts.register(1);
+        let token = interrupts.token(1);
+        assert!(token.is_interrupted());
+        assert_eq!(interrupts.take_stale(), None);
+    }
+
+    #[test]
+    fn interrupt_after_reply() {
+        let interrupts = Interrupts::default();
+        interrupts.register(1);
+        let token = interrupts.token(1);
+        interrupts.complete(1);
+        interrupts.interrupt(2, 1);
+        assert!(!token.is_interrupted());
+        assert!(!token.wait_timeout(Duration::from_millis(1)));
+        assert_eq!(interrupts.take_stale(), Some(2));
+        assert_eq!(interrupts.take_stale(), None);
+    }
+
+    #[test]
+    fn wait_for_requests_in_flight() {
+        let interrupts = Arc::new(Interrupts::default());
+        assert!(interrupts.wait_idle(Duration::from_secs(10)));
+        interrupts.register(1);
+        let other = interrupts.clone();
+        let handle = std::thread::spawn(move || other.complete(1));
+        assert!(interrupts.wait_idle(Duration::from_secs(10)));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn wait_for_stuck_requests() {
+        let interrupts = Interrupts::default();
+        interrupts.register(1);
+        let token = interrupts.token(1);
+        assert!(!interrupts.wait_idle(Duration::from_millis(1)));
+        interrupts.interrupt_all();
+        assert!(token.is_interrupted());
+    }
+
+    #[test]
+    fn token_on_demand() {
+        let interrupts = Interrupts::default();
+        interrupts.register(1);
+        interrupts.interrupt(2, 1);
+        assert!(interrupts.is_interrupted(1));
+        // A token created after the interrupt is interrupted already
+        assert!(interrupts.token(1).is_interrupted());
+        interrupts.complete(1);
+        assert!(!interrupts.is_interrupted(1));
+        assert!(!interrupts.token(1).is_interrupted());
+    }
+}
@@ -0,0 +1,394 @@
+//! Kernel connection configuration
+//!
+//! During initialization, the kernel driver reports the protocol version it speaks and the
+//! capabilities it supports. The filesystem implementation can inspect these and request the
+//! capabilities and limits it wants to use on the connection. After initialization, the
+//! negotiated configuration stays available for the lifetime of the session.
+
+use fuse_abi::consts::*;
+#[cfg(target_os = "linux")]
+use fuse_abi::cuse_init_in;
+use fuse_abi::{fuse_init_in, FUSE_KERNEL_MINOR_VERSION};
+use std::sync::OnceLock;
+use std::{cmp, fs};
+
+use crate::session::MAX_WRITE_SIZE;
+
+/// We generally support async reads, the extended init flags and max pages (which allows
+/// write requests larger than 32 pages)
+#[cfg(not(target_os = "macos"))]
+const INIT_FLAGS: u64 = FUSE_ASYNC_READ | FUSE_INIT_EXT | FUSE_MAX_PAGES;
+
+/// On macOS, we additionally support case insensitiveness, volume renames and xtimes
+#[cfg(target_os = "macos")]
+const INIT_FLAGS: u64 = FUSE_ASYNC_READ | FUSE_CASE_INSENSITIVE | FUSE_VOL_RENAME | FUSE_XTIMES;
+
+/// The minimum max write size the kernel driver accepts
+pub(crate) const MIN_WRITE_SIZE: u32 = 4096;
+
+/// The minimum size of a buffer the kernel driver reads requests into
+const MIN_BUFFER_SIZE: usize = 8192;
+
+/// Page size assumed for the max number of pages of a request
+const PAGE_SIZE: u32 = 4096;
+
+/// Max number of pages of a request the kernel driver allows by default
+const DEFAULT_MAX_PAGES_LIMIT: u32 = 256;
+
+/// Returns the largest max write size the kernel driver accepts. It's limited by the
+/// max number of pages of a request (`/proc/sys/fs/fuse/max_pages_limit`, 256 pages if
+/// unavailable) and never exceeds `MAX_WRITE_SIZE`.
+pub(crate) fn kernel_max_write() -> u32 {
+    static KERNEL_MAX_WRITE: OnceLock<u32> = OnceLock::new();
+    *KERNEL_MAX_WRITE.get_or_init(|| {
+        let limit = fs::read_to_string("/proc/sys/fs/fuse/max_pages_limit").ok();
+        max_write_for_pages(limit.and_then(|limit| limit.trim().parse().ok()))
+    })
+}
+
+/// Returns the max write size for the given max number of pages of a request
+fn max_write_for_pages(pages: Option<u32>) -> u32 {
+    let pages = pages.filter(|&pages| pages > 0).unwrap_or(DEFAULT_MAX_PAGES_LIMIT);
+    cmp::min(pages.saturating_mul(PAGE_SIZE), MAX_WRITE_SIZE as u32)
+}
+
+/// Configuration of the connection to the kernel driver.
+///
+/// A mutable reference is passed to `Filesystem::init`, which may request additional
+/// capabilities and change the connection limits. Capabilities can only be requested if the
+/// kernel driver offers them.
+#[derive(Clone, Copy, Debug)]
+pub struct KernelConfig {
+    /// FUSE protocol major version reported by the kernel
+    proto_major: u32,
+    /// FUSE protocol minor version reported by the kernel
+    proto_minor: u32,
+    /// Capabilities offered by the kernel
+    capabilities: u64,
+    /// Capabilities requested by the filesystem
+    requested: u64,
+    /// Max readahead size offered by the kernel
+    kernel_max_readahead: u32,
+    /// Max readahead size requested by the filesystem
+    max_readahead: u32,
+    /// Max size of write requests
+    max_write: u32,
+    /// Upper bound of the max size of write requests set by the session
+    max_write_limit: u32,
+    /// Max number of pending background requests (0 means kernel default)
+    max_background: u16,
+    /// Number of pending background requests before the kernel considers the
+    /// filesystem congested (0 means kernel default)
+    congestion_threshold: u16,
+}
+
+impl KernelConfig {
+    /// Create an empty configuration for a connection that isn't initialized yet
+    pub(crate) fn empty() -> KernelConfig {
+        KernelConfig {
+            proto_major: 0,
+            proto_minor: 0,
+            capabilities: 0,
+            requested: 0,
+            kernel_max_readahead: 0,
+            max_readahead: 0,
+            max_write: 0,
+            max_write_limit: kernel_max_write(),
+            max_background: 0,
+            congestion_threshold: 0,
+        }
+    }
+
+    /// Create a new configuration from the given kernel INIT arguments. By default, the
+    /// capabilities we generally support are requested if the kernel offers them.
+    pub(crate) fn new(arg: &fuse_init_in) -> KernelConfig {
+        let capabilities = init_flags(arg);
+        KernelConfig {
+            proto_major: arg.major,
+            proto_minor: arg.minor,
+            capabilities,
+            requested: capabilities & INIT_FLAGS,
+            kernel_max_readahead: arg.max_readahead,
+            max_readahead: arg.max_readahead,
+            max_write: kernel_max_write(),
+            max_write_limit: kernel_max_write(),
+            max_background: 0,
+            congestion_threshold: 0,
+        }
+    }
+
+    /// Create a new configuration from the given CUSE INIT arguments. CUSE init flags have
+    /// a different meaning than FUSE init flags, so no capabilities are offered.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn cuse(arg: &cuse_init_in) -> KernelConfig {
+        KernelConfig {
+            proto_major: arg.major,
+            proto_minor: arg.minor,
+            max_write: kernel_max_write(),
+            ..KernelConfig::empty()
+        }
+    }
+
+    /// Returns the FUSE protocol major version reported by the kernel
+    pub fn proto_major(&self) -> u32 {
+        self.proto_major
+    }
+
+    /// Returns the FUSE protocol minor version reported by the kernel
+    pub fn proto_minor(&self) -> u32 {
+        self.proto_minor
+    }
+
+    /// Returns the FUSE protocol minor version used on the connection, which is the lower
+    /// of the kernel's and our version
+    pub(crate) fn negotiated_minor(&self) -> u32 {
+        cmp::min(self.proto_minor, FUSE_KERNEL_MINOR_VERSION)
+    }
+
+    /// Returns the capability flags (`FUSE_*` init flags) offered by the kernel
+    pub fn capabilities(&self) -> u64 {
+        self.capabilities
+    }
+
+    /// Returns the capability flags requested for this connection. After initialization,
+    /// these are the negotiated flags.
+    pub fn flags(&self) -> u64 {
+        self.requested
+    }
+
+    /// Request the given capability flags. If the kernel doesn't offer some of them, nothing
+    /// is changed and the unsupported flags are returned as error.
+    pub fn add_capabilities(&mut self, flags: u64) -> Result<(), u64> {
+        let unsupported = flags & !self.capabilities;
+        if unsupported != 0 {
+            return Err(unsupported);
+        }
+        self.requested |= flags;
+        Ok(())
+    }
+
+    /// Stop requesting the given capability flags
+    pub fn remove_capabilities(&mut self, flags: u64) {
+        self.requested &= !flags;
+    }
+
+    /// Returns the max readahead size
+    pub fn max_readahead(&self) -> u32 {
+        self.max_readahead
+    }
+
+    /// Set the max readahead size. It can't exceed the size offered by the kernel, which is
+    /// returned as error if the given value is too large. On success, the previous value
+    /// is returned.
+    pub fn set_max_readahead(&mut self, value: u32) -> Result<u32, u32> {
+        if value > self.kernel_max_readahead {
+            return Err(self.kernel_max_readahead);
+        }
+        let previous = self.max_readahead;
+        self.max_readahead = value;
+        Ok(previous)
+    }
+
+    /// Returns the max size of write requests
+    pub fn max_write(&self) -> u32 {
+        self.max_write
+    }
+
+    /// Set the max size of write requests. It must be at least 4k and can't exceed the
+    /// session's limit (see `Session::set_max_write_limit`). If the given value is out of
+    /// range, the nearest valid value is returned as error. On success, the previous value is
+    /// returned. Note that the kernel only sends writes larger than 4k if `FUSE_BIG_WRITES`
+    /// is requested as well.
+    pub fn set_max_write(&mut self, value: u32) -> Result<u32, u32> {
+        if value < MIN_WRITE_SIZE {
+            return Err(MIN_WRITE_SIZE);
+        }
+        if value > self.max_write_limit {
+            return Err(self.max_write_limit);
+        }
+        let previous = self.max_write;
+        self.max_write = value;
+        Ok(previous)
+    }
+
+    /// Limit the max size of write requests to the given value (the session's limit)
+    pub(crate) fn limit_max_write(&mut self, limit: u32) {
+        self.max_write_limit = cmp::min(limit, kernel_max_write());
+        self.max_write = cmp::min(self.max_write, self.max_write_limit);
+    }
+
+    /// Returns the max number of pages of a request to reply to init with. Without
+    /// `FUSE_MAX_PAGES`, the kernel uses its default of 32 pages.
+    pub(crate) fn max_pages(&self) -> u16 {
+        if self.requested & FUSE_MAX_PAGES == 0 {
+            return 0;
+        }
+        let pages = self.max_write.div_ceil(PAGE_SIZE);
+        cmp::min(pages, u32::from(u16::MAX)) as u16
+    }
+
+    /// Returns the size of a buffer that can hold any request of the connection, i.e. the
+    /// largest write request plus its headers. Before init, only small requests are sent.
+    pub(crate) fn buffer_size(&self) -> usize {
+        cmp::max(self.max_write as usize + 4096, MIN_BUFFER_SIZE)
+    }
+
+    /// Returns the max number of pending background requests (0 means kernel default)
+    pub fn max_background(&self) -> u16 {
+        self.max_background
+    }
+
+    /// Set the max number of pending background requests. On success, the previous value
+    /// is returned. Zero is not allowed since the kernel interprets it as "use default".
+    pub fn set_max_background(&mut self, value: u16) -> Result<u16, u16> {
+        if value == 0 {
+            return Err(1);
+        }
+        let previous = self.max_background;
+        self.max_background = value;
+        Ok(previous)
+    }
+
+    /// Returns the congestion threshold (0 means kernel default)
+    pub fn congestion_threshold(&self) -> u16 {
+        self.congestion_threshold
+    }
+
+    /// Set the number of pending background requests at which the kernel considers the
+    /// filesystem congested. It can't exceed the max number of background requests, which
+    /// is returned as error if the given value is too large. On success, the previous
+    /// value is returned.
+    pub fn set_congestion_threshold(&mut self, value: u16) -> Result<u16, u16> {
+        if self.max_background != 0 && value > self.max_background {
+            return Err(self.max_background);
+        }
+        let previous = self.congestion_threshold;
+        self.congestion_threshold = value;
+        Ok(previous)
+    }
+}
+
+/// Returns the init flags offered by the kernel. Flags beyond the first 32 bits are only
+/// valid if the kernel sets `FUSE_INIT_EXT`.
+#[cfg(not(target_os = "macos"))]
+fn init_flags(arg: &fuse_init_in) -> u64 {
+    let flags = u64::from(arg.flags);
+    if flags & FUSE_INIT_EXT != 0 {
+        flags | u64::from(arg.flags2) << 32
+    } else {
+        flags
+    }
+}
+
+/// Returns the init flags offered by the kernel
+#[cfg(target_os = "macos")]
+fn init_flags(arg: &fuse_init_in) -> u64 {
+    u64::from(arg.flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_in(flags: u64) -> fuse_init_in {
+        fuse_init_in {
+            major: 7,
+            minor: 36,
+            max_readahead: 131072,
+            flags: flags as u32,
+            flags2: (flags >> 32) as u32,
+            unused: [0; 11],
+        }
+    }
+
+    #[test]
+    fn default_flags() {
+        let config = KernelConfig::new(&init_in(FUSE_ASYNC_READ | FUSE_POSIX_LOCKS));
+        assert_eq!(config.capabilities(), FUSE_ASYNC_READ | FUSE_POSIX_LOCKS);
+        assert_eq!(config.flags(), FUSE_ASYNC_READ);
+        let config = KernelConfig::new(&init_in(FUSE_POSIX_LOCKS));
+        assert_eq!(config.flags(), 0);
+    }
+
+    #[test]
+    fn add_capabilities() {
+        let mut config = KernelConfig::new(&init_in(FUSE_ASYNC_READ | FUSE_BIG_WRITES));
+        assert_eq!(config.add_capabilities(FUSE_BIG_WRITES | FUSE_POSIX_LOCKS), Err(FUSE_POSIX_LOCKS));
+        assert_eq!(config.flags(), FUSE_ASYNC_READ);
+        assert_eq!(config.add_capabilities(FUSE_BIG_WRITES), Ok(()));
+        assert_eq!(config.flags(), FUSE_ASYNC_READ | FUSE_BIG_WRITES);
+        config.remove_capabilities(FUSE_ASYNC_READ);
+        assert_eq!(config.flags(), FUSE_BIG_WRITES);
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn extended_flags() {
+        let config = KernelConfig::new(&init_in(FUSE_ASYNC_READ | FUSE_SECURITY_CTX));
+        assert_eq!(config.capabilities(), FUSE_ASYNC_READ);
+        let mut config = KernelConfig::new(&init_in(FUSE_ASYNC_READ | FUSE_INIT_EXT | FUSE_SECURITY_CTX));
+        assert_eq!(config.capabilities(), FUSE_ASYNC_READ | FUSE_INIT_EXT | FUSE_SECURITY_CTX);
+        assert_eq!(config.flags(), FUSE_ASYNC_READ | FUSE_INIT_EXT);
+        assert_eq!(config.add_capabilities(FUSE_SECURITY_CTX), Ok(()));
+        assert_eq!(config.add_capabilities(FUSE_PASSTHROUGH), Err(FUSE_PASSTHROUGH));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn cuse() {
+        let config = KernelConfig::cuse(&cuse_init_in { major: 7, minor: 31, unused: 0, flags: 1 });
+        assert_eq!((config.proto_major(), config.proto_minor()), (7, 31));
+        assert_eq!(config.capabilities(), 0);
+        assert_eq!(config.flags(), 0);
+        assert_eq!(config.max_write(), kernel_max_write());
+    }
+
+    #[test]
+    fn limits() {
+        let mut config = KernelConfig::new(&init_in(0));
+        assert_eq!(config.set_max_readahead(262144), Err(131072));
+        assert_eq!(config.set_max_readahead(4096), Ok(131072));
+        assert_eq!(config.set_max_write(1024), Err(4096));
+        assert_eq!(config.set_max_write(131072), Ok(kernel_max_write()));
+        assert_eq!(config.set_max_background(0), Err(1));
+        assert_eq!(config.set_max_background(16), Ok(0));
+        assert_eq!(config.set_congestion_threshold(32), Err(16));
+        assert_eq!(config.set_congestion_threshold(12), Ok(0));
+    }
+
+    #[test]
+    fn max_write_limit() {
+        let mut config = KernelConfig::new(&init_in(0));
+        config.limit_max_write(131072);
+        assert_eq!(config.max_write(), 131072);
+        assert_eq!(config.set_max_write(262144), Err(131072));
+        assert_eq!(config.set_max_write(65536), Ok(131072));
+        assert_eq!(config.buffer_size(), 65536 + 4096);
+        assert_eq!(KernelConfig::empty().buffer_size(), MIN_BUFFER_SIZE);
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn max_pages() {
+        let mut config = KernelConfig::new(&init_in(FUSE_MAX_PAGES));
+        assert_eq!(config.max_pages() as u32, kernel_max_write() / PAGE_SIZE);
+        assert_eq!(config.set_max_write(65536 + 1), Ok(kernel_max_write()));
+        assert_eq!(config.max_pages(), 17);
+        config.remove_capabilities(FUSE_MAX_PAGES);
+        assert_eq!(config.max_pages(), 0);
+    }
+
+    #[test]
+    fn clamped_max_write() {
+        assert_eq!(max_write_for_pages(None), 256 * PAGE_SIZE);
+        assert_eq!(max_write_for_pages(Some(0)), 256 * PAGE_SIZE);
+        assert_eq!(max_write_for_pages(Some(32)), 32 * PAGE_SIZE);
+        assert_eq!(max_write_for_pages(Some(65535)), MAX_WRITE_SIZE as u32);
+        let mut config = KernelConfig::new(&init_in(0));
+        assert!(config.max_write() <= MAX_WRITE_SIZE as u32);
+        assert_eq!(config.max_write(), kernel_max_write());
+        assert_eq!(config.buffer_size(), kernel_max_write() as usize + 4096);
+        config.limit_max_write(MAX_WRITE_SIZE as u32);
+        assert_eq!(config.max_write_limit, kernel_max_write());
+    }
+}
@@ -11,27 +11,55 @@ use std::convert::AsRef;
 use std::io;
 use std::ffi::OsStr;
 use std::path::Path;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use libc::{c_int, ENOSYS};
 
 pub use fuse_abi::FUSE_ROOT_ID;
 pub use fuse_abi::consts;
+#[cfg(target_os = "linux")]
+pub use cuse::{CharDevice, CuseSession};
+pub use interrupt::InterruptToken;
+pub use kernel_config::KernelConfig;
+pub use notify::{Notifier, PollHandle};
+pub use ready::WaitReady;
 pub use reply::{Reply, ReplyEmpty, ReplyData, ReplyEntry, ReplyAttr, ReplyOpen};
 pub use reply::{ReplyWrite, ReplyStatfs, ReplyCreate, ReplyLock, ReplyBmap, ReplyDirectory};
-pub use reply::ReplyXattr;
+pub use reply::ReplyDirectoryPlus;
+pub use reply::{ReplyIoctl, ReplyLseek, ReplyPoll, ReplyXattr};
 #[cfg(target_os = "macos")]
 pub use reply::ReplyXTimes;
 pub use request::Request;
-pub use session::{Session, BackgroundSession, EventedSession};
+pub use session::{Session, BackgroundSession, EventedSession, HandleStatus, LoopConfig};
+pub use shutdown::ShutdownToken;
+#[cfg(target_os = "linux")]
+pub use splice::PipeData;
+#[cfg(feature = "tokio_support")]
+pub use session::AsyncSession;
+#[cfg(feature = "tokio_support")]
+pub use async_filesystem::{AsyncFilesystem, Attr, DirEntry, Entry, Opened, RequestInfo, SetAttr, Statfs};
+pub use sync_filesystem::SyncFilesystem;
 
 #[cfg(feature = "serde_support")]
 use serde_derive::{Deserialize, Serialize};
 
+#[cfg(feature = "tokio_support")]
+mod async_filesystem;
+mod buffer;
 mod channel;
+#[cfg(target_os = "linux")]
+mod cuse;
+mod interrupt;
+mod kernel_config;
 mod ll;
+mod notify;
+mod ready;
 mod reply;
 mod request;
 mod session;
+mod shutdown;
+#[cfg(target_os = "linux")]
+mod splice;
+mod sync_filesystem;
 
 /// File types
 #[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
@@ -87,16 +115,103 @@ pub struct FileAttr {
     pub flags: u32,
 }
 
+/// Mode flags of a fallocate operation (see fallocate(2)). Without flags, the given range
+/// is allocated and the file size is extended if needed.
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct FallocateFlags(u32);
+
+impl FallocateFlags {
+    /// Don't change the file size, even if the range extends beyond it (FALLOC_FL_KEEP_SIZE)
+    pub const KEEP_SIZE: FallocateFlags = FallocateFlags(0x01);
+    /// Deallocate the range, always combined with `KEEP_SIZE` (FALLOC_FL_PUNCH_HOLE)
+    pub const PUNCH_HOLE: FallocateFlags = FallocateFlags(0x02);
+    /// Zero the range, preferably by converting it to unwritten extents (FALLOC_FL_ZERO_RANGE)
+    pub const ZERO_RANGE: FallocateFlags = FallocateFlags(0x10);
+
+    /// Create flags from the raw mode of a fallocate request
+    pub fn from_bits(bits: u32) -> FallocateFlags {
+        FallocateFlags(bits)
+    }
+
+    /// Returns the raw mode
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns true if all of the given flags are set
+    pub fn contains(&self, other: FallocateFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for FallocateFlags {
+    type Output = FallocateFlags;
+
+    fn bitor(self, other: FallocateFlags) -> FallocateFlags {
+        FallocateFlags(self.0 | other.0)
+    }
+}
+
+/// Flags of a rename operation (see renameat2(2))
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct RenameFlags(u32);
+
+impl RenameFlags {
+    /// Don't overwrite the target, fail with `EEXIST` if it exists (RENAME_NOREPLACE)
+    pub const NOREPLACE: RenameFlags = RenameFlags(0x01);
+    /// Atomically exchange source and target, which both must exist (RENAME_EXCHANGE)
+    pub const EXCHANGE: RenameFlags = RenameFlags(0x02);
+    /// Leave a whiteout object at the source, used by overlay filesystems (RENAME_WHITEOUT)
+    pub const WHITEOUT: RenameFlags = RenameFlags(0x04);
+
+    /// Create flags from the raw flags of a rename request
+    pub fn from_bits(bits: u32) -> RenameFlags {
+        RenameFlags(bits)
+    }
+
+    /// Returns the raw flags
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns true if no flags are set
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if all of the given flags are set
+    pub fn contains(&self, other: RenameFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for RenameFlags {
+    type Output = RenameFlags;
+
+    fn bitor(self, other: RenameFlags) -> RenameFlags {
+        RenameFlags(self.0 | other.0)
+    }
+}
+
 /// Filesystem trait.
 ///
 /// This trait must be implemented to provide a userspace filesystem via FUSE.
 /// These methods correspond to fuse_lowlevel_ops in libfuse. Reasonable default
 /// implementations are provided here to get a mountable filesystem that does
 /// nothing.
+///
+/// Long running operations should check `Request::check_interrupted` (or wait on the
+/// token returned by `Request::interrupt_token`) and reply with the returned `EINTR`
+/// error if the calling process was interrupted. Interrupts that arrive after the
+/// reply are answered by the session.
 pub trait Filesystem {
     /// Initialize filesystem.
-    /// Called before any other filesystem method.
-    fn init(&mut self, _req: &Request<'_>) -> Result<(), c_int> {
+    /// Called before any other filesystem method. The kernel connection configuration
+    /// shows the capabilities the kernel offers and may be changed to request capabilities
+    /// and limits for this connection.
+    fn init(&mut self, _req: &Request<'_>, _config: &mut KernelConfig) -> Result<(), c_int> {
         Ok(())
     }
 
@@ -118,12 +233,23 @@ pub trait Filesystem {
     /// inodes will receive a forget message.
     fn forget(&mut self, _req: &Request<'_>, _ino: u64, _nlookup: u64) {}
 
+    /// Forget about multiple inodes.
+    /// Each node is given as inode number and number of lookups to forget, see `forget`.
+    /// By default, `forget` is called for each node. This method can be implemented to
+    /// handle a whole batch at once, e.g. by locking the inode table only once.
+    fn batch_forget(&mut self, req: &Request<'_>, nodes: &[(u64, u64)]) {
+        for &(ino, nlookup) in nodes {
+            self.forget(req, ino, nlookup);
+        }
+    }
+
     /// Get file attributes.
     fn getattr(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyAttr) {
         reply.error(ENOSYS);
     }
 
     /// Set file attributes.
+    #[allow(clippy::too_many_arguments)]
     fn setattr(&mut self, _req: &Request<'_>, _ino: u64, _mode: Option<u32>, _uid: Option<u32>, _gid: Option<u32>, _size: Option<u64>, _atime: Option<SystemTime>, _mtime: Option<SystemTime>, _fh: Option<u64>, _crtime: Option<SystemTime>, _chgtime: Option<SystemTime>, _bkuptime: Option<SystemTime>, _flags: Option<u32>, reply: ReplyAttr) {
         reply.error(ENOSYS);
     }
@@ -160,7 +286,10 @@ pub trait Filesystem {
     }
 
     /// Rename a file.
-    fn rename(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _newparent: u64, _newname: &OsStr, reply: ReplyEmpty) {
+    /// `flags` are empty for a plain rename. Flags are only sent by Linux kernels (ABI 7.23
+    /// and later) for renameat2 calls. Unsupported flags should be replied with `EINVAL`.
+    #[allow(clippy::too_many_arguments)]
+    fn rename(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _newparent: u64, _newname: &OsStr, _flags: RenameFlags, reply: ReplyEmpty) {
         reply.error(ENOSYS);
     }
 
@@ -198,10 +327,25 @@ pub trait Filesystem {
     /// which case the return value of the write system call will reflect the return
     /// value of this operation. fh will contain the value set by the open method, or
     /// will be undefined if the open method didn't set any value.
+    #[allow(clippy::too_many_arguments)]
     fn write(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _data: &[u8], _flags: u32, reply: ReplyWrite) {
         reply.error(ENOSYS);
     }
 
+    /// Write data from a pipe (Linux only).
+    /// Called instead of write if FUSE_SPLICE_READ is negotiated during init and the
+    /// kernel moved the data of a write request into a pipe. The data can be moved into a
+    /// backing file with `PipeData::splice_to` without copying it through userspace. The
+    /// default implementation reads the data into memory and calls write.
+    #[cfg(target_os = "linux")]
+    #[allow(clippy::too_many_arguments)]
+    fn write_pipe(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, mut data: PipeData<'_>, flags: u32, reply: ReplyWrite) {
+        match data.read() {
+            Ok(data) => self.write(req, ino, fh, offset, &data, flags, reply),
+            Err(err) => reply.error(err.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
     /// Flush method.
     /// This is called on each close() of the opened file. Since file descriptors can
     /// be duplicated (dup, dup2, fork), for one open call there may be many flush
@@ -224,6 +368,7 @@ pub trait Filesystem {
     /// the release. fh will contain the value set by the open method, or will be undefined
     /// if the open method didn't set any value. flags will contain the same flags as for
     /// open.
+    #[allow(clippy::too_many_arguments)]
     fn release(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _flags: u32, _lock_owner: u64, _flush: bool, reply: ReplyEmpty) {
         reply.ok();
     }
@@ -255,6 +400,16 @@ pub trait Filesystem {
         reply.error(ENOSYS);
     }
 
+    /// Read directory with attributes.
+    /// Like readdir, but every entry is sent together with its attributes, which saves the
+    /// kernel a lookup per entry. Only called if `FUSE_DO_READDIRPLUS` was requested in the
+    /// init method. With `FUSE_READDIRPLUS_AUTO`, the kernel decides per request whether to
+    /// call readdir or readdirplus, so both need to be implemented. Every entry except "."
+    /// and ".." counts as a lookup, so forget will be called for it later.
+    fn readdirplus(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, reply: ReplyDirectoryPlus) {
+        reply.error(ENOSYS);
+    }
+
     /// Release an open directory.
     /// For every opendir call there will be exactly one releasedir call. fh will
     /// contain the value set by the opendir method, or will be undefined if the
@@ -277,6 +432,7 @@ pub trait Filesystem {
     }
 
     /// Set an extended attribute.
+    #[allow(clippy::too_many_arguments)]
     fn setxattr(&mut self, _req: &Request<'_>, _ino: u64, _name: &OsStr, _value: &[u8], _flags: u32, _position: u32, reply: ReplyEmpty) {
         reply.error(ENOSYS);
     }
@@ -325,6 +481,7 @@ pub trait Filesystem {
     }
 
     /// Test for a POSIX file lock.
+    #[allow(clippy::too_many_arguments)]
     fn getlk(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _lock_owner: u64, _start: u64, _end: u64, _typ: u32, _pid: u32, reply: ReplyLock) {
         reply.error(ENOSYS);
     }
@@ -336,6 +493,7 @@ pub trait Filesystem {
     /// used to fill in this field in getlk(). Note: if the locking methods are not
     /// implemented, the kernel will still allow file locking to work locally.
     /// Hence these are only interesting for network filesystems and similar.
+    #[allow(clippy::too_many_arguments)]
     fn setlk(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _lock_owner: u64, _start: u64, _end: u64, _typ: u32, _pid: u32, _sleep: bool, reply: ReplyEmpty) {
         reply.error(ENOSYS);
     }
@@ -347,6 +505,60 @@ pub trait Filesystem {
         reply.error(ENOSYS);
     }
 
+    /// Control device.
+    /// `cmd` and `arg` are the arguments of the ioctl system call, `in_data` holds the
+    /// input data and `out_size` is the max size of the output data. `flags` tells whether
+    /// the ioctl comes from a 32-bit process (`FUSE_IOCTL_COMPAT`, `FUSE_IOCTL_32BIT`) or is
+    /// done on a directory (`FUSE_IOCTL_DIR`, only sent if `FUSE_HAS_IOCTL_DIR` is requested
+    /// in init). For restricted ioctls, the kernel derives input and output size from the
+    /// command number. Unrestricted ioctls (`FUSE_IOCTL_UNRESTRICTED`, only used by CUSE)
+    /// initially come without data and can be replied with `reply.retry()` to ask the kernel
+    /// for the memory areas the command needs.
+    #[allow(clippy::too_many_arguments)]
+    fn ioctl(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _flags: u32, _cmd: u32, _arg: u64, _in_data: &[u8], _out_size: u32, reply: ReplyIoctl) {
+        reply.error(ENOSYS);
+    }
+
+    /// Poll for IO readiness events.
+    /// `events` are the poll events (`POLLIN`, `POLLOUT`, ...) the caller is interested in.
+    /// If the kernel wants to be notified when the file becomes ready, a poll handle `ph` is
+    /// given, which should be stored and fired once any of the requested events occur. If
+    /// this method isn't implemented, the kernel considers the file always ready.
+    #[allow(clippy::too_many_arguments)]
+    fn poll(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _ph: Option<PollHandle>, _events: u32, _flags: u32, reply: ReplyPoll) {
+        reply.error(ENOSYS);
+    }
+
+    /// Preallocate or deallocate space of a file.
+    /// The range starts at `offset` and spans `length` bytes. `mode` tells whether the file
+    /// size should be kept and whether the range should be deallocated or zeroed. The kernel
+    /// only sends the `KEEP_SIZE`, `PUNCH_HOLE` and `ZERO_RANGE` flags. If this method isn't
+    /// implemented, the kernel fails the fallocate system call with `EOPNOTSUPP`.
+    #[allow(clippy::too_many_arguments)]
+    fn fallocate(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _length: i64, _mode: FallocateFlags, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Reposition the file offset to the next data or hole.
+    /// `whence` is either `SEEK_DATA` or `SEEK_HOLE`, other values are handled by the
+    /// kernel. Reply with the resulting offset, or `ENXIO` if there's no data or hole at
+    /// or after `offset`. If this method isn't implemented, the kernel treats the whole file
+    /// as data.
+    fn lseek(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _whence: i32, reply: ReplyLseek) {
+        reply.error(ENOSYS);
+    }
+
+    /// Copy a range of data from one file to another.
+    /// This is called for copy_file_range system calls on two files of this filesystem and
+    /// allows to copy data without passing it through the kernel, e.g. with server-side
+    /// copies. Reply with the number of bytes copied, which may be less than `len`. If this
+    /// method isn't implemented, the kernel falls back to copying the data with reads and
+    /// writes.
+    #[allow(clippy::too_many_arguments)]
+    fn copy_file_range(&mut self, _req: &Request<'_>, _ino_in: u64, _fh_in: u64, _offset_in: i64, _ino_out: u64, _fh_out: u64, _offset_out: i64, _len: u64, _flags: u64, reply: ReplyWrite) {
+        reply.error(ENOSYS);
+    }
+
     /// macOS only: Rename the volume. Set fuse_init_out.flags during init to
     /// FUSE_VOL_RENAME to enable
     #[cfg(target_os = "macos")]
@@ -377,13 +589,23 @@ pub fn mount<FS: Filesystem, P: AsRef<Path>>(filesystem: FS, mountpoint: P, opti
     Session::new(filesystem, mountpoint.as_ref(), options).and_then(|mut se| se.run())
 }
 
+/// How long `spawn_mount` waits for the filesystem to be initialized
+const SPAWN_INIT_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Mount the given filesystem to the given mountpoint. This function spawns
 /// a background thread to handle filesystem operations while being mounted
-/// and therefore returns immediately. The returned handle should be stored
-/// to reference the mounted filesystem. If it's dropped, the filesystem will
-/// be unmounted.
-pub unsafe fn spawn_mount<'a, FS: Filesystem+Send+'a, P: AsRef<Path>>(filesystem: FS, mountpoint: P, options: &[&OsStr]) -> io::Result<BackgroundSession<'a>> {
-    Session::new(filesystem, mountpoint.as_ref(), options).and_then(|se| se.spawn())
+/// and returns as soon as the filesystem is initialized, so that the mount
+/// point can be used right away. The returned handle should be stored to
+/// reference the mounted filesystem. If it's dropped, the filesystem will be
+/// unmounted. If the filesystem fails to initialize (or isn't initialized
+/// within 10 seconds), it is unmounted and the error is returned. To wait for
+/// the initialization differently, use `Session::spawn` and
+/// `BackgroundSession::wait_ready`. To mount a filesystem that borrows data,
+/// use `Session::spawn_scoped`.
+pub fn spawn_mount<FS: Filesystem+Send+'static, P: AsRef<Path>>(filesystem: FS, mountpoint: P, options: &[&OsStr]) -> io::Result<BackgroundSession<'static>> {
+    let se = Session::new(filesystem, mountpoint.as_ref(), options)?.spawn()?;
+    se.wait_ready(SPAWN_INIT_TIMEOUT)?;
+    Ok(se)
 }
 
 ///
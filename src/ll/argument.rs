@@ -4,8 +4,30 @@
 //! structures (request arguments).
 
 use std::ffi::OsStr;
-use std::mem;
+use std::ops::Deref;
 use std::os::unix::ffi::OsStrExt;
+use std::{mem, ptr, slice};
+
+
+/// A typed argument fetched from request data. Kernels speaking an older ABI version may send
+/// a shorter variant of an argument structure, in which case a copy of the structure with the
+/// missing fields zeroed is held instead of a reference.
+#[derive(Debug)]
+pub enum Argument<'a, T> {
+    Borrowed(&'a T),
+    Owned(T),
+}
+
+impl<'a, T> Deref for Argument<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            Argument::Borrowed(arg) => arg,
+            Argument::Owned(arg) => arg,
+        }
+    }
+}
 
 
 /// An iterator that can be used to fetch typed arguments from a byte slice.
@@ -47,6 +69,29 @@ impl<'a> ArgumentIterator<'a> {
         (bytes.as_ptr() as *const T).as_ref()
     }
 
+    /// Fetch a typed argument that the kernel sends with the given size, which may be smaller
+    /// than the size of T for older ABI versions. Missing trailing fields are zeroed. Returns
+    /// `None` if there's not enough data left. This function is unsafe because there is no
+    /// guarantee that the data actually contains the type T and that T is valid if zeroed.
+    pub unsafe fn fetch_compat<T>(&mut self, size: usize) -> Option<Argument<'a, T>> {
+        if size >= mem::size_of::<T>() {
+            return self.fetch().map(Argument::Borrowed);
+        }
+        let bytes = self.fetch_bytes(size)?;
+        let mut arg: T = mem::zeroed();
+        ptr::copy_nonoverlapping(bytes.as_ptr(), &mut arg as *mut T as *mut u8, size);
+        Some(Argument::Owned(arg))
+    }
+
+    /// Fetch a slice of the given number of typed arguments. Returns `None` if there's not
+    /// enough data left. This function is unsafe because there is no guarantee that the data
+    /// actually contains the type T.
+    pub unsafe fn fetch_slice<T>(&mut self, count: usize) -> Option<&'a [T]> {
+        let len = mem::size_of::<T>().checked_mul(count)?;
+        let bytes = self.fetch_bytes(len)?;
+        Some(slice::from_raw_parts(bytes.as_ptr() as *const T, count))
+    }
+
     /// Fetch a (zero-terminated) string (can be non-utf8). Returns `None` if there's not enough
     /// data left or no zero-termination could be found. This function is unsafe because there is
     /// no guarantee that the data actually contains a string.
@@ -63,14 +108,18 @@ impl<'a> ArgumentIterator<'a> {
 mod tests {
     use super::*;
 
-    const TEST_DATA: [u8; 10] = [0x66, 0x6f, 0x6f, 0x00, 0x62, 0x61, 0x72, 0x00, 0x62, 0x61];
+    /// Test data aligned like request data in a receive buffer
+    #[repr(C, align(8))]
+    struct Aligned<T>(T);
+
+    static TEST_DATA: Aligned<[u8; 10]> = Aligned([0x66, 0x6f, 0x6f, 0x00, 0x62, 0x61, 0x72, 0x00, 0x62, 0x61]);
 
     #[repr(C)]
     struct TestArgument { p1: u8, p2: u8, p3: u16 }
 
     #[test]
     fn all_data() {
-        let mut it = ArgumentIterator::new(&TEST_DATA);
+        let mut it = ArgumentIterator::new(&TEST_DATA.0);
         unsafe { it.fetch_str().unwrap() };
         let arg = it.fetch_all();
         assert_eq!(arg, [0x62, 0x61, 0x72, 0x00, 0x62, 0x61]);
@@ -78,7 +127,7 @@ mod tests {
 
     #[test]
     fn bytes_data() {
-        let mut it = ArgumentIterator::new(&TEST_DATA);
+        let mut it = ArgumentIterator::new(&TEST_DATA.0);
         let arg = it.fetch_bytes(5).unwrap();
         assert_eq!(arg, [0x66, 0x6f, 0x6f, 0x00, 0x62]);
         let arg = it.fetch_bytes(2).unwrap();
@@ -88,7 +137,7 @@ mod tests {
 
     #[test]
     fn generic_argument() {
-        let mut it = ArgumentIterator::new(&TEST_DATA);
+        let mut it = ArgumentIterator::new(&TEST_DATA.0);
         let arg: &TestArgument = unsafe { it.fetch().unwrap() };
         assert_eq!(arg.p1, 0x66);
         assert_eq!(arg.p2, 0x6f);
@@ -102,7 +151,7 @@ mod tests {
 
     #[test]
     fn string_argument() {
-        let mut it = ArgumentIterator::new(&TEST_DATA);
+        let mut it = ArgumentIterator::new(&TEST_DATA.0);
         let arg = unsafe { it.fetch_str().unwrap() };
         assert_eq!(arg, "foo");
         let arg = unsafe { it.fetch_str().unwrap() };
@@ -110,9 +159,36 @@ mod tests {
         assert_eq!(it.len(), 2);
     }
 
+    #[test]
+    fn compat_argument() {
+        let mut it = ArgumentIterator::new(&TEST_DATA.0);
+        let arg: Argument<'_, TestArgument> = unsafe { it.fetch_compat(2).unwrap() };
+        assert_eq!(arg.p1, 0x66);
+        assert_eq!(arg.p2, 0x6f);
+        assert_eq!(arg.p3, 0x0000);
+        let arg: Argument<'_, TestArgument> = unsafe { it.fetch_compat(4).unwrap() };
+        assert_eq!(arg.p1, 0x6f);
+        assert_eq!(arg.p2, 0x00);
+        assert_eq!(arg.p3, 0x6162);
+        assert_eq!(it.len(), 4);
+    }
+
+    #[test]
+    fn slice_argument() {
+        let mut it = ArgumentIterator::new(&TEST_DATA.0);
+        let arg: &[TestArgument] = unsafe { it.fetch_slice(2).unwrap() };
+        assert_eq!(arg.len(), 2);
+        assert_eq!(arg[0].p1, 0x66);
+        assert_eq!(arg[1].p1, 0x62);
+        assert_eq!(arg[1].p3, 0x0072);
+        let arg: Option<&[TestArgument]> = unsafe { it.fetch_slice(1) };
+        assert!(arg.is_none());
+        assert_eq!(it.len(), 2);
+    }
+
     #[test]
     fn mixed_arguments() {
-        let mut it = ArgumentIterator::new(&TEST_DATA);
+        let mut it = ArgumentIterator::new(&TEST_DATA.0);
         let arg: &TestArgument = unsafe { it.fetch().unwrap() };
         assert_eq!(arg.p1, 0x66);
         assert_eq!(arg.p2, 0x6f);
@@ -125,7 +201,7 @@ mod tests {
 
     #[test]
     fn out_of_data() {
-        let mut it = ArgumentIterator::new(&TEST_DATA);
+        let mut it = ArgumentIterator::new(&TEST_DATA.0);
         let _arg = it.fetch_bytes(8).unwrap();
         let arg: Option<&TestArgument> = unsafe { it.fetch() };
         assert!(arg.is_none());
@@ -3,20 +3,21 @@
 //! A request represents information about a filesystem operation the kernel driver wants us to
 //! perform.
 
+use fuse_abi::consts::*;
 use fuse_abi::*;
 use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::{error, fmt, mem};
 
-use super::argument::ArgumentIterator;
+use super::argument::{Argument, ArgumentIterator};
 
 /// Error that may occur while reading and parsing a request from the kernel driver.
 #[derive(Debug)]
 pub enum RequestError {
     /// Not enough data for parsing header (short read).
     ShortReadHeader(usize),
-    /// Kernel requested an unknown operation.
-    UnknownOperation(u32),
+    /// Kernel requested an unknown operation (opcode and unique id of the request).
+    UnknownOperation(u32, u64),
     /// Not enough data for arguments (short read).
     ShortRead(usize, usize),
     /// Insufficient argument data.
@@ -32,7 +33,7 @@ impl fmt::Display for RequestError {
                 len,
                 mem::size_of::<fuse_in_header>()
             ),
-            RequestError::UnknownOperation(opcode) => write!(f, "Unknown FUSE opcode ({})", opcode),
+            RequestError::UnknownOperation(opcode, _) => write!(f, "Unknown FUSE opcode ({})", opcode),
             RequestError::ShortRead(len, total) => {
                 write!(f, "Short read of FUSE request ({} < {})", len, total)
             }
@@ -63,7 +64,7 @@ pub enum Operation<'a> {
         link: &'a OsStr,
     },
     MkNod {
-        arg: &'a fuse_mknod_in,
+        arg: Argument<'a, fuse_mknod_in>,
         name: &'a OsStr,
     },
     MkDir {
@@ -89,10 +90,10 @@ pub enum Operation<'a> {
         arg: &'a fuse_open_in,
     },
     Read {
-        arg: &'a fuse_read_in,
+        arg: Argument<'a, fuse_read_in>,
     },
     Write {
-        arg: &'a fuse_write_in,
+        arg: Argument<'a, fuse_write_in>,
         data: &'a [u8],
     },
     StatFs,
@@ -103,7 +104,7 @@ pub enum Operation<'a> {
         arg: &'a fuse_fsync_in,
     },
     SetXAttr {
-        arg: &'a fuse_setxattr_in,
+        arg: Argument<'a, fuse_setxattr_in>,
         name: &'a OsStr,
         value: &'a [u8],
     },
@@ -121,13 +122,13 @@ pub enum Operation<'a> {
         arg: &'a fuse_flush_in,
     },
     Init {
-        arg: &'a fuse_init_in,
+        arg: Argument<'a, fuse_init_in>,
     },
     OpenDir {
         arg: &'a fuse_open_in,
     },
     ReadDir {
-        arg: &'a fuse_read_in,
+        arg: Argument<'a, fuse_read_in>,
     },
     ReleaseDir {
         arg: &'a fuse_release_in,
@@ -136,19 +137,19 @@ pub enum Operation<'a> {
         arg: &'a fuse_fsync_in,
     },
     GetLk {
-        arg: &'a fuse_lk_in,
+        arg: Argument<'a, fuse_lk_in>,
     },
     SetLk {
-        arg: &'a fuse_lk_in,
+        arg: Argument<'a, fuse_lk_in>,
     },
     SetLkW {
-        arg: &'a fuse_lk_in,
+        arg: Argument<'a, fuse_lk_in>,
     },
     Access {
         arg: &'a fuse_access_in,
     },
     Create {
-        arg: &'a fuse_create_in,
+        arg: Argument<'a, fuse_create_in>,
         name: &'a OsStr,
     },
     Interrupt {
@@ -158,28 +159,56 @@ pub enum Operation<'a> {
         arg: &'a fuse_bmap_in,
     },
     Destroy,
-    // TODO: FUSE_IOCTL since ABI 7.11
-    // IoCtl {
-    //    arg: &'a fuse_ioctl_in,
-    //    data: &'a [u8],
-    // },
-    // TODO: FUSE_POLL since ABI 7.11
-    // Poll {
-    //     arg: &'a fuse_poll_in,
-    // },
-    // TODO: FUSE_NOTIFY_REPLY since ABI 7.15
-    // NotifyReply {
-    //     data: &'a [u8],
-    // },
-    // TODO: FUSE_BATCH_FORGET since ABI 7.16
-    // BatchForget {
-    //     arg: &'a fuse_forget_in,
-    //     nodes: &'a [fuse_forget_one],
-    // },
-    // TODO: FUSE_FALLOCATE since ABI 7.19
-    // FAllocate {
-    //     arg: &'a fuse_fallocate_in,
-    // },
+    IoCtl {
+        arg: &'a fuse_ioctl_in,
+        data: &'a [u8],
+    },
+    Poll {
+        arg: &'a fuse_poll_in,
+    },
+    NotifyReply {
+        arg: &'a fuse_notify_retrieve_in,
+        data: &'a [u8],
+    },
+    BatchForget {
+        arg: &'a fuse_batch_forget_in,
+        nodes: &'a [fuse_forget_one],
+    },
+    FAllocate {
+        arg: &'a fuse_fallocate_in,
+    },
+    ReadDirPlus {
+        arg: &'a fuse_read_in,
+    },
+    Rename2 {
+        arg: &'a fuse_rename2_in,
+        name: &'a OsStr,
+        newname: &'a OsStr,
+    },
+    LSeek {
+        arg: &'a fuse_lseek_in,
+    },
+    CopyFileRange {
+        arg: &'a fuse_copy_file_range_in,
+    },
+    SetupMapping {
+        arg: &'a fuse_setupmapping_in,
+    },
+    RemoveMapping {
+        arg: &'a fuse_removemapping_in,
+        mappings: &'a [fuse_removemapping_one],
+    },
+    SyncFs,
+    TmpFile {
+        arg: &'a fuse_create_in,
+        name: &'a OsStr,
+    },
+    StatX {
+        arg: &'a fuse_statx_in,
+    },
+    CopyFileRange64 {
+        arg: &'a fuse_copy_file_range_in,
+    },
 
     #[cfg(target_os = "macos")]
     SetVolName {
@@ -194,10 +223,9 @@ pub enum Operation<'a> {
         newname: &'a OsStr,
     },
 
-    // TODO: CUSE_INIT since ABI 7.12
-    // CuseInit {
-    //     arg: &'a fuse_init_in,
-    // },
+    CuseInit {
+        arg: &'a cuse_init_in,
+    },
 }
 
 impl<'a> fmt::Display for Operation<'a> {
@@ -239,6 +267,21 @@ impl<'a> fmt::Display for Operation<'a> {
             Operation::Interrupt { arg } => write!(f, "INTERRUPT unique {}", arg.unique),
             Operation::BMap { arg } => write!(f, "BMAP blocksize {}, ids {}", arg.blocksize, arg.block),
             Operation::Destroy => write!(f, "DESTROY"),
+            Operation::IoCtl { arg, data } => write!(f, "IOCTL fh {}, cmd {}, data size {}, flags {:#x}", arg.fh, arg.cmd, data.len(), arg.flags),
+            Operation::Poll { arg } => write!(f, "POLL fh {}, kh {}, flags {:#x}", arg.fh, arg.kh, arg.flags),
+            Operation::NotifyReply { arg, data } => write!(f, "NOTIFY_REPLY offset {}, size {}, data size {}", arg.offset, arg.size, data.len()),
+            Operation::BatchForget { arg, nodes } => write!(f, "BATCH_FORGET count {}, nodes {}", arg.count, nodes.len()),
+            Operation::FAllocate { arg } => write!(f, "FALLOCATE fh {}, offset {}, length {}, mode {:#x}", arg.fh, arg.offset, arg.length, arg.mode),
+            Operation::ReadDirPlus { arg } => write!(f, "READDIRPLUS fh {}, offset {}, size {}", arg.fh, arg.offset, arg.size),
+            Operation::Rename2 { arg, name, newname } => write!(f, "RENAME2 name {:?}, newdir {:#018x}, newname {:?}, flags {:#x}", name, arg.newdir, newname, arg.flags),
+            Operation::LSeek { arg } => write!(f, "LSEEK fh {}, offset {}, whence {}", arg.fh, arg.offset, arg.whence),
+            Operation::CopyFileRange { arg } => write!(f, "COPY_FILE_RANGE fh {}, offset {}, nodeid out {:#018x}, fh out {}, offset out {}, len {}, flags {:#x}", arg.fh_in, arg.off_in, arg.nodeid_out, arg.fh_out, arg.off_out, arg.len, arg.flags),
+            Operation::SetupMapping { arg } => write!(f, "SETUPMAPPING fh {}, offset {}, len {}, flags {:#x}, moffset {}", arg.fh, arg.foffset, arg.len, arg.flags, arg.moffset),
+            Operation::RemoveMapping { arg, mappings } => write!(f, "REMOVEMAPPING count {}, mappings {}", arg.count, mappings.len()),
+            Operation::SyncFs => write!(f, "SYNCFS"),
+            Operation::TmpFile { arg, name } => write!(f, "TMPFILE name {:?}, mode {:#05o}, flags {:#x}", name, arg.mode, arg.flags),
+            Operation::StatX { arg } => write!(f, "STATX fh {}, getattr flags {:#x}, flags {:#x}, mask {:#x}", arg.fh, arg.getattr_flags, arg.sx_flags, arg.sx_mask),
+            Operation::CopyFileRange64 { arg } => write!(f, "COPY_FILE_RANGE_64 fh {}, offset {}, nodeid out {:#018x}, fh out {}, offset out {}, len {}, flags {:#x}", arg.fh_in, arg.off_in, arg.nodeid_out, arg.fh_out, arg.off_out, arg.len, arg.flags),
 
             #[cfg(target_os = "macos")]
             Operation::SetVolName { name } => write!(f, "SETVOLNAME name {:?}", name),
@@ -246,12 +289,14 @@ impl<'a> fmt::Display for Operation<'a> {
             Operation::GetXTimes => write!(f, "GETXTIMES"),
             #[cfg(target_os = "macos")]
             Operation::Exchange { arg, oldname, newname } => write!(f, "EXCHANGE olddir {:#018x}, oldname {:?}, newdir {:#018x}, newname {:?}, options {:#x}", arg.olddir, oldname, arg.newdir, newname, arg.options),
+
+            Operation::CuseInit { arg } => write!(f, "CUSE_INIT kernel ABI {}.{}, flags {:#x}", arg.major, arg.minor, arg.flags),
         }
     }
 }
 
 impl<'a> Operation<'a> {
-    fn parse(opcode: &fuse_opcode, data: &mut ArgumentIterator<'a>) -> Option<Self> {
+    fn parse(opcode: &fuse_opcode, proto_minor: u32, flags: u64, data: &mut ArgumentIterator<'a>) -> Option<Self> {
         unsafe {
             Some(match opcode {
                 fuse_opcode::FUSE_LOOKUP => Operation::Lookup {
@@ -266,7 +311,10 @@ impl<'a> Operation<'a> {
                     link: data.fetch_str()?,
                 },
                 fuse_opcode::FUSE_MKNOD => Operation::MkNod {
-                    arg: data.fetch()?,
+                    arg: match proto_minor {
+                        0..=11 => data.fetch_compat(FUSE_COMPAT_MKNOD_IN_SIZE)?,
+                        _ => data.fetch_compat(mem::size_of::<fuse_mknod_in>())?,
+                    },
                     name: data.fetch_str()?,
                 },
                 fuse_opcode::FUSE_MKDIR => Operation::MkDir {
@@ -289,16 +337,24 @@ impl<'a> Operation<'a> {
                     name: data.fetch_str()?,
                 },
                 fuse_opcode::FUSE_OPEN => Operation::Open { arg: data.fetch()? },
-                fuse_opcode::FUSE_READ => Operation::Read { arg: data.fetch()? },
+                fuse_opcode::FUSE_READ => Operation::Read {
+                    arg: match proto_minor {
+                        0..=8 => data.fetch_compat(FUSE_COMPAT_READ_IN_SIZE)?,
+                        _ => data.fetch_compat(mem::size_of::<fuse_read_in>())?,
+                    },
+                },
                 fuse_opcode::FUSE_WRITE => Operation::Write {
-                    arg: data.fetch()?,
+                    arg: match proto_minor {
+                        0..=8 => data.fetch_compat(FUSE_COMPAT_WRITE_IN_SIZE)?,
+                        _ => data.fetch_compat(mem::size_of::<fuse_write_in>())?,
+                    },
                     data: data.fetch_all(),
                 },
                 fuse_opcode::FUSE_STATFS => Operation::StatFs,
                 fuse_opcode::FUSE_RELEASE => Operation::Release { arg: data.fetch()? },
                 fuse_opcode::FUSE_FSYNC => Operation::FSync { arg: data.fetch()? },
                 fuse_opcode::FUSE_SETXATTR => Operation::SetXAttr {
-                    arg: data.fetch()?,
+                    arg: Self::fetch_setxattr_in(flags, data)?,
                     name: data.fetch_str()?,
                     value: data.fetch_all(),
                 },
@@ -311,22 +367,76 @@ impl<'a> Operation<'a> {
                     name: data.fetch_str()?,
                 },
                 fuse_opcode::FUSE_FLUSH => Operation::Flush { arg: data.fetch()? },
-                fuse_opcode::FUSE_INIT => Operation::Init { arg: data.fetch()? },
+                fuse_opcode::FUSE_INIT => Operation::Init {
+                    // Kernels before ABI 7.36 don't send the extended init flags
+                    arg: match data.len() {
+                        len if len < mem::size_of::<fuse_init_in>() => data.fetch_compat(FUSE_COMPAT_INIT_IN_SIZE)?,
+                        _ => data.fetch_compat(mem::size_of::<fuse_init_in>())?,
+                    },
+                },
                 fuse_opcode::FUSE_OPENDIR => Operation::OpenDir { arg: data.fetch()? },
-                fuse_opcode::FUSE_READDIR => Operation::ReadDir { arg: data.fetch()? },
+                fuse_opcode::FUSE_READDIR => Operation::ReadDir {
+                    arg: match proto_minor {
+                        0..=8 => data.fetch_compat(FUSE_COMPAT_READ_IN_SIZE)?,
+                        _ => data.fetch_compat(mem::size_of::<fuse_read_in>())?,
+                    },
+                },
                 fuse_opcode::FUSE_RELEASEDIR => Operation::ReleaseDir { arg: data.fetch()? },
                 fuse_opcode::FUSE_FSYNCDIR => Operation::FSyncDir { arg: data.fetch()? },
-                fuse_opcode::FUSE_GETLK => Operation::GetLk { arg: data.fetch()? },
-                fuse_opcode::FUSE_SETLK => Operation::SetLk { arg: data.fetch()? },
-                fuse_opcode::FUSE_SETLKW => Operation::SetLkW { arg: data.fetch()? },
+                fuse_opcode::FUSE_GETLK => Operation::GetLk { arg: Self::fetch_lk_in(proto_minor, data)? },
+                fuse_opcode::FUSE_SETLK => Operation::SetLk { arg: Self::fetch_lk_in(proto_minor, data)? },
+                fuse_opcode::FUSE_SETLKW => Operation::SetLkW { arg: Self::fetch_lk_in(proto_minor, data)? },
                 fuse_opcode::FUSE_ACCESS => Operation::Access { arg: data.fetch()? },
                 fuse_opcode::FUSE_CREATE => Operation::Create {
-                    arg: data.fetch()?,
+                    arg: match proto_minor {
+                        0..=11 => data.fetch_compat(FUSE_COMPAT_CREATE_IN_SIZE)?,
+                        _ => data.fetch_compat(mem::size_of::<fuse_create_in>())?,
+                    },
                     name: data.fetch_str()?,
                 },
                 fuse_opcode::FUSE_INTERRUPT => Operation::Interrupt { arg: data.fetch()? },
                 fuse_opcode::FUSE_BMAP => Operation::BMap { arg: data.fetch()? },
                 fuse_opcode::FUSE_DESTROY => Operation::Destroy,
+                fuse_opcode::FUSE_IOCTL => Operation::IoCtl {
+                    arg: data.fetch()?,
+                    data: data.fetch_all(),
+                },
+                fuse_opcode::FUSE_POLL => Operation::Poll { arg: data.fetch()? },
+                fuse_opcode::FUSE_NOTIFY_REPLY => Operation::NotifyReply {
+                    arg: data.fetch()?,
+                    data: data.fetch_all(),
+                },
+                fuse_opcode::FUSE_BATCH_FORGET => {
+                    let arg: &fuse_batch_forget_in = data.fetch()?;
+                    Operation::BatchForget {
+                        arg,
+                        nodes: data.fetch_slice(arg.count as usize)?,
+                    }
+                }
+                fuse_opcode::FUSE_FALLOCATE => Operation::FAllocate { arg: data.fetch()? },
+                fuse_opcode::FUSE_READDIRPLUS => Operation::ReadDirPlus { arg: data.fetch()? },
+                fuse_opcode::FUSE_RENAME2 => Operation::Rename2 {
+                    arg: data.fetch()?,
+                    name: data.fetch_str()?,
+                    newname: data.fetch_str()?,
+                },
+                fuse_opcode::FUSE_LSEEK => Operation::LSeek { arg: data.fetch()? },
+                fuse_opcode::FUSE_COPY_FILE_RANGE => Operation::CopyFileRange { arg: data.fetch()? },
+                fuse_opcode::FUSE_SETUPMAPPING => Operation::SetupMapping { arg: data.fetch()? },
+                fuse_opcode::FUSE_REMOVEMAPPING => {
+                    let arg: &fuse_removemapping_in = data.fetch()?;
+                    Operation::RemoveMapping {
+                        arg,
+                        mappings: data.fetch_slice(arg.count as usize)?,
+                    }
+                }
+                fuse_opcode::FUSE_SYNCFS => Operation::SyncFs,
+                fuse_opcode::FUSE_TMPFILE => Operation::TmpFile {
+                    arg: data.fetch()?,
+                    name: data.fetch_str()?,
+                },
+                fuse_opcode::FUSE_STATX => Operation::StatX { arg: data.fetch()? },
+                fuse_opcode::FUSE_COPY_FILE_RANGE_64 => Operation::CopyFileRange64 { arg: data.fetch()? },
 
                 #[cfg(target_os = "macos")]
                 fuse_opcode::FUSE_SETVOLNAME => Operation::SetVolName {
@@ -340,9 +450,35 @@ impl<'a> Operation<'a> {
                     oldname: data.fetch_str()?,
                     newname: data.fetch_str()?,
                 },
+
+                fuse_opcode::CUSE_INIT => Operation::CuseInit { arg: data.fetch()? },
             })
         }
     }
+
+    /// Fetch the lock argument, which lacks the lock flags before ABI 7.9
+    unsafe fn fetch_lk_in(proto_minor: u32, data: &mut ArgumentIterator<'a>) -> Option<Argument<'a, fuse_lk_in>> {
+        match proto_minor {
+            0..=8 => data.fetch_compat(FUSE_COMPAT_LK_IN_SIZE),
+            _ => data.fetch_compat(mem::size_of::<fuse_lk_in>()),
+        }
+    }
+
+    /// Fetch the setxattr argument, which lacks the setxattr flags unless FUSE_SETXATTR_EXT
+    /// is negotiated
+    #[cfg(not(target_os = "macos"))]
+    unsafe fn fetch_setxattr_in(flags: u64, data: &mut ArgumentIterator<'a>) -> Option<Argument<'a, fuse_setxattr_in>> {
+        match flags & FUSE_SETXATTR_EXT {
+            0 => data.fetch_compat(FUSE_COMPAT_SETXATTR_IN_SIZE),
+            _ => data.fetch_compat(mem::size_of::<fuse_setxattr_in>()),
+        }
+    }
+
+    /// Fetch the setxattr argument
+    #[cfg(target_os = "macos")]
+    unsafe fn fetch_setxattr_in(_flags: u64, data: &mut ArgumentIterator<'a>) -> Option<Argument<'a, fuse_setxattr_in>> {
+        data.fetch().map(Argument::Borrowed)
+    }
 }
 
 
@@ -366,7 +502,26 @@ impl<'a> fmt::Display for Request<'a> {
 impl<'a> TryFrom<&'a [u8]> for Request<'a> {
     type Error = RequestError;
 
+    /// Parse a request that uses the structure layouts of the latest supported ABI version.
     fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::parse(data, FUSE_KERNEL_MINOR_VERSION, 0)
+    }
+}
+
+/// Header of requests that don't come from the kernel driver
+static SYNTHETIC_HEADER: fuse_in_header =
+    fuse_in_header { len: 0, opcode: 0, unique: 0, nodeid: 0, uid: 0, gid: 0, pid: 0, total_extlen: 0, padding: 0 };
+
+impl<'a> Request<'a> {
+    /// Create a request for the given operation that doesn't come from the kernel driver.
+    /// Its header (unique id, node id and caller ids) is all zeros.
+    pub(crate) fn synthetic(operation: Operation<'a>) -> Self {
+        Self { header: &SYNTHETIC_HEADER, operation }
+    }
+
+    /// Parse a request that uses the structure layouts of the given ABI minor version and init
+    /// flags, which are the ones negotiated with the kernel driver during initialization.
+    pub fn parse(data: &'a [u8], proto_minor: u32, flags: u64) -> Result<Self, RequestError> {
         // Parse a raw packet as sent by the kernel driver into typed data. Every request always
         // begins with a `fuse_in_header` struct followed by arguments depending on the opcode.
         let data_len = data.len();
@@ -376,19 +531,18 @@ impl<'a> TryFrom<&'a [u8]> for Request<'a> {
             unsafe { data.fetch() }.ok_or_else(|| RequestError::ShortReadHeader(data.len()))?;
         // Parse/check opcode
         let opcode = fuse_opcode::try_from(header.opcode)
-            .map_err(|_: InvalidOpcodeError| RequestError::UnknownOperation(header.opcode))?;
+            .map_err(|_: InvalidOpcodeError| RequestError::UnknownOperation(header.opcode, header.unique))?;
         // Check data size
         if data_len < header.len as usize {
             return Err(RequestError::ShortRead(data_len, header.len as usize));
         }
         // Parse/check operation arguments
         let operation =
-            Operation::parse(&opcode, &mut data).ok_or_else(|| RequestError::InsufficientData)?;
+            Operation::parse(&opcode, proto_minor, flags, &mut data).ok_or_else(|| RequestError::InsufficientData)?;
         Ok(Self { header, operation })
     }
-}
 
-impl<'a> Request<'a> {
+
     /// Returns the unique identifier of this request.
     ///
     /// The FUSE kernel driver assigns a unique id to every concurrent request. This allows to
@@ -458,7 +612,31 @@ mod tests {
     ];
 
     #[cfg(target_endian = "big")]
-    const MKNOD_REQUEST: [u8; 56] = [
+    const MKNOD_REQUEST: [u8; 64] = [
+        0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x08, // len, opcode
+        0xde, 0xad, 0xbe, 0xef, 0xba, 0xad, 0xd0, 0x0d, // unique
+        0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, // nodeid
+        0xc0, 0x01, 0xd0, 0x0d, 0xc0, 0x01, 0xca, 0xfe, // uid, gid
+        0xc0, 0xde, 0xba, 0x5e, 0x00, 0x00, 0x00, 0x00, // pid, padding
+        0x00, 0x00, 0x01, 0xa4, 0x00, 0x00, 0x00, 0x00, // mode, rdev
+        0x00, 0x00, 0x00, 0x12, 0x00, 0x00, 0x00, 0x00, // umask, padding
+        0x66, 0x6f, 0x6f, 0x2e, 0x74, 0x78, 0x74, 0x00, // name
+    ];
+
+    #[cfg(target_endian = "little")]
+    const MKNOD_REQUEST: [u8; 64] = [
+        0x40, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, // len, opcode
+        0x0d, 0xf0, 0xad, 0xba, 0xef, 0xbe, 0xad, 0xde, // unique
+        0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // nodeid
+        0x0d, 0xd0, 0x01, 0xc0, 0xfe, 0xca, 0x01, 0xc0, // uid, gid
+        0x5e, 0xba, 0xde, 0xc0, 0x00, 0x00, 0x00, 0x00, // pid, padding
+        0xa4, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // mode, rdev
+        0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // umask, padding
+        0x66, 0x6f, 0x6f, 0x2e, 0x74, 0x78, 0x74, 0x00, // name
+    ];
+
+    #[cfg(target_endian = "big")]
+    const MKNOD_COMPAT_REQUEST: [u8; 56] = [
         0x00, 0x00, 0x00, 0x38, 0x00, 0x00, 0x00, 0x08, // len, opcode
         0xde, 0xad, 0xbe, 0xef, 0xba, 0xad, 0xd0, 0x0d, // unique
         0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, // nodeid
@@ -469,7 +647,7 @@ mod tests {
     ];
 
     #[cfg(target_endian = "little")]
-    const MKNOD_REQUEST: [u8; 56] = [
+    const MKNOD_COMPAT_REQUEST: [u8; 56] = [
         0x38, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, // len, opcode
         0x0d, 0xf0, 0xad, 0xba, 0xef, 0xbe, 0xad, 0xde, // unique
         0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // nodeid
@@ -479,6 +657,60 @@ mod tests {
         0x66, 0x6f, 0x6f, 0x2e, 0x74, 0x78, 0x74, 0x00, // name
     ];
 
+    #[cfg(all(target_endian = "big", not(target_os = "macos")))]
+    const SETXATTR_REQUEST: [u8; 56] = [
+        0x00, 0x00, 0x00, 0x38, 0x00, 0x00, 0x00, 0x15, // len, opcode
+        0xde, 0xad, 0xbe, 0xef, 0xba, 0xad, 0xd0, 0x0d, // unique
+        0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, // nodeid
+        0xc0, 0x01, 0xd0, 0x0d, 0xc0, 0x01, 0xca, 0xfe, // uid, gid
+        0xc0, 0xde, 0xba, 0x5e, 0x00, 0x00, 0x00, 0x00, // pid, padding
+        0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, // size, flags
+        0x66, 0x6f, 0x6f, 0x00, 0x62, 0x61, 0x72, 0x00, // name, value
+    ];
+
+    #[cfg(all(target_endian = "little", not(target_os = "macos")))]
+    const SETXATTR_REQUEST: [u8; 56] = [
+        0x38, 0x00, 0x00, 0x00, 0x15, 0x00, 0x00, 0x00, // len, opcode
+        0x0d, 0xf0, 0xad, 0xba, 0xef, 0xbe, 0xad, 0xde, // unique
+        0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // nodeid
+        0x0d, 0xd0, 0x01, 0xc0, 0xfe, 0xca, 0x01, 0xc0, // uid, gid
+        0x5e, 0xba, 0xde, 0xc0, 0x00, 0x00, 0x00, 0x00, // pid, padding
+        0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, // size, flags
+        0x66, 0x6f, 0x6f, 0x00, 0x62, 0x61, 0x72, 0x00, // name, value
+    ];
+
+    /// Wrapper to align request data like the buffer it is read into
+    #[repr(C, align(8))]
+    struct Aligned<T>(T);
+
+    #[cfg(target_endian = "big")]
+    static BATCH_FORGET_REQUEST: Aligned<[u8; 80]> = Aligned([
+        0x00, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x2a, // len, opcode
+        0xde, 0xad, 0xbe, 0xef, 0xba, 0xad, 0xd0, 0x0d, // unique
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // nodeid
+        0xc0, 0x01, 0xd0, 0x0d, 0xc0, 0x01, 0xca, 0xfe, // uid, gid
+        0xc0, 0xde, 0xba, 0x5e, 0x00, 0x00, 0x00, 0x00, // pid, padding
+        0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, // count, dummy
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x11, // nodeid
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // nlookup
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x22, // nodeid
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, // nlookup
+    ]);
+
+    #[cfg(target_endian = "little")]
+    static BATCH_FORGET_REQUEST: Aligned<[u8; 80]> = Aligned([
+        0x50, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, // len, opcode
+        0x0d, 0xf0, 0xad, 0xba, 0xef, 0xbe, 0xad, 0xde, // unique
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // nodeid
+        0x0d, 0xd0, 0x01, 0xc0, 0xfe, 0xca, 0x01, 0xc0, // uid, gid
+        0x5e, 0xba, 0xde, 0xc0, 0x00, 0x00, 0x00, 0x00, // pid, padding
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // count, dummy
+        0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // nodeid
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // nlookup
+        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // nodeid
+        0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // nlookup
+    ]);
+
     #[test]
     fn short_read_header() {
         match Request::try_from(&INIT_REQUEST[..20]) {
@@ -518,7 +750,7 @@ mod tests {
     #[test]
     fn mknod() {
         let req = Request::try_from(&MKNOD_REQUEST[..]).unwrap();
-        assert_eq!(req.header.len, 56);
+        assert_eq!(req.header.len, 64);
         assert_eq!(req.header.opcode, 8);
         assert_eq!(req.unique(), 0xdead_beef_baad_f00d);
         assert_eq!(req.nodeid(), 0x1122_3344_5566_7788);
@@ -528,9 +760,58 @@ mod tests {
         match req.operation() {
             Operation::MkNod { arg, name } => {
                 assert_eq!(arg.mode, 0o644);
+                assert_eq!(arg.umask, 0o022);
                 assert_eq!(*name, "foo.txt");
             }
             _ => panic!("Unexpected request operation"),
         }
     }
+
+    #[test]
+    fn mknod_compat() {
+        let req = Request::parse(&MKNOD_COMPAT_REQUEST[..], 11, 0).unwrap();
+        assert_eq!(req.header.len, 56);
+        assert_eq!(req.header.opcode, 8);
+        match req.operation() {
+            Operation::MkNod { arg, name } => {
+                assert_eq!(arg.mode, 0o644);
+                assert_eq!(arg.umask, 0);
+                assert_eq!(*name, "foo.txt");
+            }
+            _ => panic!("Unexpected request operation"),
+        }
+        // The same data is too short for a request of a later ABI version
+        assert!(Request::parse(&MKNOD_COMPAT_REQUEST[..], 12, 0).is_err());
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn setxattr() {
+        // Without FUSE_SETXATTR_EXT, the kernel sends the short argument
+        let req = Request::parse(&SETXATTR_REQUEST[..], FUSE_KERNEL_MINOR_VERSION, 0).unwrap();
+        match req.operation() {
+            Operation::SetXAttr { arg, name, value } => {
+                assert_eq!(arg.size, 4);
+                assert_eq!(arg.flags, 1);
+                assert_eq!(arg.setxattr_flags, 0);
+                assert_eq!(*name, "foo");
+                assert_eq!(*value, b"bar\0");
+            }
+            _ => panic!("Unexpected request operation"),
+        }
+    }
+
+    #[test]
+    fn batch_forget() {
+        let req = Request::try_from(&BATCH_FORGET_REQUEST.0[..]).unwrap();
+        match req.operation() {
+            Operation::BatchForget { arg, nodes } => {
+                assert_eq!(arg.count, 2);
+                assert_eq!(nodes.len(), 2);
+                assert_eq!((nodes[0].nodeid, nodes[0].nlookup), (0x11, 1));
+                assert_eq!((nodes[1].nodeid, nodes[1].nlookup), (0x22, 3));
+            }
+            _ => panic!("Unexpected request operation"),
+        }
+    }
 }
@@ -0,0 +1,316 @@
+//! Kernel notifications
+//!
+//! Besides replying to requests, the filesystem can send unsolicited notifications to the
+//! kernel driver, e.g. to invalidate cached data and directory entries that changed without
+//! the kernel knowing about it (like changes made on a network filesystem's server), or to
+//! store data to or retrieve data from the kernel's page cache.
+
+use fuse_abi::{fuse_notify_code, fuse_notify_delete_out, fuse_notify_inval_entry_out};
+use fuse_abi::{fuse_notify_inval_inode_out, fuse_notify_retrieve_out, fuse_notify_store_out};
+use fuse_abi::fuse_notify_poll_wakeup_out;
+use fuse_abi::fuse_out_header;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::sync::{Arc, Mutex};
+use std::{fmt, io, mem, slice};
+
+use crate::channel::SharedSender;
+
+/// Serialize an arbitrary type to bytes (memory copy, useful for fuse_*_out types)
+fn as_bytes<T>(data: &T) -> &[u8] {
+    let p = data as *const T as *const u8;
+    unsafe { slice::from_raw_parts(p, mem::size_of::<T>()) }
+}
+
+/// Sends notifications to the kernel driver.
+///
+/// A notifier can be cloned and sent to other threads. It can be used while the session is
+/// running, even while a request is processed. Once the session ended and the channel to the
+/// kernel driver is closed, notifications fail with `ENODEV`.
+#[derive(Clone, Debug)]
+pub struct Notifier {
+    sender: SharedSender,
+    retrievals: Arc<Retrievals>,
+}
+
+impl Notifier {
+    /// Create a new notifier that sends to the given channel
+    pub(crate) fn new(sender: SharedSender, retrievals: Arc<Retrievals>) -> Notifier {
+        Notifier { sender, retrievals }
+    }
+
+    /// Invalidate cached attributes and data of the given inode. Data is invalidated from the
+    /// given offset on for the given length, or up to the end of the file if the length is
+    /// zero or negative. If the offset is negative, only the attributes are invalidated.
+    pub fn inval_inode(&self, ino: u64, offset: i64, len: i64) -> io::Result<()> {
+        let arg = fuse_notify_inval_inode_out { ino, off: offset, len };
+        self.send(fuse_notify_code::FUSE_NOTIFY_INVAL_INODE, &[as_bytes(&arg)])
+    }
+
+    /// Invalidate the cached directory entry with the given name in the given parent
+    /// directory, and the attributes of the parent directory.
+    pub fn inval_entry(&self, parent: u64, name: &OsStr) -> io::Result<()> {
+        let name = name.as_bytes();
+        let arg = fuse_notify_inval_entry_out { parent, namelen: name.len() as u32, flags: 0 };
+        self.send(fuse_notify_code::FUSE_NOTIFY_INVAL_ENTRY, &[as_bytes(&arg), name, &[0]])
+    }
+
+    /// Notify the kernel that the given child was deleted from the given parent directory.
+    /// Like `inval_entry`, but if the child's inode is cached, it is also removed from any
+    /// directory listing (e.g. an inotify watch gets notified). The kernel rejects the
+    /// notification with an error if the cached entry doesn't refer to the given child.
+    pub fn delete(&self, parent: u64, child: u64, name: &OsStr) -> io::Result<()> {
+        let name = name.as_bytes();
+        let arg = fuse_notify_delete_out { parent, child, namelen: name.len() as u32, padding: 0 };
+        self.send(fuse_notify_code::FUSE_NOTIFY_DELETE, &[as_bytes(&arg), name, &[0]])
+    }
+
+    /// Notify the kernel that the file with the given kernel poll handle is ready, which
+    /// wakes up processes polling on it
+    fn poll_wakeup(&self, kh: u64) -> io::Result<()> {
+        let arg = fuse_notify_poll_wakeup_out { kh };
+        self.send(fuse_notify_code::FUSE_POLL, &[as_bytes(&arg)])
+    }
+
+    /// Store the given data in the kernel's page cache of the given inode, starting at the
+    /// given offset. If the data extends beyond the cached file size, the file size is
+    /// updated. The kernel rejects the notification with `ENOENT` if the inode isn't cached.
+    pub fn store(&self, ino: u64, offset: u64, data: &[u8]) -> io::Result<()> {
+        let arg = fuse_notify_store_out { nodeid: ino, offset, size: data.len() as u32, padding: 0 };
+        self.send(fuse_notify_code::FUSE_NOTIFY_STORE, &[as_bytes(&arg), data])
+    }
+
+    /// Retrieve up to the given number of bytes from the kernel's page cache of the given
+    /// inode, starting at the given offset. The kernel replies asynchronously with the cached
+    /// data, which is then passed to the given callback together with its offset. The data
+    /// may be shorter than requested (or empty) if less is cached. The callback is called by
+    /// the session loop, so it must not block on requests to the filesystem. It's never called
+    /// if sending the notification fails, e.g. with `ENOENT` if the inode isn't cached.
+    pub fn retrieve<F>(&self, ino: u64, offset: u64, size: u32, callback: F) -> io::Result<()>
+    where
+        F: FnOnce(u64, &[u8]) + Send + 'static,
+    {
+        let notify_unique = self.retrievals.register(Box::new(callback));
+        let arg = fuse_notify_retrieve_out { notify_unique, nodeid: ino, offset, size, padding: 0 };
+        self.send(fuse_notify_code::FUSE_NOTIFY_RETRIEVE, &[as_bytes(&arg)]).inspect_err(|_| {
+            self.retrievals.remove(notify_unique);
+        })
+    }
+
+    /// Send a notification with the given code and data. Notifications look like replies
+    /// with a zero unique id and the notification code in the error field.
+    fn send(&self, code: fuse_notify_code, data: &[&[u8]]) -> io::Result<()> {
+        let len = data.iter().fold(0, |l, b| l + b.len());
+        let header = fuse_out_header {
+            len: (mem::size_of::<fuse_out_header>() + len) as u32,
+            error: code as i32,
+            unique: 0,
+        };
+        let mut bytes = vec![as_bytes(&header)];
+        bytes.extend(data);
+        self.sender.send(&bytes)
+    }
+}
+
+/// Handle to wake up processes polling on a file.
+///
+/// A poll handle is passed to `Filesystem::poll` if the kernel asks to be notified when the
+/// file becomes ready. It can be stored and sent to other threads to fire the wakeup later,
+/// until the session ends (it fails with `ENODEV` afterwards). A later poll request on the
+/// same file replaces the handle, and the kernel ignores notifications for handles it doesn't
+/// know anymore.
+#[derive(Clone, Debug)]
+pub struct PollHandle {
+    kh: u64,
+    notifier: Notifier,
+}
+
+impl PollHandle {
+    /// Create a new handle for the given kernel poll handle
+    pub(crate) fn new(kh: u64, notifier: Notifier) -> PollHandle {
+        PollHandle { kh, notifier }
+    }
+
+    /// Notify the kernel that the file is ready, which wakes up processes polling on it
+    pub fn notify(&self) -> io::Result<()> {
+        self.notifier.poll_wakeup(self.kh)
+    }
+}
+
+/// Callback that receives the offset and data of a retrieve notification reply
+type RetrieveCallback = Box<dyn FnOnce(u64, &[u8]) + Send>;
+
+/// Retrieve notifications of a session that haven't been replied to yet
+#[derive(Default)]
+pub(crate) struct Retrievals {
+    table: Mutex<RetrieveTable>,
+}
+
+#[derive(Default)]
+struct RetrieveTable {
+    /// Last unique id handed out to a retrieve notification
+    last_unique: u64,
+    /// Callbacks of pending retrieve notifications by their unique id
+    callbacks: HashMap<u64, RetrieveCallback>,
+}
+
+impl Retrievals {
+    /// Keep the given callback until the reply arrives and return the unique id to send
+    /// with the retrieve notification
+    fn register(&self, callback: RetrieveCallback) -> u64 {
+        let mut table = self.table.lock().unwrap();
+        table.last_unique += 1;
+        let unique = table.last_unique;
+        table.callbacks.insert(unique, callback);
+        unique
+    }
+
+    /// Forget the callback of the retrieve notification with the given unique id
+    fn remove(&self, unique: u64) -> Option<RetrieveCallback> {
+        self.table.lock().unwrap().callbacks.remove(&unique)
+    }
+
+    /// Pass the data of a retrieve notification reply to its callback. Returns false if
+    /// the unique id is unknown.
+    pub(crate) fn complete(&self, unique: u64, offset: u64, data: &[u8]) -> bool {
+        // Don't hold the lock while calling, so that the callback can send notifications
+        match self.remove(unique) {
+            Some(callback) => {
+                callback(offset, data);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// replace with #[derive(Debug)] if Debug ever gets implemented for closures
+impl fmt::Debug for Retrievals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "Retrievals {{ pending: {} }}", self.table.lock().unwrap().callbacks.len())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{Notifier, PollHandle, Retrievals};
+    use crate::channel::{Channel, SharedSender};
+    use std::ffi::OsStr;
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::{mpsc, Arc};
+
+    /// Run the given function with a notifier and return the bytes it sent
+    fn notify<F: FnOnce(&Notifier)>(f: F) -> Vec<u8> {
+        notify_with(Arc::default(), f)
+    }
+
+    /// Run the given function with a notifier using the given retrievals and return the bytes
+    /// it sent
+    fn notify_with<F: FnOnce(&Notifier)>(retrievals: Arc<Retrievals>, f: F) -> Vec<u8> {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (mut reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        f(&Notifier::new(SharedSender::from_raw_fd(fds[1]), retrievals));
+        drop(writer);
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn inval_inode() {
+        let bytes = notify(|n| n.inval_inode(0x1122, 0x100, -1).unwrap());
+        assert_eq!(bytes, vec![
+            0x28, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x22, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ]);
+    }
+
+    #[test]
+    fn inval_entry() {
+        let bytes = notify(|n| n.inval_entry(0x1122, OsStr::new("foo")).unwrap());
+        assert_eq!(bytes, vec![
+            0x24, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x22, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x66, 0x6f, 0x6f, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn delete() {
+        let bytes = notify(|n| n.delete(0x1122, 0x3344, OsStr::new("foo")).unwrap());
+        assert_eq!(bytes, vec![
+            0x2c, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x22, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x44, 0x33, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x6f,
+            0x6f, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn poll_wakeup() {
+        let bytes = notify(|n| PollHandle::new(0x1122, n.clone()).notify().unwrap());
+        assert_eq!(bytes, vec![
+            0x18, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x22, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn store() {
+        let bytes = notify(|n| n.store(0x1122, 0x100, &[0x11, 0x22, 0x33]).unwrap());
+        assert_eq!(bytes, vec![
+            0x2b, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x22, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x11, 0x22,
+            0x33,
+        ]);
+    }
+
+    #[test]
+    fn retrieve() {
+        let retrievals = Arc::new(Retrievals::default());
+        let (tx, rx) = mpsc::channel();
+        let bytes = notify_with(retrievals.clone(), |n| {
+            n.retrieve(0x1122, 0x100, 0x200, move |offset, data| tx.send((offset, data.to_vec())).unwrap()).unwrap();
+        });
+        assert_eq!(bytes, vec![
+            0x30, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x22, 0x11, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        assert!(!retrievals.complete(2, 0x100, &[]));
+        assert!(retrievals.complete(1, 0x100, &[0x11, 0x22]));
+        assert_eq!(rx.try_recv(), Ok((0x100, vec![0x11, 0x22])));
+        assert!(!retrievals.complete(1, 0x100, &[]));
+    }
+
+    #[test]
+    fn notify_after_unmount() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let reader = unsafe { File::from_raw_fd(fds[0]) };
+        let mut ch = Channel::from_raw_fd(fds[1]);
+        let notifier = Notifier::new(ch.shared_sender(), Arc::default());
+        let handle = PollHandle::new(0x1122, notifier.clone());
+        ch.unmount().unwrap();
+        // The closed fd number is likely reused, but nothing must be written to it
+        let mut other = [0; 2];
+        assert_eq!(unsafe { libc::pipe(other.as_mut_ptr()) }, 0);
+        let (mut other_reader, other_writer) = unsafe { (File::from_raw_fd(other[0]), File::from_raw_fd(other[1])) };
+        assert_eq!(notifier.inval_inode(0x1122, 0, 0).unwrap_err().raw_os_error(), Some(libc::ENODEV));
+        assert_eq!(notifier.store(0x1122, 0, &[0x11]).unwrap_err().raw_os_error(), Some(libc::ENODEV));
+        assert_eq!(handle.notify().unwrap_err().raw_os_error(), Some(libc::ENODEV));
+        drop(other_writer);
+        let mut bytes = Vec::new();
+        other_reader.read_to_end(&mut bytes).unwrap();
+        assert!(bytes.is_empty());
+        drop(reader);
+    }
+}
@@ -0,0 +1,147 @@
+//! Session readiness
+//!
+//! A mounted filesystem becomes usable once the session handled the init request of the
+//! kernel driver. The readiness of a session tells whether this happened, so that callers
+//! can wait for it before accessing the mount point.
+
+use libc::c_int;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+/// Readiness of a session, shared between the session and its handles
+#[derive(Debug, Default)]
+pub(crate) struct Readiness {
+    state: Mutex<ReadyState>,
+    cond: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct ReadyState {
+    /// Result of the init request (the error code if it was rejected), none while pending
+    result: Option<Result<(), c_int>>,
+    /// Tasks waiting for the init request to be handled
+    wakers: Vec<Waker>,
+}
+
+impl Readiness {
+    /// Set the result of the init request and wake up all waiters. Only the first result
+    /// counts, later ones are ignored.
+    pub(crate) fn set(&self, result: Result<(), c_int>) {
+        let mut state = self.state.lock().unwrap();
+        if state.result.is_none() {
+            state.result = Some(result);
+            for waker in state.wakers.drain(..) {
+                waker.wake();
+            }
+            self.cond.notify_all();
+        }
+    }
+
+    /// Block until the init request is handled or the given timeout elapsed
+    pub(crate) fn wait_timeout(&self, timeout: Duration) -> io::Result<()> {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self.cond.wait_timeout_while(state, timeout, |s| s.result.is_none()).unwrap();
+        match state.result {
+            Some(result) => into_io_result(result),
+            None => Err(io::Error::new(io::ErrorKind::TimedOut, "filesystem not initialized in time")),
+        }
+    }
+
+    /// Check if the init request is handled, otherwise wake up the given task once it is
+    fn poll(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.state.lock().unwrap();
+        match state.result {
+            Some(result) => Poll::Ready(into_io_result(result)),
+            None => {
+                if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    state.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+fn into_io_result(result: Result<(), c_int>) -> io::Result<()> {
+    result.map_err(io::Error::from_raw_os_error)
+}
+
+/// Future that completes once a session handled the init request (see
+/// `BackgroundSession::ready`)
+#[derive(Debug)]
+pub struct WaitReady<'a> {
+    readiness: &'a Readiness,
+}
+
+impl<'a> WaitReady<'a> {
+    pub(crate) fn new(readiness: &'a Readiness) -> WaitReady<'a> {
+        WaitReady { readiness }
+    }
+}
+
+impl Future for WaitReady<'_> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.readiness.poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Readiness, WaitReady};
+    use libc::EPROTO;
+    use std::future::Future;
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn wait_for_init() {
+        let readiness = Arc::new(Readiness::default());
+        let err = readiness.wait_timeout(Duration::from_millis(1)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        let r = readiness.clone();
+        thread::spawn(move || r.set(Ok(())));
+        readiness.wait_timeout(Duration::from_secs(10)).unwrap();
+        // Later results are ignored
+        readiness.set(Err(EPROTO));
+        readiness.wait_timeout(Duration::from_millis(1)).unwrap();
+    }
+
+    #[test]
+    fn init_rejected() {
+        let readiness = Readiness::default();
+        readiness.set(Err(EPROTO));
+        assert_eq!(readiness.wait_timeout(Duration::from_secs(10)).unwrap_err().raw_os_error(), Some(EPROTO));
+    }
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn wake_waiting_task() {
+        let readiness = Readiness::default();
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut future = WaitReady::new(&readiness);
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        readiness.set(Ok(()));
+        assert!(flag.0.load(Ordering::SeqCst));
+        assert!(matches!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(Ok(()))));
+    }
+}
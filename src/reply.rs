@@ -6,28 +6,50 @@
 //! data without cloning the data. A reply *must always* be used (by calling either ok() or
 //! error() exactly once).
 
-use fuse_abi::fuse_getxattr_out;
+use fuse_abi::consts::{FUSE_COMPAT_ATTR_OUT_SIZE, FUSE_COMPAT_ENTRY_OUT_SIZE};
+use fuse_abi::consts::FUSE_IOCTL_RETRY;
+use fuse_abi::{fuse_getxattr_out, fuse_ioctl_iovec, fuse_ioctl_out, fuse_lseek_out, fuse_poll_out};
 #[cfg(target_os = "macos")]
 use fuse_abi::fuse_getxtimes_out;
 use fuse_abi::{fuse_attr, fuse_attr_out, fuse_entry_out, fuse_file_lock, fuse_kstatfs};
 use fuse_abi::{fuse_bmap_out, fuse_lk_out, fuse_open_out, fuse_statfs_out, fuse_write_out};
-use fuse_abi::{fuse_dirent, fuse_out_header};
-use libc::{c_int, EIO, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFREG, S_IFSOCK};
-use log::warn;
+use fuse_abi::{fuse_dirent, fuse_direntplus, fuse_out_header, FUSE_KERNEL_MINOR_VERSION};
+use libc::{c_int, c_void, iovec, EIO, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFREG, S_IFSOCK};
+use log::{error, warn};
 use std::convert::AsRef;
 use std::ffi::OsStr;
 use std::fmt;
+use std::io;
+use std::os::unix::io::RawFd;
 use std::marker::PhantomData;
 use std::os::unix::ffi::OsStrExt;
 use std::time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH};
-use std::{mem, ptr, slice};
+use std::{cmp, mem, ptr, slice};
 
+use crate::buffer::BufferPool;
+use crate::channel::ChannelSender;
+use crate::interrupt::TrackingSender;
 use crate::{FileAttr, FileType};
 
+/// Number of slices (including the header) a reply can be sent from without allocating.
+/// Only vectored data replies can consist of more slices.
+const INLINE_SLICES: usize = 8;
+
+/// Buffers for reading file data into if a reply can't be spliced
+static FILE_BUFFERS: BufferPool = BufferPool::new(16 * 1024 * 1024);
+
 /// Generic reply callback to send data
 pub trait ReplySender: Send + 'static {
     /// Send data.
     fn send(&self, data: &[&[u8]]);
+
+    /// Send the given reply header followed by `len` bytes of the file `fd` at `offset`
+    /// without copying the data through userspace (or less data at the end of the file, in
+    /// which case the length in the header is reduced). Returns `Ok(false)` if the sender
+    /// can't splice the reply, without sending anything.
+    fn send_spliced(&self, _header: &[u8], _fd: RawFd, _offset: i64, _len: usize) -> io::Result<bool> {
+        Ok(false)
+    }
 }
 
 impl fmt::Debug for Box<dyn ReplySender> {
@@ -36,10 +58,64 @@ impl fmt::Debug for Box<dyn ReplySender> {
     }
 }
 
+/// Sender stored in a reply. The sender of requests received by a session is stored inline,
+/// any other sender is boxed.
+#[derive(Debug)]
+enum StoredSender {
+    Session(TrackingSender<ChannelSender>),
+    Boxed(Box<dyn ReplySender>),
+}
+
+impl ReplySender for StoredSender {
+    fn send(&self, data: &[&[u8]]) {
+        match self {
+            StoredSender::Session(sender) => sender.send(data),
+            StoredSender::Boxed(sender) => sender.send(data),
+        }
+    }
+
+    fn send_spliced(&self, header: &[u8], fd: RawFd, offset: i64, len: usize) -> io::Result<bool> {
+        match self {
+            StoredSender::Session(sender) => sender.send_spliced(header, fd, offset, len),
+            StoredSender::Boxed(sender) => sender.send_spliced(header, fd, offset, len),
+        }
+    }
+}
+
 /// Generic reply trait
 pub trait Reply {
     /// Create a new reply for the given request
     fn new<S: ReplySender>(unique: u64, sender: S) -> Self;
+
+    /// Create a new reply for the given request that is encoded for the given FUSE protocol
+    /// minor version. Most replies look the same for all supported versions.
+    fn with_proto_minor<S: ReplySender>(unique: u64, sender: S, _proto_minor: u32) -> Self
+    where
+        Self: Sized,
+    {
+        Self::new(unique, sender)
+    }
+}
+
+/// Replies to requests received by a session, which store the session's sender inline
+/// instead of boxing it like `Reply::new` does with any other sender
+pub(crate) trait SessionReply: Reply {
+    /// Create a new reply for a request received by a session that is encoded for the given
+    /// FUSE protocol minor version
+    fn from_session(unique: u64, sender: TrackingSender<ChannelSender>, proto_minor: u32) -> Self;
+}
+
+/// Implement `SessionReply` for replies that only wrap a raw reply
+macro_rules! session_reply {
+    ($($reply:ident),*) => {
+        $(
+            impl SessionReply for $reply {
+                fn from_session(unique: u64, sender: TrackingSender<ChannelSender>, proto_minor: u32) -> $reply {
+                    $reply { reply: ReplyRaw::from_session(unique, sender, proto_minor) }
+                }
+            }
+        )*
+    };
 }
 
 /// Serialize an arbitrary type to bytes (memory copy, useful for fuse_*_out types)
@@ -55,6 +131,30 @@ fn as_bytes<T, U, F: FnOnce(&[&[u8]]) -> U>(data: &T, f: F) -> U {
     }
 }
 
+/// Serialize the first `size` bytes of an arbitrary type (memory copy, useful for sending
+/// fuse_*_out types to kernels that expect a shorter version of the type)
+fn as_compat_bytes<T>(data: &T, size: usize) -> &[u8] {
+    let len = cmp::min(size, mem::size_of::<T>());
+    let p = data as *const T as *const u8;
+    unsafe { slice::from_raw_parts(p, len) }
+}
+
+/// Returns the size of fuse_entry_out for the given FUSE protocol minor version
+fn entry_out_size(proto_minor: u32) -> usize {
+    match proto_minor {
+        0..=8 => FUSE_COMPAT_ENTRY_OUT_SIZE,
+        _ => mem::size_of::<fuse_entry_out>(),
+    }
+}
+
+/// Returns the size of fuse_attr_out for the given FUSE protocol minor version
+fn attr_out_size(proto_minor: u32) -> usize {
+    match proto_minor {
+        0..=8 => FUSE_COMPAT_ATTR_OUT_SIZE,
+        _ => mem::size_of::<fuse_attr_out>(),
+    }
+}
+
 fn time_from_system_time(system_time: &SystemTime) -> Result<(u64, u32), SystemTimeError> {
     let duration = system_time.duration_since(UNIX_EPOCH)?;
     Ok((duration.as_secs(), duration.subsec_nanos()))
@@ -131,7 +231,7 @@ fn fuse_attr_from_attr(attr: &FileAttr) -> fuse_attr {
         gid: attr.gid,
         rdev: attr.rdev,
         blksize: 0, // TODO: fix this
-        padding: 0, // TODO: fix this too
+        flags: 0,
     }
 }
 
@@ -142,8 +242,10 @@ fn fuse_attr_from_attr(attr: &FileAttr) -> fuse_attr {
 pub struct ReplyRaw<T> {
     /// Unique id of the request to reply to
     unique: u64,
+    /// FUSE protocol minor version to encode the reply for
+    proto_minor: u32,
     /// Closure to call for sending the reply
-    sender: Option<Box<dyn ReplySender>>,
+    sender: Option<StoredSender>,
     /// Marker for being able to have T on this struct (which enforces
     /// reply types to send the correct type of data)
     marker: PhantomData<T>,
@@ -151,10 +253,25 @@ pub struct ReplyRaw<T> {
 
 impl<T> Reply for ReplyRaw<T> {
     fn new<S: ReplySender>(unique: u64, sender: S) -> ReplyRaw<T> {
-        let sender = Box::new(sender);
+        Reply::with_proto_minor(unique, sender, FUSE_KERNEL_MINOR_VERSION)
+    }
+
+    fn with_proto_minor<S: ReplySender>(unique: u64, sender: S, proto_minor: u32) -> ReplyRaw<T> {
         ReplyRaw {
             unique: unique,
-            sender: Some(sender),
+            proto_minor,
+            sender: Some(StoredSender::Boxed(Box::new(sender))),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> SessionReply for ReplyRaw<T> {
+    fn from_session(unique: u64, sender: TrackingSender<ChannelSender>, proto_minor: u32) -> ReplyRaw<T> {
+        ReplyRaw {
+            unique,
+            proto_minor,
+            sender: Some(StoredSender::Session(sender)),
             marker: PhantomData,
         }
     }
@@ -173,9 +290,19 @@ impl<T> ReplyRaw<T> {
         };
         as_bytes(&header, |headerbytes| {
             let sender = self.sender.take().unwrap();
-            let mut sendbytes = headerbytes.to_vec();
-            sendbytes.extend(bytes);
-            sender.send(&sendbytes);
+            if bytes.len() < INLINE_SLICES {
+                // Common case: collect the slices on the stack
+                let mut sendbytes: [&[u8]; INLINE_SLICES] = [&[]; INLINE_SLICES];
+                sendbytes[0] = headerbytes[0];
+                sendbytes[1..=bytes.len()].copy_from_slice(bytes);
+                sender.send(&sendbytes[..=bytes.len()]);
+            } else {
+                // Replies of more slices (only `data_vectored` with many chunks) collect
+                // them in a vector, which allocates
+                let mut sendbytes = headerbytes.to_vec();
+                sendbytes.extend(bytes);
+                sender.send(&sendbytes);
+            }
         });
     }
 
@@ -186,12 +313,69 @@ impl<T> ReplyRaw<T> {
         })
     }
 
+    /// Reply to a request with the given type, truncated to the given size for kernels
+    /// that expect the shorter variant of an older ABI version
+    pub(crate) fn ok_compat(mut self, data: &T, size: usize) {
+        self.send(0, &[as_compat_bytes(data, size)]);
+    }
+
+    /// Reply to a request with the given type followed by the given data
+    pub(crate) fn ok_with_data(mut self, data: &T, extra: &[u8]) {
+        self.send(0, &[as_compat_bytes(data, mem::size_of::<T>()), extra]);
+    }
+
+    /// Reply to a request with `len` bytes of the given file at the given offset, or less at
+    /// the end of the file. The data is spliced if the sender supports it, otherwise it's
+    /// read into a pooled buffer.
+    fn send_file(&mut self, fd: RawFd, offset: i64, len: usize) {
+        let header = fuse_out_header {
+            len: (mem::size_of::<fuse_out_header>() + len) as u32,
+            error: 0,
+            unique: self.unique,
+        };
+        let sender = self.sender.as_ref().unwrap();
+        match as_bytes(&header, |headerbytes| sender.send_spliced(headerbytes[0], fd, offset, len)) {
+            Ok(true) => {
+                self.sender = None;
+            }
+            Ok(false) => {
+                let mut buffer = FILE_BUFFERS.take(len);
+                match read_file(fd, offset, len, &mut buffer) {
+                    Ok(()) => self.send(0, &[&buffer]),
+                    Err(err) => self.send(err.raw_os_error().unwrap_or(EIO), &[]),
+                }
+            }
+            Err(err) => {
+                error!("Failed to splice reply data: {}", err);
+                self.send(EIO, &[]);
+            }
+        }
+    }
+
     /// Reply to a request with the given error code
     pub fn error(mut self, err: c_int) {
         self.send(err, &[]);
     }
 }
 
+/// Read up to `len` bytes of the given file at the given offset into the given empty buffer
+/// (less at the end of the file)
+fn read_file(fd: RawFd, offset: i64, len: usize, data: &mut Vec<u8>) -> io::Result<()> {
+    data.reserve(len);
+    while data.len() < len {
+        let rc = unsafe {
+            libc::pread(fd, data.as_mut_ptr().add(data.len()) as *mut c_void, len - data.len(), offset + data.len() as i64)
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        } else if rc == 0 {
+            break;
+        }
+        unsafe { data.set_len(data.len() + rc as usize); }
+    }
+    Ok(())
+}
+
 impl<T> Drop for ReplyRaw<T> {
     fn drop(&mut self) {
         if self.sender.is_some() {
@@ -254,6 +438,21 @@ impl ReplyData {
         self.reply.send(0, &[data]);
     }
 
+    /// Reply to a request with the concatenation of the given chunks of data. The chunks are
+    /// sent in a single vectored write without joining them first.
+    pub fn data_vectored(mut self, data: &[&[u8]]) {
+        self.reply.send(0, data);
+    }
+
+    /// Reply to a request with `len` bytes of the given file at the given offset, or less at
+    /// the end of the file. If FUSE_SPLICE_WRITE is negotiated (Linux only), the data is
+    /// spliced from the file into the channel without copying it through userspace.
+    /// Otherwise, it's read into a reused buffer and sent from there. The data is sent
+    /// before this method returns, so the file only needs to stay open until then.
+    pub fn from_fd(mut self, fd: RawFd, offset: i64, len: usize) {
+        self.reply.send_file(fd, offset, len);
+    }
+
     /// Reply to a request with the given error code
     pub fn error(self, err: c_int) {
         self.reply.error(err);
@@ -274,12 +473,18 @@ impl Reply for ReplyEntry {
             reply: Reply::new(unique, sender),
         }
     }
+
+    fn with_proto_minor<S: ReplySender>(unique: u64, sender: S, proto_minor: u32) -> ReplyEntry {
+        ReplyEntry {
+            reply: Reply::with_proto_minor(unique, sender, proto_minor),
+        }
+    }
 }
 
 impl ReplyEntry {
     /// Reply to a request with the given entry
-    pub fn entry(self, ttl: &Duration, attr: &FileAttr, generation: u64) {
-        self.reply.ok(&fuse_entry_out {
+    pub fn entry(mut self, ttl: &Duration, attr: &FileAttr, generation: u64) {
+        let entry = fuse_entry_out {
             nodeid: attr.ino,
             generation: generation,
             entry_valid: ttl.as_secs(),
@@ -287,7 +492,9 @@ impl ReplyEntry {
             entry_valid_nsec: ttl.subsec_nanos(),
             attr_valid_nsec: ttl.subsec_nanos(),
             attr: fuse_attr_from_attr(attr),
-        });
+        };
+        let size = entry_out_size(self.reply.proto_minor);
+        self.reply.send(0, &[as_compat_bytes(&entry, size)]);
     }
 
     /// Reply to a request with the given error code
@@ -310,17 +517,25 @@ impl Reply for ReplyAttr {
             reply: Reply::new(unique, sender),
         }
     }
+
+    fn with_proto_minor<S: ReplySender>(unique: u64, sender: S, proto_minor: u32) -> ReplyAttr {
+        ReplyAttr {
+            reply: Reply::with_proto_minor(unique, sender, proto_minor),
+        }
+    }
 }
 
 impl ReplyAttr {
     /// Reply to a request with the given attribute
-    pub fn attr(self, ttl: &Duration, attr: &FileAttr) {
-        self.reply.ok(&fuse_attr_out {
+    pub fn attr(mut self, ttl: &Duration, attr: &FileAttr) {
+        let attr = fuse_attr_out {
             attr_valid: ttl.as_secs(),
             attr_valid_nsec: ttl.subsec_nanos(),
             dummy: 0,
             attr: fuse_attr_from_attr(attr),
-        });
+        };
+        let size = attr_out_size(self.reply.proto_minor);
+        self.reply.send(0, &[as_compat_bytes(&attr, size)]);
     }
 
     /// Reply to a request with the given error code
@@ -390,7 +605,7 @@ impl ReplyOpen {
         self.reply.ok(&fuse_open_out {
             fh: fh,
             open_flags: flags,
-            padding: 0,
+            backing_id: 0,
         });
     }
 
@@ -496,27 +711,39 @@ impl Reply for ReplyCreate {
             reply: Reply::new(unique, sender),
         }
     }
+
+    fn with_proto_minor<S: ReplySender>(unique: u64, sender: S, proto_minor: u32) -> ReplyCreate {
+        ReplyCreate {
+            reply: Reply::with_proto_minor(unique, sender, proto_minor),
+        }
+    }
 }
 
 impl ReplyCreate {
     /// Reply to a request with the given entry
-    pub fn created(self, ttl: &Duration, attr: &FileAttr, generation: u64, fh: u64, flags: u32) {
-        self.reply.ok(&(
-            fuse_entry_out {
-                nodeid: attr.ino,
-                generation: generation,
-                entry_valid: ttl.as_secs(),
-                attr_valid: ttl.as_secs(),
-                entry_valid_nsec: ttl.subsec_nanos(),
-                attr_valid_nsec: ttl.subsec_nanos(),
-                attr: fuse_attr_from_attr(attr),
-            },
-            fuse_open_out {
-                fh: fh,
-                open_flags: flags,
-                padding: 0,
-            },
-        ));
+    pub fn created(mut self, ttl: &Duration, attr: &FileAttr, generation: u64, fh: u64, flags: u32) {
+        let entry = fuse_entry_out {
+            nodeid: attr.ino,
+            generation: generation,
+            entry_valid: ttl.as_secs(),
+            attr_valid: ttl.as_secs(),
+            entry_valid_nsec: ttl.subsec_nanos(),
+            attr_valid_nsec: ttl.subsec_nanos(),
+            attr: fuse_attr_from_attr(attr),
+        };
+        let open = fuse_open_out {
+            fh: fh,
+            open_flags: flags,
+            backing_id: 0,
+        };
+        let size = entry_out_size(self.reply.proto_minor);
+        if size == mem::size_of::<fuse_entry_out>() {
+            self.reply.ok(&(entry, open));
+        } else {
+            let entrybytes = as_compat_bytes(&entry, size);
+            let openbytes = as_compat_bytes(&open, mem::size_of::<fuse_open_out>());
+            self.reply.send(0, &[entrybytes, openbytes]);
+        }
     }
 
     /// Reply to a request with the given error code
@@ -606,6 +833,15 @@ impl ReplyDirectory {
         }
     }
 
+    /// Creates a new ReplyDirectory with a specified buffer size for a request received by a
+    /// session
+    pub(crate) fn from_session(unique: u64, sender: TrackingSender<ChannelSender>, size: usize) -> ReplyDirectory {
+        ReplyDirectory {
+            reply: ReplyRaw::from_session(unique, sender, FUSE_KERNEL_MINOR_VERSION),
+            data: Vec::with_capacity(size),
+        }
+    }
+
     /// Add an entry to the directory reply buffer. Returns true if the buffer is full.
     /// A transparent offset value can be provided for each entry. The kernel uses these
     /// value to request the next entries in further readdir calls
@@ -645,6 +881,82 @@ impl ReplyDirectory {
     }
 }
 
+///
+/// DirectoryPlus reply
+///
+#[derive(Debug)]
+pub struct ReplyDirectoryPlus {
+    reply: ReplyRaw<()>,
+    data: Vec<u8>,
+}
+
+impl ReplyDirectoryPlus {
+    /// Creates a new ReplyDirectoryPlus with a specified buffer size.
+    pub fn new<S: ReplySender>(unique: u64, sender: S, size: usize) -> ReplyDirectoryPlus {
+        ReplyDirectoryPlus {
+            reply: Reply::new(unique, sender),
+            data: Vec::with_capacity(size),
+        }
+    }
+
+    /// Creates a new ReplyDirectoryPlus with a specified buffer size for a request received by a
+    /// session
+    pub(crate) fn from_session(unique: u64, sender: TrackingSender<ChannelSender>, size: usize) -> ReplyDirectoryPlus {
+        ReplyDirectoryPlus {
+            reply: ReplyRaw::from_session(unique, sender, FUSE_KERNEL_MINOR_VERSION),
+            data: Vec::with_capacity(size),
+        }
+    }
+
+    /// Add an entry with its attributes to the directory reply buffer. Returns true if the
+    /// buffer is full. Like with `ReplyDirectory`, a transparent offset value can be provided
+    /// for each entry. The kernel treats every entry except "." and ".." like a lookup reply,
+    /// i.e. it caches the attributes for the given ttl and increments the entry's lookup count.
+    pub fn add<T: AsRef<OsStr>>(&mut self, ino: u64, offset: i64, name: T, ttl: &Duration, attr: &FileAttr, generation: u64) -> bool {
+        let name = name.as_ref().as_bytes();
+        let entlen = mem::size_of::<fuse_direntplus>() + name.len();
+        let entsize = (entlen + mem::size_of::<u64>() - 1) & !(mem::size_of::<u64>() - 1); // 64bit align
+        if self.data.len() + entsize > self.data.capacity() {
+            return true;
+        }
+        let entry = fuse_direntplus {
+            entry_out: fuse_entry_out {
+                nodeid: attr.ino,
+                generation,
+                entry_valid: ttl.as_secs(),
+                attr_valid: ttl.as_secs(),
+                entry_valid_nsec: ttl.subsec_nanos(),
+                attr_valid_nsec: ttl.subsec_nanos(),
+                attr: fuse_attr_from_attr(attr),
+            },
+            dirent: fuse_dirent {
+                ino,
+                off: offset as u64,
+                namelen: name.len() as u32,
+                typ: mode_from_kind_and_perm(attr.kind, 0) >> 12,
+            },
+        };
+        as_bytes(&entry, |bytes| {
+            for b in bytes {
+                self.data.extend_from_slice(b);
+            }
+        });
+        self.data.extend_from_slice(name);
+        self.data.resize(self.data.len() + entsize - entlen, 0);
+        false
+    }
+
+    /// Reply to a request with the filled directory buffer
+    pub fn ok(mut self) {
+        self.reply.send(0, &[&self.data]);
+    }
+
+    /// Reply to a request with the given error code
+    pub fn error(self, err: c_int) {
+        self.reply.error(err);
+    }
+}
+
 ///
 /// Xattr reply
 ///
@@ -675,21 +987,176 @@ impl ReplyXattr {
         self.reply.send(0, &[data]);
     }
 
+    /// Reply to a request with the data in the xattr, given as chunks that are sent in a
+    /// single vectored write.
+    pub fn data_vectored(mut self, data: &[&[u8]]) {
+        self.reply.send(0, data);
+    }
+
     /// Reply to a request with the given error code.
     pub fn error(self, err: c_int) {
         self.reply.error(err);
     }
 }
 
+///
+/// Ioctl reply
+///
+#[derive(Debug)]
+pub struct ReplyIoctl {
+    reply: ReplyRaw<fuse_ioctl_out>,
+}
+
+impl Reply for ReplyIoctl {
+    fn new<S: ReplySender>(unique: u64, sender: S) -> ReplyIoctl {
+        ReplyIoctl {
+            reply: Reply::new(unique, sender),
+        }
+    }
+
+    fn with_proto_minor<S: ReplySender>(unique: u64, sender: S, proto_minor: u32) -> ReplyIoctl {
+        ReplyIoctl {
+            reply: Reply::with_proto_minor(unique, sender, proto_minor),
+        }
+    }
+}
+
+impl ReplyIoctl {
+    /// Reply to a request with the given result (the return value of the ioctl system call)
+    /// and output data. The data must not exceed the output size of the request.
+    pub fn ioctl(mut self, result: i32, data: &[u8]) {
+        let out = fuse_ioctl_out {
+            result,
+            flags: 0,
+            in_iovs: 0,
+            out_iovs: 0,
+        };
+        as_bytes(&out, |bytes| {
+            self.reply.send(0, &[bytes[0], data]);
+        });
+    }
+
+    /// Reply to an unrestricted ioctl request (`FUSE_IOCTL_UNRESTRICTED`) by asking the kernel
+    /// to retry it with the given input and output areas. Areas are given as address and length
+    /// in the memory of the calling process. The kernel then sends the request again with the
+    /// contents of the input areas as input data and the total length of the output areas as
+    /// output size. Output data is copied to the output areas in order. At most
+    /// `FUSE_IOCTL_MAX_IOV` areas can be given in total.
+    pub fn retry(mut self, in_iovs: &[(u64, u64)], out_iovs: &[(u64, u64)]) {
+        let out = fuse_ioctl_out {
+            result: 0,
+            flags: FUSE_IOCTL_RETRY,
+            in_iovs: in_iovs.len() as u32,
+            out_iovs: out_iovs.len() as u32,
+        };
+        // Kernels before ABI 7.16 expect areas as native struct iovec
+        let iovs = in_iovs.iter().chain(out_iovs);
+        let iovs: Vec<u8> = if self.reply.proto_minor < 16 {
+            iovs.flat_map(|&(base, len)| {
+                let iov = iovec { iov_base: base as usize as *mut c_void, iov_len: len as usize };
+                as_bytes(&iov, |bytes| bytes[0].to_vec())
+            }).collect()
+        } else {
+            iovs.flat_map(|&(base, len)| {
+                as_bytes(&fuse_ioctl_iovec { base, len }, |bytes| bytes[0].to_vec())
+            }).collect()
+        };
+        as_bytes(&out, |bytes| {
+            self.reply.send(0, &[bytes[0], &iovs]);
+        });
+    }
+
+    /// Reply to a request with the given error code
+    pub fn error(self, err: c_int) {
+        self.reply.error(err);
+    }
+}
+
+///
+/// Poll reply
+///
+#[derive(Debug)]
+pub struct ReplyPoll {
+    reply: ReplyRaw<fuse_poll_out>,
+}
+
+impl Reply for ReplyPoll {
+    fn new<S: ReplySender>(unique: u64, sender: S) -> ReplyPoll {
+        ReplyPoll {
+            reply: Reply::new(unique, sender),
+        }
+    }
+}
+
+impl ReplyPoll {
+    /// Reply to a request with the given poll events (`POLLIN`, `POLLOUT`, ...) the file is
+    /// ready for
+    pub fn poll(self, revents: u32) {
+        self.reply.ok(&fuse_poll_out {
+            revents,
+            padding: 0,
+        });
+    }
+
+    /// Reply to a request with the given error code
+    pub fn error(self, err: c_int) {
+        self.reply.error(err);
+    }
+}
+
+///
+/// Lseek reply
+///
+#[derive(Debug)]
+pub struct ReplyLseek {
+    reply: ReplyRaw<fuse_lseek_out>,
+}
+
+impl Reply for ReplyLseek {
+    fn new<S: ReplySender>(unique: u64, sender: S) -> ReplyLseek {
+        ReplyLseek {
+            reply: Reply::new(unique, sender),
+        }
+    }
+}
+
+impl ReplyLseek {
+    /// Reply to a request with the resulting file offset
+    pub fn offset(self, offset: i64) {
+        self.reply.ok(&fuse_lseek_out {
+            offset: offset as u64,
+        });
+    }
+
+    /// Reply to a request with the given error code
+    pub fn error(self, err: c_int) {
+        self.reply.error(err);
+    }
+}
+
+session_reply!(
+    ReplyEmpty, ReplyData, ReplyEntry, ReplyAttr, ReplyOpen, ReplyWrite, ReplyStatfs, ReplyCreate,
+    ReplyLock, ReplyBmap, ReplyXattr, ReplyIoctl, ReplyPoll, ReplyLseek
+);
+#[cfg(target_os = "macos")]
+session_reply!(ReplyXTimes);
+
 #[cfg(test)]
 mod test {
     use super::as_bytes;
     #[cfg(target_os = "macos")]
     use super::ReplyXTimes;
-    use super::ReplyXattr;
+    use super::{ReplyIoctl, ReplyLseek, ReplyPoll, ReplyXattr};
     use super::{Reply, ReplyAttr, ReplyData, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyRaw};
-    use super::{ReplyBmap, ReplyCreate, ReplyDirectory, ReplyLock, ReplyStatfs, ReplyWrite};
+    use super::{ReplyBmap, ReplyCreate, ReplyDirectory, ReplyDirectoryPlus, ReplyLock, ReplyStatfs, ReplyWrite};
+    use super::{SessionReply, StoredSender};
+    use crate::channel::ChannelSender;
+    use crate::interrupt::TrackingSender;
     use crate::{FileAttr, FileType};
+    use std::fs::{self, File};
+    use std::io::Read;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::sync::Arc;
     use std::sync::mpsc::{channel, Sender};
     use std::thread;
     use std::time::{Duration, UNIX_EPOCH};
@@ -819,38 +1286,116 @@ mod test {
     }
 
     #[test]
-    fn reply_entry() {
+    fn reply_data_vectored() {
         let sender = AssertSender {
-            expected: if cfg!(target_os = "macos") {
+            expected: vec![
                 vec![
-                    vec![
-                        0x98, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
-                        0x00, 0x00, 0x00, 0x00,
-                    ],
-                    vec![
-                        0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, 0x00, 0x00, 0x00,
-                        0x00, 0x00, 0x00, 0x00, 0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                        0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
-                        0x21, 0x43, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
-                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
-                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                        0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
-                        0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
-                        0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
-                        0x99, 0x00, 0x00, 0x00,
-                    ],
-                ]
-            } else {
+                    0x16, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                vec![0xde, 0xad],
+                vec![0xbe, 0xef],
+                vec![0x12, 0x34],
+            ],
+        };
+        let reply: ReplyData = Reply::new(0xdeadbeef, sender);
+        reply.data_vectored(&[&[0xde, 0xad], &[0xbe, 0xef], &[0x12, 0x34]]);
+    }
+
+    #[test]
+    fn reply_data_from_thread() {
+        let sender = AssertSender {
+            expected: vec![
                 vec![
-                    vec![
-                        0x88, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
-                        0x00, 0x00, 0x00, 0x00,
-                    ],
-                    vec![
-                        0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, 0x00, 0x00, 0x00,
-                        0x00, 0x00, 0x00, 0x00, 0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                vec![0xde, 0xad, 0xbe, 0xef],
+            ],
+        };
+        let reply: ReplyData = Reply::new(0xdeadbeef, sender);
+        let data = vec![0xde, 0xad, 0xbe, 0xef];
+        thread::spawn(move || {
+            reply.data(&data);
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn reply_data_from_fd() {
+        let path = std::env::temp_dir().join(format!("fuse-reply-{}-from-fd", std::process::id()));
+        fs::write(&path, b"0123456789").unwrap();
+        let file = File::open(&path).unwrap();
+        let sender = AssertSender {
+            expected: vec![
+                vec![
+                    0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                b"2345".to_vec(),
+            ],
+        };
+        let reply: ReplyData = Reply::new(0xdeadbeef, sender);
+        reply.from_fd(file.as_raw_fd(), 2, 4);
+        // Short read at the end of the file
+        let sender = AssertSender {
+            expected: vec![
+                vec![
+                    0x13, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                b"789".to_vec(),
+            ],
+        };
+        let reply: ReplyData = Reply::new(0xdeadbeef, sender);
+        reply.from_fd(file.as_raw_fd(), 7, 4096);
+        // Read beyond the end of the file
+        let sender = AssertSender {
+            expected: vec![
+                vec![
+                    0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                vec![],
+            ],
+        };
+        let reply: ReplyData = Reply::new(0xdeadbeef, sender);
+        reply.from_fd(file.as_raw_fd(), 20, 4);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reply_entry() {
+        let sender = AssertSender {
+            expected: if cfg!(target_os = "macos") {
+                vec![
+                    vec![
+                        0xa0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
+                        0x21, 0x43, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
+                        0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                        0x99, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    ],
+                ]
+            } else {
+                vec![
+                    vec![
+                        0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                         0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
                         0x21, 0x43, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                         0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
@@ -859,6 +1404,7 @@ mod test {
                         0x00, 0x00, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
                         0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
                         0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                     ],
                 ]
             },
@@ -885,8 +1431,139 @@ mod test {
         reply.entry(&ttl, &attr, 0xaa);
     }
 
+    #[test]
+    fn reply_entry_compat() {
+        let sender = AssertSender {
+            expected: if cfg!(target_os = "macos") {
+                vec![
+                    vec![
+                        0x98, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
+                        0x21, 0x43, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
+                        0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                        0x99, 0x00, 0x00, 0x00,
+                    ],
+                ]
+            } else {
+                vec![
+                    vec![
+                        0x88, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
+                        0x21, 0x43, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
+                        0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                    ],
+                ]
+            },
+        };
+        let reply: ReplyEntry = Reply::with_proto_minor(0xdeadbeef, sender, 8);
+        let time = UNIX_EPOCH + Duration::new(0x1234, 0x5678);
+        let ttl = Duration::new(0x8765, 0x4321);
+        let attr = FileAttr {
+            ino: 0x11,
+            size: 0x22,
+            blocks: 0x33,
+            atime: time,
+            mtime: time,
+            ctime: time,
+            crtime: time,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 0x55,
+            uid: 0x66,
+            gid: 0x77,
+            rdev: 0x88,
+            flags: 0x99,
+        };
+        reply.entry(&ttl, &attr, 0xaa);
+    }
+
     #[test]
     fn reply_attr() {
+        let sender = AssertSender {
+            expected: if cfg!(target_os = "macos") {
+                vec![
+                    vec![
+                        0x88, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
+                        0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                        0x99, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    ],
+                ]
+            } else {
+                vec![
+                    vec![
+                        0x78, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
+                        0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    ],
+                ]
+            },
+        };
+        let reply: ReplyAttr = Reply::new(0xdeadbeef, sender);
+        let time = UNIX_EPOCH + Duration::new(0x1234, 0x5678);
+        let ttl = Duration::new(0x8765, 0x4321);
+        let attr = FileAttr {
+            ino: 0x11,
+            size: 0x22,
+            blocks: 0x33,
+            atime: time,
+            mtime: time,
+            ctime: time,
+            crtime: time,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 0x55,
+            uid: 0x66,
+            gid: 0x77,
+            rdev: 0x88,
+            flags: 0x99,
+        };
+        reply.attr(&ttl, &attr);
+    }
+
+    #[test]
+    fn reply_attr_compat() {
         let sender = AssertSender {
             expected: if cfg!(target_os = "macos") {
                 vec![
@@ -926,7 +1603,71 @@ mod test {
                 ]
             },
         };
-        let reply: ReplyAttr = Reply::new(0xdeadbeef, sender);
+        let reply: ReplyAttr = Reply::with_proto_minor(0xdeadbeef, sender, 8);
+        let time = UNIX_EPOCH + Duration::new(0x1234, 0x5678);
+        let ttl = Duration::new(0x8765, 0x4321);
+        let attr = FileAttr {
+            ino: 0x11,
+            size: 0x22,
+            blocks: 0x33,
+            atime: time,
+            mtime: time,
+            ctime: time,
+            crtime: time,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 0x55,
+            uid: 0x66,
+            gid: 0x77,
+            rdev: 0x88,
+            flags: 0x99,
+        };
+        reply.attr(&ttl, &attr);
+    }
+
+    #[test]
+    fn reply_attr_abi_7_9() {
+        let sender = AssertSender {
+            expected: if cfg!(target_os = "macos") {
+                vec![
+                    vec![
+                        0x88, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
+                        0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                        0x99, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    ],
+                ]
+            } else {
+                vec![
+                    vec![
+                        0x78, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
+                        0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    ],
+                ]
+            },
+        };
+        let reply: ReplyAttr = Reply::with_proto_minor(0xdeadbeef, sender, 9);
         let time = UNIX_EPOCH + Duration::new(0x1234, 0x5678);
         let ttl = Duration::new(0x8765, 0x4321);
         let attr = FileAttr {
@@ -1026,6 +1767,77 @@ mod test {
 
     #[test]
     fn reply_create() {
+        let sender = AssertSender {
+            expected: if cfg!(target_os = "macos") {
+                vec![
+                    vec![
+                        0xb0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
+                        0x21, 0x43, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
+                        0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                        0x99, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0xbb, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xcc, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                ]
+            } else {
+                vec![
+                    vec![
+                        0xa0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                        0x00, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
+                        0x21, 0x43, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+                        0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
+                        0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xbb, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00, 0xcc, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    ],
+                ]
+            },
+        };
+        let reply: ReplyCreate = Reply::new(0xdeadbeef, sender);
+        let time = UNIX_EPOCH + Duration::new(0x1234, 0x5678);
+        let ttl = Duration::new(0x8765, 0x4321);
+        let attr = FileAttr {
+            ino: 0x11,
+            size: 0x22,
+            blocks: 0x33,
+            atime: time,
+            mtime: time,
+            ctime: time,
+            crtime: time,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 0x55,
+            uid: 0x66,
+            gid: 0x77,
+            rdev: 0x88,
+            flags: 0x99,
+        };
+        reply.created(&ttl, &attr, 0xaa, 0xbb, 0xcc);
+    }
+
+    #[test]
+    fn reply_create_compat() {
         let sender = AssertSender {
             expected: if cfg!(target_os = "macos") {
                 vec![
@@ -1045,8 +1857,11 @@ mod test {
                         0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
                         0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
                         0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
-                        0x99, 0x00, 0x00, 0x00, 0xbb, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                        0xcc, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x99, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
+                        0xbb, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xcc, 0x00, 0x00, 0x00,
+                        0x00, 0x00, 0x00, 0x00,
                     ],
                 ]
             } else {
@@ -1066,13 +1881,15 @@ mod test {
                         0x00, 0x00, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
                         0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
                         0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                    ],
+                    vec![
                         0xbb, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xcc, 0x00, 0x00, 0x00,
                         0x00, 0x00, 0x00, 0x00,
                     ],
                 ]
             },
         };
-        let reply: ReplyCreate = Reply::new(0xdeadbeef, sender);
+        let reply: ReplyCreate = Reply::with_proto_minor(0xdeadbeef, sender, 8);
         let time = UNIX_EPOCH + Duration::new(0x1234, 0x5678);
         let ttl = Duration::new(0x8765, 0x4321);
         let attr = FileAttr {
@@ -1150,6 +1967,56 @@ mod test {
         reply.ok();
     }
 
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn reply_directory_plus() {
+        let sender = AssertSender {
+            expected: vec![
+                vec![
+                    0xb0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+                    0x00, 0x00, 0x00, 0x00,
+                ],
+                vec![
+                    0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x65, 0x87, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x43, 0x00, 0x00,
+                    0x21, 0x43, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+                    0x78, 0x56, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00,
+                    0x66, 0x00, 0x00, 0x00, 0x77, 0x00, 0x00, 0x00, 0x88, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x05, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x68, 0x65, 0x6c, 0x6c,
+                    0x6f, 0x00, 0x00, 0x00,
+                ],
+            ],
+        };
+        let time = UNIX_EPOCH + Duration::new(0x1234, 0x5678);
+        let ttl = Duration::new(0x8765, 0x4321);
+        let attr = FileAttr {
+            ino: 0x11,
+            size: 0x22,
+            blocks: 0x33,
+            atime: time,
+            mtime: time,
+            ctime: time,
+            crtime: time,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 0x55,
+            uid: 0x66,
+            gid: 0x77,
+            rdev: 0x88,
+            flags: 0x99,
+        };
+        let mut reply = ReplyDirectoryPlus::new(0xdeadbeef, sender, 4096);
+        reply.add(0x11, 1, "hello", &ttl, &attr, 0xaa);
+        reply.ok();
+    }
+
     impl super::ReplySender for Sender<()> {
         fn send(&self, _: &[&[u8]]) {
             Sender::send(self, ()).unwrap()
@@ -1186,6 +2053,116 @@ mod test {
         reply.data(&vec![0x11, 0x22, 0x33, 0x44]);
     }
 
+    #[test]
+    fn reply_xattr_data_vectored() {
+        let sender = AssertSender {
+            expected: vec![
+                vec![
+                    0x15, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xEF, 0xBE, 0xAD, 0xDE, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                vec![0x11, 0x22],
+                vec![0x33, 0x44, 0x55],
+            ],
+        };
+        let reply = ReplyXattr::new(0xdeadbeef, sender);
+        reply.data_vectored(&[&[0x11, 0x22], &[0x33, 0x44, 0x55]]);
+    }
+
+    #[test]
+    fn reply_ioctl() {
+        let sender = AssertSender {
+            expected: vec![
+                vec![
+                    0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                vec![
+                    0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                vec![0x22, 0x33, 0x44, 0x55],
+            ],
+        };
+        let reply: ReplyIoctl = Reply::new(0xdeadbeef, sender);
+        reply.ioctl(0x11, &[0x22, 0x33, 0x44, 0x55]);
+    }
+
+    #[test]
+    fn reply_ioctl_retry() {
+        let sender = AssertSender {
+            expected: vec![
+                vec![
+                    0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                vec![
+                    0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01,
+                    0x00, 0x00, 0x00,
+                ],
+                vec![
+                    0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x22, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x44, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                ],
+            ],
+        };
+        let reply: ReplyIoctl = Reply::new(0xdeadbeef, sender);
+        reply.retry(&[(0x11, 0x22)], &[(0x33, 0x44)]);
+    }
+
+    #[test]
+    fn reply_poll() {
+        let sender = AssertSender {
+            expected: vec![
+                vec![
+                    0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                vec![0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            ],
+        };
+        let reply: ReplyPoll = Reply::new(0xdeadbeef, sender);
+        reply.poll(0x05);
+    }
+
+    #[test]
+    fn reply_lseek() {
+        let sender = AssertSender {
+            expected: vec![
+                vec![
+                    0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00,
+                    0x00, 0x00, 0x00,
+                ],
+                vec![0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            ],
+        };
+        let reply: ReplyLseek = Reply::new(0xdeadbeef, sender);
+        reply.offset(0x1000);
+    }
+
+    #[test]
+    fn session_sender_inline() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (mut reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let sender = TrackingSender::new(ChannelSender::from_raw_fd(fds[1]), 0xdeadbeef, Arc::default());
+        let mut reply: ReplyEmpty = Reply::new(0xdeadbeef, sender);
+        assert!(matches!(reply.reply.sender, Some(StoredSender::Boxed(_))));
+        reply.reply.sender.take();
+        let sender = TrackingSender::new(ChannelSender::from_raw_fd(fds[1]), 0xdeadbeef, Arc::default());
+        let reply = ReplyEmpty::from_session(0xdeadbeef, sender, 36);
+        assert!(matches!(reply.reply.sender, Some(StoredSender::Session(_))));
+        reply.ok();
+        drop(writer);
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, [
+            0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde, 0x00, 0x00,
+            0x00, 0x00,
+        ]);
+    }
+
     #[test]
     fn async_reply() {
         let (tx, rx) = channel::<()>();
@@ -7,9 +7,9 @@
 
 use fuse_abi::consts::*;
 use fuse_abi::*;
-use libc::{EIO, ENOSYS, EPROTO};
-use std::convert::TryFrom;
+use libc::{c_int, EAGAIN, EINTR, EIO, ENOSYS, EPROTO};
 use std::path::Path;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[cfg(not(feature = "tracing_support"))]
@@ -19,21 +19,17 @@ use log::{debug, error, warn};
 use tracing::{debug, error, warn};
 
 use crate::channel::ChannelSender;
+#[cfg(target_os = "linux")]
+use crate::cuse::{CharDevice, CuseSession};
+use crate::interrupt::{InterruptToken, Interrupts, TrackingSender};
+use crate::kernel_config::KernelConfig;
 use crate::ll;
-use crate::reply::{Reply, ReplyDirectory, ReplyEmpty, ReplyRaw};
-use crate::session::{Session, MAX_WRITE_SIZE};
-use crate::Filesystem;
-
-/// We generally support async reads
-#[cfg(not(target_os = "macos"))]
-const INIT_FLAGS: u32 = FUSE_ASYNC_READ;
-// TODO: Add FUSE_EXPORT_SUPPORT and FUSE_BIG_WRITES (requires ABI 7.10)
-
-/// On macOS, we additionally support case insensitiveness, volume renames and xtimes
-/// TODO: we should eventually let the filesystem implementation decide which flags to set
-#[cfg(target_os = "macos")]
-const INIT_FLAGS: u32 = FUSE_ASYNC_READ | FUSE_CASE_INSENSITIVE | FUSE_VOL_RENAME | FUSE_XTIMES;
-// TODO: Add FUSE_EXPORT_SUPPORT and FUSE_BIG_WRITES (requires ABI 7.10)
+use crate::notify::PollHandle;
+use crate::reply::{Reply, ReplyDirectory, ReplyDirectoryPlus, ReplyEmpty, ReplyRaw, SessionReply};
+use crate::session::Session;
+#[cfg(target_os = "linux")]
+use crate::splice::PipeData;
+use crate::{FallocateFlags, Filesystem, RenameFlags};
 
 /// Request data structure
 #[derive(Debug)]
@@ -44,21 +40,56 @@ pub struct Request<'a> {
     data: &'a [u8],
     /// Parsed request
     request: ll::Request<'a>,
+    /// Connection configuration negotiated during init
+    config: KernelConfig,
+    /// Requests of the session that haven't been replied to yet
+    interrupts: Arc<Interrupts>,
 }
 
 impl<'a> Request<'a> {
-    /// Create a new request from the given data
-    pub fn new(ch: ChannelSender, data: &'a [u8]) -> Option<Request<'a>> {
-        let request = match ll::Request::try_from(data) {
+    /// Create a new request from the given data. Requests of unknown operations are replied
+    /// with `ENOSYS`, so the session can go on with the next request.
+    pub(crate) fn new(ch: ChannelSender, data: &'a [u8], config: KernelConfig, interrupts: &Arc<Interrupts>) -> Result<Request<'a>, ll::RequestError> {
+        let request = match ll::Request::parse(data, config.negotiated_minor(), config.flags()) {
             Ok(request) => request,
+            Err(ll::RequestError::UnknownOperation(opcode, unique)) => {
+                warn!("Unsupported FUSE opcode {}, replying ENOSYS", opcode);
+                ReplyEmpty::new(unique, ch).error(ENOSYS);
+                return Err(ll::RequestError::UnknownOperation(opcode, unique));
+            }
             Err(err) => {
-                // FIXME: Reply with ENOSYS?
                 error!("{}", err);
-                return None;
+                return Err(err);
             }
         };
 
-        Some(Self { ch, data, request })
+        // Track requests that expect a reply, so that they can be interrupted
+        match request.operation() {
+            ll::Operation::Forget { .. }
+            | ll::Operation::BatchForget { .. }
+            | ll::Operation::Interrupt { .. }
+            | ll::Operation::NotifyReply { .. } => (),
+            _ => interrupts.register(request.unique()),
+        }
+
+        Ok(Self { ch, data, request, config, interrupts: interrupts.clone() })
+    }
+
+    /// Create a request for the given operation that doesn't come from the kernel driver,
+    /// e.g. to destroy the filesystem if a session ends before the kernel sends its destroy
+    /// request. It isn't tracked for interrupts and must not be replied to.
+    pub(crate) fn synthetic(ch: ChannelSender, operation: ll::Operation<'a>, config: KernelConfig, interrupts: &Arc<Interrupts>) -> Request<'a> {
+        let request = ll::Request::synthetic(operation);
+        Self { ch, data: &[], request, config, interrupts: interrupts.clone() }
+    }
+
+    /// Reply to interrupts of unknown requests. Now that another request arrived, such an
+    /// interrupt is either for a request that is already completed or one that isn't received
+    /// yet. Replying with EAGAIN makes the kernel drop or re-send it.
+    fn reply_stale_interrupts(&self) {
+        while let Some(unique) = self.interrupts.take_stale() {
+            ReplyEmpty::new(unique, self.ch).error(EAGAIN);
+        }
     }
 
     /// Dispatch request to the given filesystem.
@@ -67,6 +98,8 @@ impl<'a> Request<'a> {
     pub fn dispatch<FS: Filesystem>(&self, se: &mut Session<FS>) {
         debug!("{}", self.request);
 
+        self.reply_stale_interrupts();
+
         match self.request.operation() {
             // Filesystem initialization
             ll::Operation::Init { arg } => {
@@ -74,6 +107,7 @@ impl<'a> Request<'a> {
                 // We don't support ABI versions before 7.6
                 if arg.major < 7 || (arg.major == 7 && arg.minor < 6) {
                     error!("Unsupported FUSE ABI version {}.{}", arg.major, arg.minor);
+                    se.ready.set(Err(EPROTO));
                     reply.error(EPROTO);
                     return;
                 }
@@ -81,30 +115,57 @@ impl<'a> Request<'a> {
                 se.proto_major = arg.major;
                 se.proto_minor = arg.minor;
                 // Call filesystem init method and give it a chance to return an error
-                let res = se.filesystem.init(self);
+                // or to request capabilities and limits for this connection
+                let mut config = KernelConfig::new(arg);
+                config.limit_max_write(se.max_write_limit);
+                let res = se.filesystem.init(self, &mut config);
                 if let Err(err) = res {
+                    se.ready.set(Err(err));
                     reply.error(err);
                     return;
                 }
+                // Receive requests through a pipe if the filesystem wants to splice them.
+                // Splicing isn't requested from the kernel if the pipe can't be created.
+                #[cfg(target_os = "linux")]
+                {
+                    if config.flags() & FUSE_SPLICE_READ != 0 {
+                        se.splice = crate::session::request_pipe(&config);
+                        if se.splice.is_none() {
+                            config.remove_capabilities(FUSE_SPLICE_READ);
+                        }
+                    }
+                }
                 // Reply with our desired version and settings. If the kernel supports a
                 // larger major version, it'll re-send a matching init message. If it
                 // supports only lower major versions, we replied with an error above.
                 let init = fuse_init_out {
                     major: FUSE_KERNEL_VERSION,
                     minor: FUSE_KERNEL_MINOR_VERSION,
-                    max_readahead: arg.max_readahead, // accept any readahead size
-                    flags: arg.flags & INIT_FLAGS, // use features given in INIT_FLAGS and reported as capable
-                    max_write: MAX_WRITE_SIZE as u32, // use a max write size that fits into the session's buffer
-                    // congestion_threshold: 0 as u16,   // TODO: see polyfuse and fix this
-                    // max_background: 0 as u16,         // TODO: see polyfuse and fix this
-                    unused: 0,
+                    max_readahead: config.max_readahead(),
+                    flags: config.flags() as u32,
+                    max_background: config.max_background(),
+                    congestion_threshold: config.congestion_threshold(),
+                    max_write: config.max_write(),
+                    time_gran: 0,
+                    max_pages: config.max_pages(),
+                    map_alignment: 0,
+                    flags2: (config.flags() >> 32) as u32,
+                    max_stack_depth: 0,
+                    request_timeout: 0,
+                    unused: [0; 11],
                 };
                 debug!(
-                    "INIT response: ABI {}.{}, flags {:#x}, max readahead {}, max write {}",
-                    init.major, init.minor, init.flags, init.max_readahead, init.max_write
+                    "INIT response: ABI {}.{}, flags {:#x}, max readahead {}, max write {}, max background {}, congestion threshold {}",
+                    init.major, init.minor, config.flags(), init.max_readahead, init.max_write, init.max_background, init.congestion_threshold
                 );
+                se.config = config;
                 se.initialized = true;
-                reply.ok(&init);
+                // Kernels before ABI 7.23 expect the short init reply
+                match arg.minor {
+                    0..=22 => reply.ok_compat(&init, FUSE_COMPAT_22_INIT_OUT_SIZE),
+                    _ => reply.ok(&init),
+                }
+                se.ready.set(Ok(()));
             }
             // Any operation is invalid before initialization
             _ if !se.initialized => {
@@ -123,9 +184,9 @@ impl<'a> Request<'a> {
                 self.reply::<ReplyEmpty>().error(EIO);
             }
 
-            ll::Operation::Interrupt { .. } => {
-                // TODO: handle FUSE_INTERRUPT
-                self.reply::<ReplyEmpty>().error(ENOSYS);
+            ll::Operation::Interrupt { arg } => {
+                // No reply, unless the interrupted request is unknown (see below)
+                self.interrupts.interrupt(self.request.unique(), arg.unique);
             }
 
             ll::Operation::Lookup { name } => {
@@ -136,6 +197,10 @@ impl<'a> Request<'a> {
                 se.filesystem
                     .forget(self, self.request.nodeid(), arg.nlookup); // no reply
             }
+            ll::Operation::BatchForget { nodes, .. } => {
+                let nodes: Vec<_> = nodes.iter().map(|node| (node.nodeid, node.nlookup)).collect();
+                se.filesystem.batch_forget(self, &nodes); // no reply
+            }
             ll::Operation::GetAttr => {
                 se.filesystem
                     .getattr(self, self.request.nodeid(), self.reply());
@@ -269,6 +334,7 @@ impl<'a> Request<'a> {
                     &name,
                     arg.newdir,
                     &newname,
+                    RenameFlags::default(),
                     self.reply(),
                 );
             }
@@ -296,6 +362,33 @@ impl<'a> Request<'a> {
                 );
             }
             ll::Operation::Write { arg, data } => {
+                // The data of a spliced write request is still in the pipe
+                #[cfg(target_os = "linux")]
+                {
+                    if let Some(rx) = se.splice.as_mut().filter(|rx| rx.pending() > 0) {
+                        if rx.pending() != arg.size as usize {
+                            error!("Spliced write data of {} bytes doesn't match write size {}", rx.pending(), arg.size);
+                            if let Err(err) = rx.discard() {
+                                error!("Failed to discard write data: {}", err);
+                            }
+                            self.reply::<ReplyEmpty>().error(EIO);
+                            return;
+                        }
+                        se.filesystem.write_pipe(
+                            self,
+                            self.request.nodeid(),
+                            arg.fh,
+                            arg.offset as i64,
+                            PipeData::new(rx),
+                            arg.write_flags,
+                            self.reply(),
+                        );
+                        if let Err(err) = rx.discard() {
+                            error!("Failed to discard write data: {}", err);
+                        }
+                        return;
+                    }
+                }
                 assert!(data.len() == arg.size as usize);
                 se.filesystem.write(
                     self,
@@ -349,7 +442,16 @@ impl<'a> Request<'a> {
                     self.request.nodeid(),
                     arg.fh,
                     arg.offset as i64,
-                    ReplyDirectory::new(self.request.unique(), self.ch, arg.size as usize),
+                    ReplyDirectory::from_session(self.request.unique(), self.sender(), arg.size as usize),
+                );
+            }
+            ll::Operation::ReadDirPlus { arg } => {
+                se.filesystem.readdirplus(
+                    self,
+                    self.request.nodeid(),
+                    arg.fh,
+                    arg.offset as i64,
+                    ReplyDirectoryPlus::from_session(self.request.unique(), self.sender(), arg.size as usize),
                 );
             }
             ll::Operation::ReleaseDir { arg } => {
@@ -471,6 +573,113 @@ impl<'a> Request<'a> {
                     self.reply(),
                 );
             }
+            ll::Operation::IoCtl { arg, data } => {
+                se.filesystem.ioctl(
+                    self,
+                    self.request.nodeid(),
+                    arg.fh,
+                    arg.flags,
+                    arg.cmd,
+                    arg.arg,
+                    data,
+                    arg.out_size,
+                    self.reply(),
+                );
+            }
+            ll::Operation::Poll { arg } => {
+                let ph = if arg.flags & FUSE_POLL_SCHEDULE_NOTIFY != 0 {
+                    Some(PollHandle::new(arg.kh, se.notifier()))
+                } else {
+                    None
+                };
+                se.filesystem.poll(
+                    self,
+                    self.request.nodeid(),
+                    arg.fh,
+                    ph,
+                    arg.events,
+                    arg.flags,
+                    self.reply(),
+                );
+            }
+            ll::Operation::NotifyReply { arg, data } => {
+                // The reply to a retrieve notification carries the notification's unique id
+                if !se.retrievals.complete(self.request.unique(), arg.offset, data) {
+                    warn!("Ignoring reply to unknown retrieve notification {}", self.request.unique());
+                }
+                // no reply
+            }
+            ll::Operation::FAllocate { arg } => {
+                se.filesystem.fallocate(
+                    self,
+                    self.request.nodeid(),
+                    arg.fh,
+                    arg.offset as i64,
+                    arg.length as i64,
+                    FallocateFlags::from_bits(arg.mode),
+                    self.reply(),
+                );
+            }
+            ll::Operation::Rename2 { arg, name, newname } => {
+                se.filesystem.rename(
+                    self,
+                    self.request.nodeid(),
+                    name,
+                    arg.newdir,
+                    newname,
+                    RenameFlags::from_bits(arg.flags),
+                    self.reply(),
+                );
+            }
+            ll::Operation::LSeek { arg } => {
+                se.filesystem.lseek(
+                    self,
+                    self.request.nodeid(),
+                    arg.fh,
+                    arg.offset as i64,
+                    arg.whence as i32,
+                    self.reply(),
+                );
+            }
+            ll::Operation::CopyFileRange { arg } => {
+                se.filesystem.copy_file_range(
+                    self,
+                    self.request.nodeid(),
+                    arg.fh_in,
+                    arg.off_in as i64,
+                    arg.nodeid_out,
+                    arg.fh_out,
+                    arg.off_out as i64,
+                    arg.len,
+                    arg.flags,
+                    self.reply(),
+                );
+            }
+            ll::Operation::SetupMapping { .. } | ll::Operation::RemoveMapping { .. } => {
+                // DAX mappings are only used by virtio-fs
+                self.reply::<ReplyEmpty>().error(ENOSYS);
+            }
+            ll::Operation::SyncFs => {
+                // TODO: handle FUSE_SYNCFS
+                self.reply::<ReplyEmpty>().error(ENOSYS);
+            }
+            ll::Operation::TmpFile { .. } => {
+                // TODO: handle FUSE_TMPFILE
+                self.reply::<ReplyEmpty>().error(ENOSYS);
+            }
+            ll::Operation::StatX { .. } => {
+                // TODO: handle FUSE_STATX
+                self.reply::<ReplyEmpty>().error(ENOSYS);
+            }
+            ll::Operation::CopyFileRange64 { .. } => {
+                // TODO: handle FUSE_COPY_FILE_RANGE_64
+                // The kernel falls back to FUSE_COPY_FILE_RANGE, which copies at most 4G at once
+                self.reply::<ReplyEmpty>().error(ENOSYS);
+            }
+            ll::Operation::CuseInit { .. } => {
+                // CUSE requests are not expected on a FUSE channel
+                self.reply::<ReplyEmpty>().error(ENOSYS);
+            }
 
             #[cfg(target_os = "macos")]
             ll::Operation::SetVolName { name } => {
@@ -500,10 +709,116 @@ impl<'a> Request<'a> {
         }
     }
 
+    /// Dispatch request to the given character device.
+    /// CUSE channels only carry operations on opened devices, other operations are
+    /// replied with `ENOSYS`.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn dispatch_cuse<D: CharDevice>(&self, se: &mut CuseSession<D>) {
+        debug!("{}", self.request);
+
+        self.reply_stale_interrupts();
+
+        match self.request.operation() {
+            // Device initialization
+            ll::Operation::CuseInit { arg } => {
+                let reply: ReplyRaw<cuse_init_out> = self.reply();
+                // CUSE was introduced with ABI 7.12
+                if arg.major < 7 || (arg.major == 7 && arg.minor < 12) {
+                    error!("Unsupported CUSE ABI version {}.{}", arg.major, arg.minor);
+                    reply.error(EPROTO);
+                    return;
+                }
+                // Call device init method and give it a chance to return an error
+                if let Err(err) = se.device.init(self) {
+                    reply.error(err);
+                    return;
+                }
+                let config = KernelConfig::cuse(arg);
+                let init = cuse_init_out {
+                    major: FUSE_KERNEL_VERSION,
+                    minor: FUSE_KERNEL_MINOR_VERSION,
+                    unused: 0,
+                    flags: se.flags,
+                    max_read: config.max_write(),
+                    max_write: config.max_write(),
+                    dev_major: se.dev_major,
+                    dev_minor: se.dev_minor,
+                    spare: [0; 10],
+                };
+                debug!(
+                    "CUSE_INIT response: ABI {}.{}, flags {:#x}, device {}:{}",
+                    init.major, init.minor, init.flags, init.dev_major, init.dev_minor
+                );
+                se.config = config;
+                se.initialized = true;
+                reply.ok_with_data(&init, &se.device_info());
+            }
+            // Any operation is invalid before initialization
+            _ if !se.initialized => {
+                warn!("Ignoring CUSE operation before init: {}", self.request);
+                self.reply::<ReplyEmpty>().error(EIO);
+            }
+            // Device destroyed
+            ll::Operation::Destroy => {
+                se.device.destroy(self);
+                se.destroyed = true;
+                self.reply::<ReplyEmpty>().ok();
+            }
+            // Any operation is invalid after destroy
+            _ if se.destroyed => {
+                warn!("Ignoring CUSE operation after destroy: {}", self.request);
+                self.reply::<ReplyEmpty>().error(EIO);
+            }
+
+            ll::Operation::Interrupt { arg } => {
+                self.interrupts.interrupt(self.request.unique(), arg.unique);
+            }
+
+            ll::Operation::Open { arg } => {
+                se.device.open(self, arg.flags, self.reply());
+            }
+            ll::Operation::Read { arg } => {
+                se.device.read(self, arg.fh, arg.offset as i64, arg.size, self.reply());
+            }
+            ll::Operation::Write { arg, data } => {
+                assert!(data.len() == arg.size as usize);
+                se.device.write(self, arg.fh, arg.offset as i64, data, arg.write_flags, self.reply());
+            }
+            ll::Operation::Release { arg } => {
+                se.device.release(self, arg.fh, arg.flags, self.reply());
+            }
+            ll::Operation::IoCtl { arg, data } => {
+                se.device.ioctl(self, arg.fh, arg.flags, arg.cmd, arg.arg, data, arg.out_size, self.reply());
+            }
+            ll::Operation::Poll { arg } => {
+                let ph = if arg.flags & FUSE_POLL_SCHEDULE_NOTIFY != 0 {
+                    Some(PollHandle::new(arg.kh, se.notifier()))
+                } else {
+                    None
+                };
+                se.device.poll(self, arg.fh, ph, arg.events, arg.flags, self.reply());
+            }
+
+            // Operations that expect no reply
+            ll::Operation::Forget { .. }
+            | ll::Operation::BatchForget { .. }
+            | ll::Operation::NotifyReply { .. } => (),
+            _ => {
+                self.reply::<ReplyEmpty>().error(ENOSYS);
+            }
+        }
+    }
+
+    /// Create a sender for the reply to this request, which stops tracking the request
+    /// for interrupts once the reply is sent
+    fn sender(&self) -> TrackingSender<ChannelSender> {
+        TrackingSender::new(self.ch.with_flags(self.config.flags()), self.request.unique(), self.interrupts.clone())
+    }
+
     /// Create a reply object for this request that can be passed to the filesystem
     /// implementation and makes sure that a request is replied exactly once
-    fn reply<T: Reply>(&self) -> T {
-        Reply::new(self.request.unique(), self.ch)
+    fn reply<T: SessionReply>(&self) -> T {
+        T::from_session(self.request.unique(), self.sender(), self.config.negotiated_minor())
     }
 
     /// Returns the unique identifier of this request
@@ -533,4 +848,126 @@ impl<'a> Request<'a> {
     pub fn pid(&self) -> i32 {
         self.request.pid()
     }
+
+    /// Returns the connection configuration negotiated with the kernel during init.
+    /// While the init request itself is handled, the configuration is still empty.
+    #[inline]
+    pub fn kernel_config(&self) -> &KernelConfig {
+        &self.config
+    }
+
+    /// Returns true if the kernel asked to interrupt this request. An interrupted request
+    /// should be replied as soon as possible, usually with `EINTR`.
+    #[inline]
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupts.is_interrupted(self.request.unique())
+    }
+
+    /// Returns `EINTR` as error if the kernel asked to interrupt this request, which can be
+    /// passed on to the reply's `error` method. This is the usual response to an interrupt.
+    #[inline]
+    pub fn check_interrupted(&self) -> Result<(), c_int> {
+        if self.is_interrupted() {
+            Err(EINTR)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns a token to check or wait for the interruption of this request, e.g. in a
+    /// thread that processes the request asynchronously. The token is created on the first
+    /// call, so requests whose interruption isn't waited for don't allocate one.
+    #[inline]
+    pub fn interrupt_token(&self) -> InterruptToken {
+        self.interrupts.token(self.request.unique())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Request;
+    use crate::channel::ChannelSender;
+    use crate::interrupt::Interrupts;
+    use crate::kernel_config::KernelConfig;
+    use crate::ll::RequestError;
+    use fuse_abi::{fuse_in_header, fuse_opcode};
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::Arc;
+    use std::{mem, slice};
+
+    fn header(opcode: u32, unique: u64) -> fuse_in_header {
+        fuse_in_header {
+            len: mem::size_of::<fuse_in_header>() as u32,
+            opcode,
+            unique,
+            nodeid: 1,
+            uid: 0,
+            gid: 0,
+            pid: 0,
+            total_extlen: 0,
+            padding: 0,
+        }
+    }
+
+    fn as_bytes(header: &fuse_in_header) -> &[u8] {
+        unsafe { slice::from_raw_parts(header as *const fuse_in_header as *const u8, mem::size_of::<fuse_in_header>()) }
+    }
+
+    fn error_reply(unique: u64, err: i32) -> Vec<u8> {
+        let mut bytes = 16u32.to_ne_bytes().to_vec();
+        bytes.extend_from_slice(&(-err).to_ne_bytes());
+        bytes.extend_from_slice(&unique.to_ne_bytes());
+        bytes
+    }
+
+    #[test]
+    fn unknown_operation() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (mut reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let header = header(200, 0xdeadbeef);
+        let data = as_bytes(&header);
+        let interrupts = Arc::new(Interrupts::default());
+        match Request::new(ChannelSender::from_raw_fd(fds[1]), data, KernelConfig::empty(), &interrupts) {
+            Err(RequestError::UnknownOperation(200, 0xdeadbeef)) => (),
+            _ => panic!("Unexpected request parsing result"),
+        }
+        // The request is replied with ENOSYS
+        drop(writer);
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, error_reply(0xdeadbeef, libc::ENOSYS));
+    }
+
+    #[test]
+    fn check_interrupted() {
+        let header = header(fuse_opcode::FUSE_STATFS as u32, 1);
+        let interrupts = Arc::new(Interrupts::default());
+        let req = Request::new(ChannelSender::from_raw_fd(-1), as_bytes(&header), KernelConfig::empty(), &interrupts).unwrap();
+        assert_eq!(req.check_interrupted(), Ok(()));
+        interrupts.interrupt(2, 1);
+        assert_eq!(req.check_interrupted(), Err(libc::EINTR));
+    }
+
+    #[test]
+    fn reply_all_stale_interrupts() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (mut reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let interrupts = Arc::new(Interrupts::default());
+        interrupts.interrupt(2, 1);
+        interrupts.interrupt(4, 3);
+        let header = header(fuse_opcode::FUSE_STATFS as u32, 5);
+        let req = Request::new(ChannelSender::from_raw_fd(fds[1]), as_bytes(&header), KernelConfig::empty(), &interrupts).unwrap();
+        req.reply_stale_interrupts();
+        assert_eq!(interrupts.take_stale(), None);
+        drop(writer);
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        let mut expected = error_reply(2, libc::EAGAIN);
+        expected.extend(error_reply(4, libc::EAGAIN));
+        assert_eq!(bytes, expected);
+    }
 }
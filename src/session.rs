@@ -7,26 +7,46 @@
 
 use std::io;
 use std::ffi::OsStr;
-use std::fmt;
+use std::panic;
 use std::path::{PathBuf, Path};
-use thread_scoped::{scoped, JoinGuard};
-use libc::{EAGAIN, EINTR, ENODEV, ENOENT};
-use log::{error, info};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use libc::{EAGAIN, EINTR, EIO, ENODEV, ENOENT};
+use log::{error, info, warn};
 use mio::{Poll, Token, Evented, Ready, PollOpt};
 use mio::unix::EventedFd;
+#[cfg(feature = "tokio_support")]
+use tokio::io::{unix::AsyncFd, Interest};
 
+#[cfg(feature = "tokio_support")]
+use crate::async_filesystem::{AsyncDispatcher, AsyncFilesystem};
+use crate::buffer::{BufferPool, PooledBuffer};
 use crate::channel::{self, Channel};
+use crate::interrupt::Interrupts;
+use crate::kernel_config::{KernelConfig, MIN_WRITE_SIZE};
+use crate::ll::{self, RequestError};
+use crate::notify::{Notifier, Retrievals};
+use crate::ready::{Readiness, WaitReady};
 use crate::request::Request;
-use crate::Filesystem;
+use crate::shutdown::ShutdownToken;
+#[cfg(target_os = "linux")]
+use crate::splice::RequestPipe;
+use crate::{Filesystem, SyncFilesystem};
 
 /// The max size of write requests from the kernel. The absolute minimum is 4k,
 /// FUSE recommends at least 128k, max 16M. The FUSE default is 16M on macOS
-/// and 128k on other systems.
+/// and 128k on other systems. Sessions use this as default limit (see
+/// `Session::set_max_write_limit`).
 pub const MAX_WRITE_SIZE: usize = 16 * 1024 * 1024;
 
-/// Size of the buffer for reading a request from the kernel. Since the kernel may send
-/// up to MAX_WRITE_SIZE bytes in a write request, we use that value plus some extra space.
-const BUFFER_SIZE: usize = MAX_WRITE_SIZE + 4096;
+/// How long a graceful shutdown waits for replies to outstanding requests (see
+/// `Session::run_until`)
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Buffers for receiving requests, shared by all sessions and worker threads. Each buffer
+/// is sized for the max write size negotiated on its connection.
+pub(crate) static REQUEST_BUFFERS: BufferPool = BufferPool::new(16 * 1024 * 1024);
 
 /// The session data structure
 #[derive(Debug)]
@@ -39,6 +59,21 @@ pub struct Session<FS: Filesystem> {
     pub proto_major: u32,
     /// FUSE protocol minor version
     pub proto_minor: u32,
+    /// Connection configuration negotiated during init
+    pub(crate) config: KernelConfig,
+    /// Upper bound of the max write size negotiated during init
+    pub(crate) max_write_limit: u32,
+    /// Requests that haven't been replied to yet
+    interrupts: Arc<Interrupts>,
+    /// Retrieve notifications that haven't been replied to yet
+    pub(crate) retrievals: Arc<Retrievals>,
+    /// Readiness of the session (set once the init request is handled)
+    pub(crate) ready: Arc<Readiness>,
+    /// Token that ends the session gracefully if signal handling is enabled
+    shutdown: Option<ShutdownToken>,
+    /// Pipe to receive requests through if FUSE_SPLICE_READ is negotiated
+    #[cfg(target_os = "linux")]
+    pub(crate) splice: Option<RequestPipe>,
     /// True if the filesystem is initialized (init operation done)
     pub initialized: bool,
     /// True if the filesystem was destroyed (destroy operation done)
@@ -59,55 +94,195 @@ impl<FS: Filesystem> Session<FS> {
     /// Create a new session by mounting the given filesystem to the given mountpoint
     pub fn new(filesystem: FS, mountpoint: &Path, options: &[&OsStr]) -> io::Result<Session<FS>> {
         info!("Mounting {}", mountpoint.display());
-        Channel::new(mountpoint, options).map(|ch| {
-            Session {
-                filesystem: filesystem,
-                ch: ch,
-                proto_major: 0,
-                proto_minor: 0,
-                initialized: false,
-                destroyed: false,
-            }
-        })
+        Channel::new(mountpoint, options).map(|ch| Session::with_channel(filesystem, ch))
+    }
+
+    /// Create a new session that receives requests through the given channel
+    fn with_channel(filesystem: FS, ch: Channel) -> Session<FS> {
+        Session {
+            filesystem,
+            ch,
+            proto_major: 0,
+            proto_minor: 0,
+            config: KernelConfig::empty(),
+            max_write_limit: MAX_WRITE_SIZE as u32,
+            interrupts: Arc::new(Interrupts::default()),
+            retrievals: Arc::new(Retrievals::default()),
+            ready: Arc::new(Readiness::default()),
+            shutdown: None,
+            #[cfg(target_os = "linux")]
+            splice: None,
+            initialized: false,
+            destroyed: false,
+        }
     }
 
     /// Return path of the mounted filesystem
     pub fn mountpoint(&self) -> &Path {
-        &self.ch.mountpoint()
+        self.ch.mountpoint().expect("session channel without mount point")
+    }
+
+    /// Returns a notifier to send notifications to the kernel driver, e.g. to invalidate
+    /// cached entries. The notifier can be used from other threads while the session runs.
+    pub fn notifier(&self) -> Notifier {
+        Notifier::new(self.ch.shared_sender(), self.retrievals.clone())
+    }
+
+    /// Returns the connection configuration negotiated with the kernel, or `None` if the
+    /// filesystem isn't initialized yet
+    pub fn kernel_config(&self) -> Option<&KernelConfig> {
+        if self.initialized {
+            Some(&self.config)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the upper bound of the max write size negotiated during init
+    pub fn max_write_limit(&self) -> u32 {
+        self.max_write_limit
+    }
+
+    /// Limit the max size of write requests to conserve memory. Request buffers are sized
+    /// for the max write size negotiated during init, which the filesystem can lower in
+    /// `init` but can't raise above this limit. The limit must be at least 4k and at most
+    /// `MAX_WRITE_SIZE` (the default), otherwise the nearest valid value is returned as
+    /// error. On success, the previous limit is returned. Must be set before the session
+    /// runs to have an effect. The negotiated max write size is further capped at what the
+    /// kernel allows (`/proc/sys/fs/fuse/max_pages_limit` pages).
+    pub fn set_max_write_limit(&mut self, limit: u32) -> Result<u32, u32> {
+        if limit < MIN_WRITE_SIZE {
+            return Err(MIN_WRITE_SIZE);
+        }
+        if limit > MAX_WRITE_SIZE as u32 {
+            return Err(MAX_WRITE_SIZE as u32);
+        }
+        let previous = self.max_write_limit;
+        self.max_write_limit = limit;
+        Ok(previous)
     }
 
     /// Run the session loop that receives kernel requests and dispatches them to method
     /// calls into the filesystem. This read-dispatch-loop is non-concurrent to prevent
     /// having multiple buffers (which take up much memory), but the filesystem methods
     /// may run concurrent by spawning threads.
+    /// If signal handling is enabled (see `handle_signals`), the session ends gracefully
+    /// like `run_until` does on SIGINT or SIGTERM.
     pub fn run(&mut self) -> io::Result<()> {
-        // Buffer for receiving requests from the kernel. Only one is allocated and
-        // it is reused immediately after dispatching to conserve memory and allocations.
-        let mut buffer: Vec<u8> = Vec::with_capacity(BUFFER_SIZE);
+        match self.shutdown.clone() {
+            Some(shutdown) => self.run_until(&shutdown),
+            // Without a shutdown token, requests are read right away without polling first
+            None => self.run_loop(None).map(|_| ()),
+        }
+    }
+
+    /// Run the session loop like `run` until the given token is triggered. The loop then
+    /// stops receiving requests and waits up to 5 seconds for replies to outstanding
+    /// requests (which may be sent from other threads). Requests that are still outstanding
+    /// afterwards are interrupted (see `InterruptToken`) and not waited for any longer.
+    /// Then, the filesystem is destroyed and unmounted. Returns early if the filesystem is
+    /// unmounted by other means. To notice the token while waiting for requests, every
+    /// request costs an additional `poll(2)` call compared to `run` without signal handling.
+    pub fn run_until(&mut self, shutdown: &ShutdownToken) -> io::Result<()> {
+        if self.run_loop(Some(shutdown))? {
+            self.shut_down()?;
+        }
+        Ok(())
+    }
+
+    /// Opt in to a graceful shutdown on SIGINT and SIGTERM: once one of these signals
+    /// arrives, `run` ends like `run_until` does. Returns the token that is triggered by
+    /// the signals (see `ShutdownToken::trigger_on_signals`), which can also be triggered
+    /// by other means.
+    pub fn handle_signals(&mut self) -> io::Result<ShutdownToken> {
+        let shutdown = ShutdownToken::new()?;
+        shutdown.trigger_on_signals()?;
+        self.shutdown = Some(shutdown.clone());
+        Ok(shutdown)
+    }
+
+    /// Receive and dispatch requests until the filesystem is unmounted or the given token
+    /// is triggered. Returns true in the latter case. Only with a token, the channel is
+    /// polled before each read.
+    fn run_loop(&mut self, shutdown: Option<&ShutdownToken>) -> io::Result<bool> {
+        // Buffer for receiving requests from the kernel. Only one is used and it is
+        // reused immediately after dispatching to conserve memory and allocations.
+        let mut buffer = REQUEST_BUFFERS.take(self.config.buffer_size());
         loop {
+            // The buffer needs to grow once the max write size is negotiated
+            if buffer.capacity() < self.config.buffer_size() {
+                buffer = REQUEST_BUFFERS.take(self.config.buffer_size());
+            }
+            if let Some(shutdown) = shutdown {
+                if !self.wait_for_request(shutdown)? {
+                    return Ok(true);
+                }
+            }
             // Read the next request from the given channel to kernel driver
             // The kernel driver makes sure that we get exactly one request per read
             match self.receive(&mut buffer) {
                 RecvResult::Some(request) => request.dispatch(self),
                 RecvResult::Retry => continue,
-                RecvResult::Drop(None) => return Ok(()),
+                RecvResult::Drop(None) => return Ok(false),
                 RecvResult::Drop(Some(err)) => return Err(err),
             }
         }
     }
 
+    /// Block until a request can be received or the given token is triggered. Returns false
+    /// in the latter case.
+    fn wait_for_request(&self, shutdown: &ShutdownToken) -> io::Result<bool> {
+        let mut fds = [
+            libc::pollfd { fd: *unsafe { self.ch.raw_fd() }, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: shutdown.fd(), events: libc::POLLIN, revents: 0 },
+        ];
+        while unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(EINTR) {
+                return Err(err);
+            }
+        }
+        Ok(!shutdown.is_shutdown())
+    }
+
+    /// End the session gracefully: wait for replies to outstanding requests (interrupting
+    /// them after a timeout), destroy the filesystem and unmount it
+    fn shut_down(&mut self) -> io::Result<()> {
+        info!("Shutting down {}", self.mountpoint().display());
+        if !self.interrupts.wait_idle(SHUTDOWN_TIMEOUT) {
+            warn!("Requests still outstanding after {:?}, interrupting them", SHUTDOWN_TIMEOUT);
+            self.interrupts.interrupt_all();
+        }
+        self.destroy();
+        self.ch.unmount()
+    }
+
+    /// Destroy the filesystem like the destroy request of the kernel driver does, for
+    /// sessions that end before the kernel driver sends it. Does nothing if the filesystem
+    /// wasn't initialized or is destroyed already.
+    fn destroy(&mut self) {
+        if !self.initialized || self.destroyed {
+            return;
+        }
+        let req = Request::synthetic(self.ch.sender(), ll::Operation::Destroy, self.config, &self.interrupts);
+        self.filesystem.destroy(&req);
+        self.destroyed = true;
+    }
+
     ///
     /// Read a single request from the fuse channel
     /// this can be non blocking if `ll::channel::set_nonblocking` is set on the fuse channel
     /// 
     #[inline]
     pub fn receive<'a>(&mut self, buffer: &'a mut Vec<u8>) -> RecvResult<'a> {
-        match self.ch.receive(buffer) {
-            Ok(_) => match Request::new(self.ch.sender(), buffer) {
+        match self.read(buffer) {
+            Ok(_) => match Request::new(self.ch.sender(), buffer, self.config, &self.interrupts) {
                 // Return request
-                Some(request) => RecvResult::Some(request),
+                Ok(request) => RecvResult::Some(request),
+                // Unknown operations are already replied with ENOSYS
+                Err(RequestError::UnknownOperation(..)) => RecvResult::Retry,
                 // Should drop on illegal request
-                None => RecvResult::Drop(None),
+                Err(_) => RecvResult::Drop(None),
             },
             Err(err) => match err.raw_os_error() {
                 // The operation was interupted by the kernel, the user or fuse explicitly request a retry
@@ -120,6 +295,17 @@ impl<FS: Filesystem> Session<FS> {
         }
     }
 
+    /// Read a request from the channel, through the splice pipe if there is one
+    fn read(&mut self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(rx) = self.splice.as_mut() {
+                return self.ch.receive_splice(rx, buffer, self.config.negotiated_minor());
+            }
+        }
+        self.ch.receive(buffer)
+    }
+
     ///
     /// Set fuse fd as evented fd (so its can be used with epoll or select)
     /// Also wrap the `Session` into an `EventedSession` that is usable in the MIO world
@@ -127,85 +313,414 @@ impl<FS: Filesystem> Session<FS> {
     pub fn evented(mut self) -> io::Result<EventedSession<FS>> {
         // Set the current session (raw fd) as evented fd
         self.ch.evented()?;
-        Ok(EventedSession(self))
+        let buffer = REQUEST_BUFFERS.take(self.config.buffer_size());
+        Ok(EventedSession { session: self, buffer, ended: false })
+    }
+}
+
+/// Create a pipe to receive requests through, which must hold the largest request of the
+/// connection. Returns `None` if the pipe can't be that large (splicing isn't used then).
+#[cfg(target_os = "linux")]
+pub(crate) fn request_pipe(config: &KernelConfig) -> Option<RequestPipe> {
+    let size = config.buffer_size();
+    RequestPipe::new(size)
+        .inspect_err(|err| warn!("Not splicing requests, failed to create pipe of {} bytes: {}", size, err))
+        .ok()
+}
+
+/// Configuration of the multi-threaded session loop (see `Session::run_mt`)
+#[derive(Clone, Copy, Debug)]
+pub struct LoopConfig {
+    /// Maximum number of worker threads
+    pub max_threads: usize,
+    /// Minimum number of idle worker threads. Another worker is started whenever a
+    /// request leaves fewer idle workers than this (and `max_threads` isn't reached).
+    pub min_idle_threads: usize,
+    /// Maximum number of idle worker threads. A worker exits after handling a request
+    /// if at least this many other workers are idle.
+    pub max_idle_threads: usize,
+    /// Give each worker its own channel to the kernel driver (Linux only, see
+    /// `Channel::clone_fd`). Otherwise workers share the session's channel.
+    pub clone_fd: bool,
+}
+
+impl Default for LoopConfig {
+    fn default() -> LoopConfig {
+        LoopConfig {
+            max_threads: 10,
+            min_idle_threads: 1,
+            max_idle_threads: 10,
+            clone_fd: true,
+        }
+    }
+}
+
+impl<FS: SyncFilesystem + 'static> Session<Arc<FS>> {
+    /// Run a multi-threaded session loop. Requests are received and dispatched by a pool
+    /// of worker threads, each with its own buffer, so filesystem methods run concurrently.
+    /// The init request is handled before any worker is started. A worker that fails to
+    /// receive a request ends with the error, while the other workers keep serving requests.
+    /// Returns once all workers ended, i.e. after the filesystem was unmounted, with the first
+    /// error a worker ended with.
+    pub fn run_mt(&mut self, config: &LoopConfig) -> io::Result<()> {
+        let mut buffer = REQUEST_BUFFERS.take(self.config.buffer_size());
+        while !self.initialized {
+            match self.receive(&mut buffer) {
+                RecvResult::Some(request) => request.dispatch(self),
+                RecvResult::Retry => continue,
+                RecvResult::Drop(None) => return Ok(()),
+                RecvResult::Drop(Some(err)) => return Err(err),
+            }
+        }
+        drop(buffer);
+
+        let pool = Arc::new(WorkerPool::new(*config));
+        pool.start();
+        if let Err(err) = self.worker(config.clone_fd).and_then(|se| WorkerPool::spawn(&pool, se)) {
+            pool.exit(false, None);
+            return Err(err);
+        }
+        let (destroyed, error) = pool.wait();
+        self.destroyed |= destroyed;
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Create a session for a worker thread that shares the filesystem and connection
+    /// state with this session
+    fn worker(&self, clone_fd: bool) -> io::Result<Session<Arc<FS>>> {
+        Ok(Session {
+            filesystem: self.filesystem.clone(),
+            ch: self.ch.clone_fd(clone_fd)?,
+            proto_major: self.proto_major,
+            proto_minor: self.proto_minor,
+            config: self.config,
+            max_write_limit: self.max_write_limit,
+            interrupts: self.interrupts.clone(),
+            retrievals: self.retrievals.clone(),
+            ready: self.ready.clone(),
+            shutdown: None,
+            #[cfg(target_os = "linux")]
+            splice: self.splice.as_ref().and_then(|_| request_pipe(&self.config)),
+            initialized: self.initialized,
+            destroyed: self.destroyed,
+        })
+    }
+
+    /// Session loop of a worker thread
+    fn run_worker(mut self, pool: Arc<WorkerPool>) {
+        let mut buffer = REQUEST_BUFFERS.take(self.config.buffer_size());
+        loop {
+            match self.receive(&mut buffer) {
+                RecvResult::Some(request) => {
+                    if pool.begin_request() {
+                        if let Err(err) = self.worker(pool.config.clone_fd).and_then(|se| WorkerPool::spawn(&pool, se)) {
+                            error!("Failed to start worker thread: {}", err);
+                            pool.exit(false, None);
+                        }
+                    }
+                    request.dispatch(&mut self);
+                    if !pool.end_request() {
+                        return;
+                    }
+                }
+                RecvResult::Retry => continue,
+                RecvResult::Drop(err) => return pool.exit(self.destroyed, err),
+            }
+        }
     }
 }
 
-impl<'a, FS: Filesystem + Send + 'a> Session<FS> {
+/// Bookkeeping of the worker threads of a multi-threaded session loop
+#[derive(Debug)]
+struct WorkerPool {
+    config: LoopConfig,
+    state: Mutex<PoolState>,
+    done: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    /// Number of running workers
+    workers: usize,
+    /// Number of workers waiting for a request
+    idle: usize,
+    /// True if a worker handled the destroy request
+    destroyed: bool,
+    /// First error a worker ended with
+    error: Option<io::Error>,
+}
+
+impl WorkerPool {
+    fn new(mut config: LoopConfig) -> WorkerPool {
+        config.max_threads = config.max_threads.max(1);
+        WorkerPool { config, state: Mutex::new(PoolState::default()), done: Condvar::new() }
+    }
+
+    /// Spawn a worker thread for the given session. The worker must already be
+    /// accounted for (see `start` and `begin_request`).
+    fn spawn<FS: SyncFilesystem + 'static>(pool: &Arc<WorkerPool>, se: Session<Arc<FS>>) -> io::Result<()> {
+        let pool = pool.clone();
+        thread::Builder::new()
+            .name("fuse-worker".to_string())
+            .spawn(move || se.run_worker(pool))
+            .map(|_| ())
+    }
+
+    /// Account for the first worker
+    fn start(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.workers += 1;
+        state.idle += 1;
+    }
+
+    /// Called by a worker that received a request. Returns true if another worker needs
+    /// to be started, which is then already accounted for as idle.
+    fn begin_request(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.idle -= 1;
+        if state.idle < self.config.min_idle_threads && state.workers < self.config.max_threads {
+            state.workers += 1;
+            state.idle += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Called by a worker that handled a request. Returns false if the worker should exit
+    /// because there are enough idle workers.
+    fn end_request(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.idle >= self.config.max_idle_threads && state.workers > 1 {
+            state.workers -= 1;
+            false
+        } else {
+            state.idle += 1;
+            true
+        }
+    }
+
+    /// Called by an idle worker that ends (or failed to start)
+    fn exit(&self, destroyed: bool, err: Option<io::Error>) {
+        let mut state = self.state.lock().unwrap();
+        state.workers -= 1;
+        state.idle -= 1;
+        state.destroyed |= destroyed;
+        if state.error.is_none() {
+            state.error = err;
+        }
+        if state.workers == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    /// Wait until all workers ended
+    fn wait(&self) -> (bool, Option<io::Error>) {
+        let mut state = self.state.lock().unwrap();
+        while state.workers > 0 {
+            state = self.done.wait(state).unwrap();
+        }
+        (state.destroyed, state.error.take())
+    }
+}
+
+impl<FS: Filesystem + Send + 'static> Session<FS> {
     /// Run the session loop in a background thread
-    pub unsafe fn spawn(self) -> io::Result<BackgroundSession<'a>> {
+    pub fn spawn(self) -> io::Result<BackgroundSession<'static>> {
         BackgroundSession::new(self)
     }
 }
 
+impl<'scope, FS: Filesystem + Send + 'scope> Session<FS> {
+    /// Run the session loop in a thread of the given scope, which allows the filesystem to
+    /// borrow data that outlives the scope
+    pub fn spawn_scoped<'env>(self, scope: &'scope thread::Scope<'scope, 'env>) -> io::Result<BackgroundSession<'scope>> {
+        BackgroundSession::scoped(scope, self)
+    }
+}
+
 impl<FS: Filesystem> Drop for Session<FS> {
     fn drop(&mut self) {
-        info!("Unmounted {}", self.mountpoint().display());
+        // Worker sessions of a multi-threaded loop don't own the mount point
+        if let Some(mountpoint) = self.ch.mountpoint() {
+            info!("Unmounted {}", mountpoint.display());
+        }
+    }
+}
+
+/// Handle of the thread that runs a background session
+#[derive(Debug)]
+enum SessionThread<'a> {
+    Spawned(thread::JoinHandle<io::Result<()>>),
+    Scoped(thread::ScopedJoinHandle<'a, io::Result<()>>),
+}
+
+impl SessionThread<'_> {
+    /// Wait for the thread to end and return the result of its session loop
+    fn join(self) -> thread::Result<io::Result<()>> {
+        match self {
+            SessionThread::Spawned(handle) => handle.join(),
+            SessionThread::Scoped(handle) => handle.join(),
+        }
     }
 }
 
 /// The background session data structure
+#[derive(Debug)]
 pub struct BackgroundSession<'a> {
     /// Path of the mounted filesystem
     pub mountpoint: PathBuf,
-    /// Thread guard of the background session
-    pub guard: JoinGuard<'a, io::Result<()>>,
+    /// Thread of the background session (none after it was joined)
+    thread: Option<SessionThread<'a>>,
+    /// Notifier of the background session
+    notifier: Notifier,
+    /// Readiness of the background session
+    ready: Arc<Readiness>,
 }
 
-impl<'a> BackgroundSession<'a> {
+impl BackgroundSession<'static> {
     /// Create a new background session for the given session by running its
     /// session loop in a background thread. If the returned handle is dropped,
     /// the filesystem is unmounted and the given session ends.
-    pub unsafe fn new<FS: Filesystem + Send + 'a>(se: Session<FS>) -> io::Result<BackgroundSession<'a>> {
+    pub fn new<FS: Filesystem + Send + 'static>(se: Session<FS>) -> io::Result<BackgroundSession<'static>> {
         let mountpoint = se.mountpoint().to_path_buf();
-        let guard = scoped(move || {
-            let mut se = se;
-            se.run()
-        });
-        Ok(BackgroundSession { mountpoint: mountpoint, guard: guard })
+        let notifier = se.notifier();
+        let ready = se.ready.clone();
+        let handle = thread::Builder::new()
+            .name("fuse-session".to_string())
+            .spawn(move || run_background(se))?;
+        Ok(BackgroundSession { mountpoint, thread: Some(SessionThread::Spawned(handle)), notifier, ready })
+    }
+}
+
+impl<'scope> BackgroundSession<'scope> {
+    /// Create a new background session for the given session by running its session loop
+    /// in a thread of the given scope (see `std::thread::scope`). The filesystem only needs
+    /// to live as long as the scope. If the returned handle is dropped, the filesystem is
+    /// unmounted and the given session ends.
+    pub fn scoped<'env, FS: Filesystem + Send + 'scope>(scope: &'scope thread::Scope<'scope, 'env>, se: Session<FS>) -> io::Result<BackgroundSession<'scope>> {
+        let mountpoint = se.mountpoint().to_path_buf();
+        let notifier = se.notifier();
+        let ready = se.ready.clone();
+        let handle = thread::Builder::new()
+            .name("fuse-session".to_string())
+            .spawn_scoped(scope, move || run_background(se))?;
+        Ok(BackgroundSession { mountpoint, thread: Some(SessionThread::Scoped(handle)), notifier, ready })
+    }
+}
+
+impl<'a> BackgroundSession<'a> {
+    /// Returns a notifier to send notifications to the kernel driver
+    pub fn notifier(&self) -> Notifier {
+        self.notifier.clone()
+    }
+
+    /// Block until the session handled the init request of the kernel driver, after which
+    /// the mount point can be used. Returns a `TimedOut` error if this didn't happen within
+    /// the given timeout. If the init request was rejected (e.g. because of an unsupported
+    /// ABI version or an error returned by `Filesystem::init`) or the session ended before,
+    /// the error is returned.
+    pub fn wait_ready(&self, timeout: Duration) -> io::Result<()> {
+        self.ready.wait_timeout(timeout)
+    }
+
+    /// Returns a future that completes once the session handled the init request of the
+    /// kernel driver, like `wait_ready` but without blocking and without timeout.
+    pub fn ready(&self) -> WaitReady<'_> {
+        WaitReady::new(&self.ready)
     }
+
+    /// Wait until the session ends, i.e. until the filesystem is unmounted by other means
+    /// (e.g. by `fusermount -u`), and return the result of the session loop
+    pub fn join(mut self) -> io::Result<()> {
+        let res = self.thread.take().unwrap().join();
+        res.unwrap_or_else(|err| panic::resume_unwind(err))
+    }
+
+    /// Unmount the filesystem and wait until the session ended, i.e. until the kernel
+    /// driver sent the destroy request and the session loop returned. Returns the result
+    /// of the session loop. If unmounting fails (e.g. because the filesystem is busy), the
+    /// error is returned and the session keeps running until the filesystem is unmounted
+    /// by other means.
+    pub fn unmount(mut self) -> io::Result<()> {
+        let thread = self.thread.take().unwrap();
+        info!("Unmounting {}", self.mountpoint.display());
+        channel::unmount(&self.mountpoint)?;
+        thread.join().unwrap_or_else(|err| panic::resume_unwind(err))
+    }
+}
+
+/// Run the session loop of a background session. If the session ends before the init
+/// request was handled, waiters for readiness get the session's error.
+fn run_background<FS: Filesystem>(mut se: Session<FS>) -> io::Result<()> {
+    let res = se.run();
+    let err = match &res {
+        Ok(()) => ENODEV,
+        Err(err) => err.raw_os_error().unwrap_or(EIO),
+    };
+    se.ready.set(Err(err));
+    res
 }
 
 impl<'a> Drop for BackgroundSession<'a> {
     fn drop(&mut self) {
-        info!("Unmounting {}", self.mountpoint.display());
-        // Unmounting the filesystem will eventually end the session loop,
-        // drop the session and hence end the background thread.
-        match channel::unmount(&self.mountpoint) {
-            Ok(()) => (),
-            Err(err) => error!("Failed to unmount {}: {}", self.mountpoint.display(), err),
+        if let Some(thread) = self.thread.take() {
+            info!("Unmounting {}", self.mountpoint.display());
+            // Unmounting the filesystem will eventually end the session loop,
+            // drop the session and hence end the background thread.
+            match channel::unmount(&self.mountpoint) {
+                Ok(()) => match thread.join() {
+                    Ok(Ok(())) => (),
+                    Ok(Err(err)) => error!("Session of {} failed: {}", self.mountpoint.display(), err),
+                    Err(_) => error!("Session thread of {} panicked", self.mountpoint.display()),
+                },
+                Err(err) => error!("Failed to unmount {}: {}", self.mountpoint.display(), err),
+            }
         }
     }
 }
 
-// replace with #[derive(Debug)] if Debug ever gets implemented for
-// thread_scoped::JoinGuard
-impl<'a> fmt::Debug for BackgroundSession<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "BackgroundSession {{ mountpoint: {:?}, guard: JoinGuard<()> }}", self.mountpoint)
-    }
+/// Outcome of `EventedSession::try_handle`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandleStatus {
+    /// A request was received and dispatched to the filesystem
+    Handled,
+    /// No request is available right now, wait until the session is readable again
+    WouldBlock,
+    /// The filesystem was unmounted, no more requests will arrive
+    Ended,
 }
 
 ///
 /// A FuseEvented provides a way to use the FUSE filesystem in a custom event
 /// loop. It implements the mio Evented trait, so it can be polled for
-/// readiness.
+/// readiness. Once readable, `try_handle` should be called until it returns
+/// `HandleStatus::WouldBlock` (or `HandleStatus::Ended` after an unmount).
+///
+/// Dropping the session destroys the filesystem and unmounts it, unless it was
+/// unmounted already. Outstanding replies aren't waited for, since an event loop
+/// may only be able to complete them while it keeps running.
 ///
-// TODO: Drop
 #[derive(Debug)]
-pub struct EventedSession<FS: Filesystem>(Session<FS>);
+pub struct EventedSession<FS: Filesystem> {
+    session: Session<FS>,
+    /// Buffer for receiving requests, reused for every request
+    buffer: PooledBuffer<'static>,
+    /// True once the filesystem was unmounted
+    ended: bool,
+}
 
 impl<FS: Filesystem>  Evented for EventedSession<FS> {
     fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
-        let raw_fd = unsafe {self.0.ch.raw_fd() };
+        let raw_fd = unsafe {self.session.ch.raw_fd() };
         EventedFd(&raw_fd).register(poll, token, interest, opts)
     }
     fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
-        let raw_fd = unsafe {self.0.ch.raw_fd() };
+        let raw_fd = unsafe {self.session.ch.raw_fd() };
         EventedFd(&raw_fd).reregister(poll, token, interest, opts)
     }
     fn deregister(&self, poll: &Poll) -> io::Result<()> {
-        let raw_fd = unsafe {self.0.ch.raw_fd() };
+        let raw_fd = unsafe {self.session.ch.raw_fd() };
         EventedFd(&raw_fd).deregister(poll)
     }
 }
@@ -213,16 +728,363 @@ impl<FS: Filesystem>  Evented for EventedSession<FS> {
 impl<FS: Filesystem> EventedSession<FS> {
 
     ///
-    /// Read a request from the fuse fd and process it with the filesystem
+    /// Read a request from the fuse fd and process it with the filesystem. Requests
+    /// that were interrupted before they could be read are skipped.
     /// 
-    pub fn try_handle(&mut self, buffer: &mut Vec<u8>) -> io::Result<()> {
-        match self.0.receive(buffer) {
-                RecvResult::Some(request) => {
-                    request.dispatch(&mut self.0);
-                    Ok(())
+    pub fn try_handle(&mut self) -> io::Result<HandleStatus> {
+        if self.ended {
+            return Ok(HandleStatus::Ended);
+        }
+        loop {
+            // The buffer needs to grow once the max write size is negotiated
+            let size = self.session.config.buffer_size();
+            if self.buffer.capacity() < size {
+                self.buffer = REQUEST_BUFFERS.take(size);
+            }
+            let se = &mut self.session;
+            match se.read(&mut self.buffer) {
+                Ok(()) => match Request::new(se.ch.sender(), &self.buffer, se.config, &se.interrupts) {
+                    Ok(request) => {
+                        request.dispatch(se);
+                        return Ok(HandleStatus::Handled);
+                    }
+                    // Unknown operations are already replied with ENOSYS
+                    Err(RequestError::UnknownOperation(..)) => continue,
+                    // Should drop on illegal request
+                    Err(_) => break,
+                },
+                Err(err) => match err.raw_os_error() {
+                    Some(EAGAIN) => return Ok(HandleStatus::WouldBlock),
+                    // The request was interrupted or the read was interrupted by a signal
+                    Some(ENOENT) | Some(EINTR) => continue,
+                    // Filesystem was unmounted
+                    Some(ENODEV) => break,
+                    _ => return Err(err),
                 },
-                RecvResult::Drop(Some(err)) => Err(err),
-                _ => Ok(())
+            }
+        }
+        self.ended = true;
+        Ok(HandleStatus::Ended)
+    }
+
+    /// Returns true once the filesystem was unmounted
+    pub fn is_ended(&self) -> bool {
+        self.ended
+    }
+}
+
+impl<FS: Filesystem> Drop for EventedSession<FS> {
+    fn drop(&mut self) {
+        self.session.destroy();
+        if !self.ended {
+            if let Err(err) = self.session.ch.unmount() {
+                error!("Failed to unmount {}: {}", self.session.mountpoint().display(), err);
+            }
         }
     }
 }
+
+/// A session that runs an asynchronous filesystem in a tokio runtime. The session loop waits
+/// for requests on the nonblocking channel and runs each request as a task, so many requests
+/// can be outstanding at once.
+#[cfg(feature = "tokio_support")]
+#[derive(Debug)]
+pub struct AsyncSession<FS: AsyncFilesystem>(Session<AsyncDispatcher<FS>>);
+
+#[cfg(feature = "tokio_support")]
+impl<FS: AsyncFilesystem> AsyncSession<FS> {
+    /// Create a new session by mounting the given filesystem to the given mountpoint
+    pub fn new(filesystem: FS, mountpoint: &Path, options: &[&OsStr]) -> io::Result<AsyncSession<FS>> {
+        let mut se = Session::new(AsyncDispatcher::new(filesystem), mountpoint, options)?;
+        se.ch.evented()?;
+        Ok(AsyncSession(se))
+    }
+
+    /// Return path of the mounted filesystem
+    pub fn mountpoint(&self) -> &Path {
+        self.0.mountpoint()
+    }
+
+    /// Returns a notifier to send notifications to the kernel driver
+    pub fn notifier(&self) -> Notifier {
+        self.0.notifier()
+    }
+
+    /// Run the session loop that receives kernel requests and spawns a task for each of them.
+    /// Must be called within a tokio runtime. Returns once the filesystem is unmounted, or with
+    /// an error if a request can't be received or is malformed.
+    pub async fn run(&mut self) -> io::Result<()> {
+        let fd = AsyncFd::with_interest(*unsafe { self.0.ch.raw_fd() }, Interest::READABLE)?;
+        let mut buffer = REQUEST_BUFFERS.take(self.0.config.buffer_size());
+        loop {
+            // The buffer needs to grow once the max write size is negotiated
+            if buffer.capacity() < self.0.config.buffer_size() {
+                buffer = REQUEST_BUFFERS.take(self.0.config.buffer_size());
+            }
+            let mut guard = fd.readable().await?;
+            if let Err(err) = self.0.ch.receive(&mut buffer) {
+                match err.raw_os_error() {
+                    // No request available, wait until the channel is readable again
+                    Some(EAGAIN) => {
+                        guard.clear_ready();
+                        continue;
+                    }
+                    Some(ENOENT) | Some(EINTR) => continue,
+                    Some(ENODEV) => return Ok(()),
+                    _ => return Err(err),
+                }
+            }
+            match Request::new(self.0.ch.sender(), &buffer, self.0.config, &self.0.interrupts) {
+                Ok(request) => request.dispatch(&mut self.0),
+                // Unknown operations are already replied with ENOSYS
+                Err(RequestError::UnknownOperation(..)) => continue,
+                // The session can't go on after a malformed request, which is no unmount
+                Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{run_background, LoopConfig, Session, WorkerPool};
+    use crate::channel::Channel;
+    use crate::kernel_config::KernelConfig;
+    use crate::request::Request;
+    use crate::{FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, SyncFilesystem};
+    use fuse_abi::{fuse_getattr_in, fuse_in_header, fuse_init_in, fuse_opcode, fuse_read_in};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::os::unix::io::FromRawFd;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+    use std::{mem, slice};
+
+    /// Allocator that counts the allocations of each thread
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+    #[test]
+    fn worker_pool_spawns_idle_workers() {
+        let config = LoopConfig { max_threads: 2, min_idle_threads: 1, max_idle_threads: 10, clone_fd: false };
+        let pool = WorkerPool::new(config);
+        pool.start();
+        // The only worker gets busy, so another one is needed
+        assert!(pool.begin_request());
+        // No more workers than max_threads
+        assert!(!pool.begin_request());
+        assert!(pool.end_request());
+        assert!(pool.end_request());
+        let state = pool.state.lock().unwrap();
+        assert_eq!(state.workers, 2);
+        assert_eq!(state.idle, 2);
+    }
+
+    #[test]
+    fn worker_pool_ends_surplus_workers() {
+        let config = LoopConfig { max_threads: 10, min_idle_threads: 1, max_idle_threads: 1, clone_fd: false };
+        let pool = WorkerPool::new(config);
+        pool.start();
+        assert!(pool.begin_request());
+        // One worker is idle already, so the busy one exits after its request
+        assert!(!pool.end_request());
+        pool.exit(true, None);
+        let (destroyed, error) = pool.wait();
+        assert!(destroyed);
+        assert!(error.is_none());
+    }
+
+    struct NullFS;
+
+    impl SyncFilesystem for NullFS {}
+
+    #[test]
+    fn run_mt_worker_error() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        unsafe { libc::close(fds[0]); }
+        // Reading from the write end of a pipe fails, which ends the only worker
+        let mut se = Session::with_channel(Arc::new(NullFS), Channel::from_raw_fd(fds[1]));
+        se.initialized = true;
+        let config = LoopConfig { max_threads: 2, min_idle_threads: 1, max_idle_threads: 2, clone_fd: false };
+        let err = se.run_mt(&config).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+    }
+
+    struct AttrFS;
+
+    impl Filesystem for AttrFS {
+        fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+            let attr = FileAttr {
+                ino,
+                size: 4,
+                blocks: 1,
+                atime: UNIX_EPOCH,
+                mtime: UNIX_EPOCH,
+                ctime: UNIX_EPOCH,
+                crtime: UNIX_EPOCH,
+                kind: FileType::RegularFile,
+                perm: 0o644,
+                nlink: 1,
+                uid: 0,
+                gid: 0,
+                rdev: 0,
+                flags: 0,
+            };
+            reply.attr(&Duration::from_secs(1), &attr);
+        }
+
+        fn read(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _size: u32, reply: ReplyData) {
+            reply.data(b"data");
+        }
+    }
+
+    #[repr(C)]
+    struct RawRequest<T> {
+        header: fuse_in_header,
+        arg: T,
+    }
+
+    /// Returns the raw bytes of a request with the given opcode, unique id and argument
+    fn raw_request<T>(opcode: fuse_opcode, unique: u64, arg: T) -> RawRequest<T> {
+        let header = fuse_in_header {
+            len: mem::size_of::<RawRequest<T>>() as u32,
+            opcode: opcode as u32,
+            unique,
+            nodeid: 1,
+            uid: 0,
+            gid: 0,
+            pid: 0,
+            total_extlen: 0,
+            padding: 0,
+        };
+        RawRequest { header, arg }
+    }
+
+    /// Dispatch the given request and return the number of allocations it took
+    fn count_allocations<FS: Filesystem, T>(se: &mut Session<FS>, request: &RawRequest<T>) -> usize {
+        let data = unsafe { slice::from_raw_parts(request as *const RawRequest<T> as *const u8, mem::size_of::<RawRequest<T>>()) };
+        let before = ALLOCATIONS.with(Cell::get);
+        let req = Request::new(se.ch.sender(), data, se.config, &se.interrupts).unwrap();
+        req.dispatch(se);
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    #[test]
+    fn reply_without_allocations() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let mut reader = unsafe { File::from_raw_fd(fds[0]) };
+        let mut se = Session::with_channel(AttrFS, Channel::from_raw_fd(fds[1]));
+        let init = fuse_init_in { major: 7, minor: 45, max_readahead: 0, flags: 0, flags2: 0, unused: [0; 11] };
+        se.config = KernelConfig::new(&init);
+        se.initialized = true;
+        let getattr = raw_request(fuse_opcode::FUSE_GETATTR, 2, fuse_getattr_in { getattr_flags: 0, dummy: 0, fh: 0 });
+        let read = raw_request(fuse_opcode::FUSE_READ, 3, fuse_read_in {
+            fh: 0,
+            offset: 0,
+            size: 4,
+            read_flags: 0,
+            lock_owner: 0,
+            flags: 0,
+            padding: 0,
+        });
+        // The first requests may initialize logging state
+        count_allocations(&mut se, &getattr);
+        count_allocations(&mut se, &read);
+        assert_eq!(count_allocations(&mut se, &getattr), 0);
+        assert_eq!(count_allocations(&mut se, &read), 0);
+        drop(se);
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        // Two attribute replies and two data replies
+        assert_eq!(bytes.len(), 2 * (16 + mem::size_of::<fuse_abi::fuse_attr_out>()) + 2 * (16 + 4));
+    }
+
+    struct DestroyFS(Arc<AtomicBool>);
+
+    impl Filesystem for DestroyFS {
+        fn destroy(&mut self, req: &Request<'_>) {
+            assert_eq!(req.unique(), 0);
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn destroy_without_kernel_request() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let reader = unsafe { File::from_raw_fd(fds[0]) };
+        let destroyed = Arc::default();
+        let mut se = Session::with_channel(DestroyFS(Arc::clone(&destroyed)), Channel::from_raw_fd(fds[1]));
+        se.initialized = true;
+        // A request in flight with unique id 0 isn't affected
+        se.interrupts.register(0);
+        se.destroy();
+        assert!(destroyed.load(Ordering::SeqCst));
+        assert!(se.destroyed);
+        assert!(!se.interrupts.wait_idle(Duration::from_millis(1)));
+        drop(reader);
+    }
+
+    struct RejectFS;
+
+    impl Filesystem for RejectFS {
+        fn init(&mut self, _req: &Request<'_>, _config: &mut KernelConfig) -> Result<(), libc::c_int> {
+            Err(libc::EPERM)
+        }
+    }
+
+    #[test]
+    fn init_rejected() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_SEQPACKET, 0, fds.as_mut_ptr()) }, 0);
+        let mut kernel = unsafe { File::from_raw_fd(fds[0]) };
+        let se = Session::with_channel(RejectFS, Channel::from_raw_fd(fds[1]));
+        let ready = se.ready.clone();
+        let handle = std::thread::spawn(move || run_background(se));
+        let init = raw_request(fuse_opcode::FUSE_INIT, 1, fuse_init_in {
+            major: 7,
+            minor: 45,
+            max_readahead: 0,
+            flags: 0,
+            flags2: 0,
+            unused: [0; 11],
+        });
+        let data = unsafe { slice::from_raw_parts(&init as *const RawRequest<fuse_init_in> as *const u8, mem::size_of_val(&init)) };
+        kernel.write_all(data).unwrap();
+        // The error of the filesystem is replied and waiting for the session returns it
+        let mut reply = [0; 64];
+        assert_eq!(kernel.read(&mut reply).unwrap(), 16);
+        assert_eq!(reply[4..8], (-libc::EPERM).to_ne_bytes());
+        let err = ready.wait_timeout(Duration::from_secs(10)).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+        drop(kernel);
+        handle.join().unwrap().unwrap();
+    }
+}
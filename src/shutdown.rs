@@ -0,0 +1,134 @@
+//! Graceful shutdown
+//!
+//! A shutdown token tells a session loop started with `Session::run_until` to stop receiving
+//! requests, finish outstanding requests (interrupting them after a timeout), destroy the
+//! filesystem and unmount it. The token can be triggered by another thread or by the SIGINT
+//! and SIGTERM signals.
+
+use libc::{c_int, c_void};
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Arc;
+use std::{mem, ptr};
+
+/// Write end of the pipe of the token that is triggered by signals (-1 if none)
+static SIGNAL_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Signal handler that triggers the token registered for signals. Only async-signal-safe
+/// functions may be called here, so it just writes to the token's pipe.
+extern "C" fn handle_signal(_signal: c_int) {
+    let fd = SIGNAL_FD.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let byte = 1u8;
+        unsafe { libc::write(fd, &byte as *const u8 as *const c_void, 1); }
+    }
+}
+
+/// Token to shut down a session gracefully (see `Session::run_until`). Clones of a token
+/// share its state, so any clone can trigger the shutdown.
+#[derive(Clone, Debug)]
+pub struct ShutdownToken {
+    state: Arc<ShutdownState>,
+}
+
+#[derive(Debug)]
+struct ShutdownState {
+    /// True if the shutdown was triggered
+    triggered: AtomicBool,
+    /// Pipe that becomes readable once the shutdown is triggered, so that a session loop
+    /// can wait for requests and the shutdown at once
+    read_fd: OwnedFd,
+    write_fd: OwnedFd,
+}
+
+impl ShutdownToken {
+    /// Create a new token that isn't triggered yet
+    pub fn new() -> io::Result<ShutdownToken> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Owning the fds right away closes them if they can't be set up
+        let (read_fd, write_fd) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        for &fd in &fds {
+            if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0
+                || unsafe { libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK) } < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let state = ShutdownState { triggered: AtomicBool::new(false), read_fd, write_fd };
+        Ok(ShutdownToken { state: Arc::new(state) })
+    }
+
+    /// Trigger the shutdown
+    pub fn shutdown(&self) {
+        if !self.state.triggered.swap(true, Ordering::SeqCst) {
+            let byte = 1u8;
+            unsafe { libc::write(self.state.write_fd.as_raw_fd(), &byte as *const u8 as *const c_void, 1); }
+        }
+    }
+
+    /// Returns true if the shutdown was triggered
+    pub fn is_shutdown(&self) -> bool {
+        if self.state.triggered.load(Ordering::SeqCst) {
+            return true;
+        }
+        // A signal handler may have written to the pipe
+        let mut pollfd = libc::pollfd { fd: self.state.read_fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(&mut pollfd, 1, 0) } > 0 {
+            self.state.triggered.store(true, Ordering::SeqCst);
+            return true;
+        }
+        false
+    }
+
+    /// Trigger the shutdown when the process receives SIGINT or SIGTERM. This replaces the
+    /// handlers of these signals and only one token can be triggered by signals at a time.
+    /// The handlers are reset after the first signal, so a second signal terminates the
+    /// process as usual.
+    pub fn trigger_on_signals(&self) -> io::Result<()> {
+        SIGNAL_FD.store(self.state.write_fd.as_raw_fd(), Ordering::SeqCst);
+        for &signal in &[libc::SIGINT, libc::SIGTERM] {
+            let mut action: libc::sigaction = unsafe { mem::zeroed() };
+            action.sa_sigaction = handle_signal as extern "C" fn(c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESETHAND;
+            unsafe { libc::sigemptyset(&mut action.sa_mask); }
+            if unsafe { libc::sigaction(signal, &action, ptr::null_mut()) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the fd that becomes readable once the shutdown is triggered
+    pub(crate) fn fd(&self) -> RawFd {
+        self.state.read_fd.as_raw_fd()
+    }
+}
+
+impl Drop for ShutdownState {
+    fn drop(&mut self) {
+        // Make sure a signal handler doesn't write to the fd after it's closed (the fds are
+        // closed when the fields are dropped afterwards)
+        let _ = SIGNAL_FD.compare_exchange(self.write_fd.as_raw_fd(), -1, Ordering::SeqCst, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ShutdownToken;
+    use std::thread;
+
+    #[test]
+    fn shutdown_from_other_thread() {
+        let token = ShutdownToken::new().unwrap();
+        assert!(!token.is_shutdown());
+        let other = token.clone();
+        thread::spawn(move || other.shutdown()).join().unwrap();
+        assert!(token.is_shutdown());
+        let mut pollfd = libc::pollfd { fd: token.fd(), events: libc::POLLIN, revents: 0 };
+        assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 0) }, 1);
+    }
+}
@@ -0,0 +1,416 @@
+//! Zero-copy data transfer with splice (Linux only)
+//!
+//! The kernel driver can move request and reply data between the FUSE channel and a pipe
+//! without copying it through userspace. Data in a pipe can then be spliced into a file, or
+//! data of a file can be spliced into a pipe. Page-sized data is only moved by reference.
+
+use fuse_abi::consts::FUSE_COMPAT_WRITE_IN_SIZE;
+use fuse_abi::{fuse_in_header, fuse_opcode, fuse_write_in};
+use libc::{c_int, c_uint, c_void, loff_t, size_t};
+use std::cell::RefCell;
+use std::os::unix::io::RawFd;
+use std::{cmp, fmt, io, mem, ptr};
+
+/// Move data between a file descriptor and a pipe. Offsets are used for (and updated in)
+/// files that aren't a pipe, `None` uses the file position.
+pub(crate) fn splice(fd_in: c_int, off_in: Option<&mut i64>, fd_out: c_int, off_out: Option<&mut i64>, len: usize, flags: c_uint) -> io::Result<usize> {
+    let off_in = off_in.map_or(ptr::null_mut(), |off| off as *mut i64 as *mut loff_t);
+    let off_out = off_out.map_or(ptr::null_mut(), |off| off as *mut i64 as *mut loff_t);
+    let rc = unsafe { libc::splice(fd_in, off_in, fd_out, off_out, len as size_t, flags) };
+    if rc < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(rc as usize)
+    }
+}
+
+/// A pipe to splice data through
+#[derive(Debug)]
+pub(crate) struct Pipe {
+    read: c_int,
+    write: c_int,
+    /// Capacity of the pipe in bytes
+    size: usize,
+}
+
+impl Pipe {
+    /// Create a new pipe. The pipe is nonblocking, so that splicing more data than fits into
+    /// the pipe fails instead of blocking forever.
+    pub(crate) fn new() -> io::Result<Pipe> {
+        let mut fds: [c_int; 2] = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut pipe = Pipe { read: fds[0], write: fds[1], size: 0 };
+        let size = unsafe { libc::fcntl(pipe.write, libc::F_GETPIPE_SZ) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        pipe.size = size as usize;
+        Ok(pipe)
+    }
+
+    /// Grow the pipe to hold at least the given number of bytes. Fails if this exceeds the
+    /// maximum pipe size of unprivileged processes (/proc/sys/fs/pipe-max-size).
+    pub(crate) fn reserve(&mut self, size: usize) -> io::Result<()> {
+        if size > self.size {
+            let rc = unsafe { libc::fcntl(self.write, libc::F_SETPIPE_SZ, size as c_int) };
+            if rc < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.size = rc as usize;
+        }
+        Ok(())
+    }
+
+    /// Returns the read end of the pipe
+    pub(crate) fn read_fd(&self) -> c_int {
+        self.read
+    }
+
+    /// Returns the write end of the pipe
+    pub(crate) fn write_fd(&self) -> c_int {
+        self.write
+    }
+
+    /// Read exactly `len` bytes from the pipe and append them to the given buffer, which
+    /// must have enough spare capacity
+    pub(crate) fn read_into(&self, buffer: &mut Vec<u8>, len: usize) -> io::Result<()> {
+        assert!(buffer.capacity() - buffer.len() >= len);
+        let end = buffer.len() + len;
+        while buffer.len() < end {
+            let rc = unsafe {
+                libc::read(self.read, buffer.as_mut_ptr().add(buffer.len()) as *mut c_void, (end - buffer.len()) as size_t)
+            };
+            if rc < 0 {
+                return Err(io::Error::last_os_error());
+            } else if rc == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            unsafe { buffer.set_len(buffer.len() + rc as usize); }
+        }
+        Ok(())
+    }
+
+    /// Write all of the given data into the pipe
+    pub(crate) fn write_all(&self, data: &[u8]) -> io::Result<()> {
+        let mut pos = 0;
+        while pos < data.len() {
+            let rc = unsafe { libc::write(self.write, data[pos..].as_ptr() as *const c_void, (data.len() - pos) as size_t) };
+            if rc < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            pos += rc as usize;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+/// Pipe of a session to receive requests through. Everything but the data of write requests
+/// is read into the session's buffer. The data of a write request stays in the pipe until the
+/// filesystem consumes it.
+#[derive(Debug)]
+pub(crate) struct RequestPipe {
+    pipe: Pipe,
+    /// Number of bytes of write data left in the pipe
+    pending: usize,
+}
+
+impl RequestPipe {
+    /// Create a pipe that can hold requests of the given size
+    pub(crate) fn new(size: usize) -> io::Result<RequestPipe> {
+        let mut pipe = Pipe::new()?;
+        pipe.reserve(size)?;
+        Ok(RequestPipe { pipe, pending: 0 })
+    }
+
+    /// Receive a request from the given channel fd (can block). The request is read into the
+    /// buffer up to its capacity, except for the data of a write request. Since the buffer
+    /// then holds the request without its data, the length in its header is reduced.
+    pub(crate) fn receive(&mut self, fd: c_int, buffer: &mut Vec<u8>, proto_minor: u32) -> io::Result<()> {
+        self.discard()?;
+        buffer.clear();
+        let len = splice(fd, None, self.pipe.write_fd(), None, buffer.capacity(), 0)?;
+        let header_size = cmp::min(len, mem::size_of::<fuse_in_header>());
+        self.pipe.read_into(buffer, header_size)?;
+        let arg_size = match proto_minor {
+            0..=8 => FUSE_COMPAT_WRITE_IN_SIZE,
+            _ => mem::size_of::<fuse_write_in>(),
+        };
+        let is_write = header_size == mem::size_of::<fuse_in_header>()
+            && buffer[4..8] == (fuse_opcode::FUSE_WRITE as u32).to_ne_bytes();
+        if is_write && len > header_size + arg_size {
+            self.pipe.read_into(buffer, arg_size)?;
+            self.pending = len - buffer.len();
+            let request_len = buffer.len() as u32;
+            buffer[0..4].copy_from_slice(&request_len.to_ne_bytes());
+        } else {
+            self.pipe.read_into(buffer, len - header_size)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes of write data left in the pipe
+    pub(crate) fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Drop write data that the filesystem didn't consume
+    pub(crate) fn discard(&mut self) -> io::Result<()> {
+        let mut scratch = Vec::with_capacity(cmp::min(self.pending, 64 * 1024));
+        while self.pending > 0 {
+            let len = cmp::min(self.pending, scratch.capacity());
+            scratch.clear();
+            self.pipe.read_into(&mut scratch, len)?;
+            self.pending -= len;
+        }
+        Ok(())
+    }
+}
+
+/// Data of a write request that the kernel driver moved into a pipe. The data can be spliced
+/// into a file without copying it through userspace, or read into memory. Data that isn't
+/// consumed is dropped after the write operation returns.
+pub struct PipeData<'a> {
+    rx: &'a mut RequestPipe,
+}
+
+impl<'a> PipeData<'a> {
+    pub(crate) fn new(rx: &'a mut RequestPipe) -> PipeData<'a> {
+        PipeData { rx }
+    }
+
+    /// Returns the number of bytes left in the pipe
+    pub fn len(&self) -> usize {
+        self.rx.pending
+    }
+
+    /// Returns true if all data was consumed
+    pub fn is_empty(&self) -> bool {
+        self.rx.pending == 0
+    }
+
+    /// Move the data into the given file at the given offset, or at the file's position if
+    /// `offset` is `None`. Returns the number of bytes moved, which is less than `len` only if
+    /// the file doesn't take any more data.
+    pub fn splice_to(&mut self, fd: RawFd, offset: Option<i64>) -> io::Result<usize> {
+        let mut offset = offset;
+        let mut moved = 0;
+        while self.rx.pending > 0 {
+            let len = splice(self.rx.pipe.read_fd(), None, fd, offset.as_mut(), self.rx.pending, libc::SPLICE_F_MOVE)?;
+            if len == 0 {
+                break;
+            }
+            self.rx.pending -= len;
+            moved += len;
+        }
+        Ok(moved)
+    }
+
+    /// Read the data into memory
+    pub fn read(&mut self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(self.rx.pending);
+        self.rx.pipe.read_into(&mut data, self.rx.pending)?;
+        self.rx.pending = 0;
+        Ok(data)
+    }
+}
+
+impl<'a> fmt::Debug for PipeData<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "PipeData {{ len: {} }}", self.rx.pending)
+    }
+}
+
+thread_local! {
+    /// Pipe of the current thread to splice reply data through
+    static REPLY_PIPE: RefCell<Option<Pipe>> = const { RefCell::new(None) };
+}
+
+/// Send a reply with the given header followed by `len` bytes of the file `fd` at `offset`
+/// to the given channel fd, splicing the data through a pipe. If the file is shorter, the
+/// length in the header (its first field) is reduced. Returns `Ok(false)` if the pipe can't
+/// hold the reply, in which case nothing was sent.
+pub(crate) fn send_spliced(chfd: c_int, header: &[u8], fd: RawFd, offset: i64, len: usize, flags: c_uint) -> io::Result<bool> {
+    REPLY_PIPE.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        if cell.is_none() {
+            *cell = Some(Pipe::new()?);
+        }
+        let pipe = cell.as_mut().unwrap();
+        let total = header.len() + len;
+        if pipe.reserve(total).is_err() {
+            return Ok(false);
+        }
+        let result = splice_reply(pipe, chfd, header, fd, offset, len, flags);
+        if result.is_err() {
+            // Data may be left in the pipe, so start over with a new one
+            *cell = None;
+        }
+        result.map(|()| true)
+    })
+}
+
+/// Fill the pipe with the reply and splice it into the channel
+fn splice_reply(pipe: &Pipe, chfd: c_int, header: &[u8], fd: RawFd, offset: i64, len: usize, flags: c_uint) -> io::Result<()> {
+    pipe.write_all(header)?;
+    let mut offset = offset;
+    let mut spliced = 0;
+    while spliced < len {
+        let n = splice(fd, Some(&mut offset), pipe.write_fd(), None, len - spliced, flags)?;
+        if n == 0 {
+            break;
+        }
+        spliced += n;
+    }
+    if spliced < len {
+        // Short read at the end of the file. The header in the pipe has the wrong length
+        // and the kernel takes a reply in a single write, so send it from memory instead.
+        let mut reply = Vec::with_capacity(header.len() + spliced);
+        pipe.read_into(&mut reply, header.len() + spliced)?;
+        reply[0..4].copy_from_slice(&((header.len() + spliced) as u32).to_ne_bytes());
+        let rc = unsafe { libc::write(chfd, reply.as_ptr() as *const c_void, reply.len() as size_t) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        return Ok(());
+    }
+    let total = header.len() + len;
+    let n = splice(pipe.read_fd(), None, chfd, None, total, flags)?;
+    if n < total {
+        return Err(io::Error::from(io::ErrorKind::WriteZero));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{send_spliced, Pipe, PipeData, RequestPipe};
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::path::PathBuf;
+
+    /// Create a temporary file with the given content
+    fn temp_file(name: &str, content: &[u8]) -> (PathBuf, File) {
+        let path = std::env::temp_dir().join(format!("fuse-splice-{}-{}", std::process::id(), name));
+        let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+        file.write_all(content).unwrap();
+        (path, file)
+    }
+
+    /// Returns a write request (protocol 7.9+) with the given data
+    fn write_request(data: &[u8]) -> Vec<u8> {
+        let mut request = Vec::new();
+        request.extend(&(80 + data.len() as u32).to_ne_bytes());        // len
+        request.extend(&16u32.to_ne_bytes());                           // opcode (FUSE_WRITE)
+        request.extend(&0xdeadbeefu64.to_ne_bytes());                   // unique
+        request.extend(&0x1122u64.to_ne_bytes());                       // nodeid
+        request.extend(&[0; 16]);                                       // uid, gid, pid, padding
+        request.extend(&3u64.to_ne_bytes());                            // fh
+        request.extend(&0u64.to_ne_bytes());                            // offset
+        request.extend(&(data.len() as u32).to_ne_bytes());             // size
+        request.extend(&[0; 20]);                                       // write flags, lock owner, flags, padding
+        request.extend(data);
+        request
+    }
+
+    #[test]
+    fn receive_write_request() {
+        let channel = Pipe::new().unwrap();
+        channel.write_all(&write_request(b"hello")).unwrap();
+        let mut rx = RequestPipe::new(4096).unwrap();
+        let mut buffer = Vec::with_capacity(4096);
+        rx.receive(channel.read_fd(), &mut buffer, 31).unwrap();
+        assert_eq!(buffer.len(), 80);
+        assert_eq!(buffer[0..4], 80u32.to_ne_bytes());
+        assert_eq!(rx.pending(), 5);
+
+        let (path, mut file) = temp_file("receive", b"");
+        let mut data = PipeData::new(&mut rx);
+        assert_eq!(data.splice_to(file.as_raw_fd(), Some(0)).unwrap(), 5);
+        assert!(data.is_empty());
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"hello");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn receive_other_request() {
+        let channel = Pipe::new().unwrap();
+        let mut request = write_request(b"");
+        request[4..8].copy_from_slice(&15u32.to_ne_bytes());           // opcode (FUSE_READ)
+        channel.write_all(&request).unwrap();
+        let mut rx = RequestPipe::new(4096).unwrap();
+        let mut buffer = Vec::with_capacity(4096);
+        rx.receive(channel.read_fd(), &mut buffer, 31).unwrap();
+        assert_eq!(buffer, request);
+        assert_eq!(rx.pending(), 0);
+    }
+
+    #[test]
+    fn discard_write_data() {
+        let channel = Pipe::new().unwrap();
+        channel.write_all(&write_request(b"unused")).unwrap();
+        let mut rx = RequestPipe::new(4096).unwrap();
+        let mut buffer = Vec::with_capacity(4096);
+        rx.receive(channel.read_fd(), &mut buffer, 31).unwrap();
+        assert_eq!(rx.pending(), 6);
+        rx.discard().unwrap();
+        assert_eq!(rx.pending(), 0);
+        channel.write_all(&write_request(b"next")).unwrap();
+        rx.receive(channel.read_fd(), &mut buffer, 31).unwrap();
+        let mut data = PipeData::new(&mut rx);
+        assert_eq!(data.read().unwrap(), b"next");
+    }
+
+    #[test]
+    fn send_file_data() {
+        let (path, file) = temp_file("send", b"0123456789");
+        let (reader, writer) = pipe_files();
+        let header = [&(16u32 + 4).to_ne_bytes()[..], &[0; 12]].concat();
+        assert!(send_spliced(writer.as_raw_fd(), &header, file.as_raw_fd(), 2, 4, 0).unwrap());
+        drop(writer);
+        let bytes = read_all(reader);
+        assert_eq!(bytes[0..4], 20u32.to_ne_bytes());
+        assert_eq!(&bytes[16..], b"2345");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn send_file_data_short() {
+        let (path, file) = temp_file("short", b"0123456789");
+        let (reader, writer) = pipe_files();
+        let header = [&(16u32 + 8).to_ne_bytes()[..], &[0; 12]].concat();
+        assert!(send_spliced(writer.as_raw_fd(), &header, file.as_raw_fd(), 6, 8, 0).unwrap());
+        drop(writer);
+        let bytes = read_all(reader);
+        assert_eq!(bytes[0..4], 20u32.to_ne_bytes());
+        assert_eq!(&bytes[16..], b"6789");
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Returns both ends of a new pipe
+    fn pipe_files() -> (File, File) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+    }
+
+    /// Read everything from the given file
+    fn read_all(mut file: File) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+}
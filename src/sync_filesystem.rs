@@ -0,0 +1,456 @@
+//! Filesystems for multi-threaded sessions
+//!
+//! A multi-threaded session handles requests in several worker threads at once, so the
+//! filesystem is shared between threads and its methods take `&self`. Synchronization (like
+//! a lock around an inode table) is up to the implementation, which allows to lock only what
+//! an operation actually needs.
+
+use libc::{c_int, ENOSYS};
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::{FallocateFlags, Filesystem, KernelConfig, PollHandle, RenameFlags, Request};
+use crate::{ReplyAttr, ReplyBmap, ReplyCreate, ReplyData, ReplyDirectory, ReplyDirectoryPlus};
+use crate::{ReplyEmpty, ReplyEntry, ReplyIoctl, ReplyLock, ReplyLseek, ReplyOpen, ReplyPoll};
+use crate::{ReplyStatfs, ReplyWrite, ReplyXattr};
+#[cfg(target_os = "linux")]
+use crate::PipeData;
+#[cfg(target_os = "macos")]
+use crate::ReplyXTimes;
+
+/// Filesystem trait for multi-threaded sessions.
+///
+/// The methods correspond to the methods of `Filesystem`, but take `&self` so that multiple
+/// requests can be handled concurrently. See `Filesystem` for the documentation of each
+/// method. A shared filesystem is mounted by passing it as `Arc<FS>`, which implements
+/// `Filesystem` by calling these methods, and can then be run with `Session::run_mt`.
+pub trait SyncFilesystem: Send + Sync {
+    /// Initialize filesystem.
+    fn init(&self, _req: &Request<'_>, _config: &mut KernelConfig) -> Result<(), c_int> {
+        Ok(())
+    }
+
+    /// Clean up filesystem.
+    fn destroy(&self, _req: &Request<'_>) {}
+
+    /// Look up a directory entry by name and get its attributes.
+    fn lookup(&self, _req: &Request<'_>, _parent: u64, _name: &OsStr, reply: ReplyEntry) {
+        reply.error(ENOSYS);
+    }
+
+    /// Forget about an inode.
+    fn forget(&self, _req: &Request<'_>, _ino: u64, _nlookup: u64) {}
+
+    /// Forget about multiple inodes.
+    fn batch_forget(&self, req: &Request<'_>, nodes: &[(u64, u64)]) {
+        for &(ino, nlookup) in nodes {
+            self.forget(req, ino, nlookup);
+        }
+    }
+
+    /// Get file attributes.
+    fn getattr(&self, _req: &Request<'_>, _ino: u64, reply: ReplyAttr) {
+        reply.error(ENOSYS);
+    }
+
+    /// Set file attributes.
+    fn setattr(&self, _req: &Request<'_>, _ino: u64, _mode: Option<u32>, _uid: Option<u32>, _gid: Option<u32>, _size: Option<u64>, _atime: Option<SystemTime>, _mtime: Option<SystemTime>, _fh: Option<u64>, _crtime: Option<SystemTime>, _chgtime: Option<SystemTime>, _bkuptime: Option<SystemTime>, _flags: Option<u32>, reply: ReplyAttr) {
+        reply.error(ENOSYS);
+    }
+
+    /// Read symbolic link.
+    fn readlink(&self, _req: &Request<'_>, _ino: u64, reply: ReplyData) {
+        reply.error(ENOSYS);
+    }
+
+    /// Create file node.
+    fn mknod(&self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _mode: u32, _rdev: u32, reply: ReplyEntry) {
+        reply.error(ENOSYS);
+    }
+
+    /// Create a directory.
+    fn mkdir(&self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _mode: u32, reply: ReplyEntry) {
+        reply.error(ENOSYS);
+    }
+
+    /// Remove a file.
+    fn unlink(&self, _req: &Request<'_>, _parent: u64, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Remove a directory.
+    fn rmdir(&self, _req: &Request<'_>, _parent: u64, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Create a symbolic link.
+    fn symlink(&self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _link: &Path, reply: ReplyEntry) {
+        reply.error(ENOSYS);
+    }
+
+    /// Rename a file.
+    #[allow(clippy::too_many_arguments)]
+    fn rename(&self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _newparent: u64, _newname: &OsStr, _flags: RenameFlags, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Create a hard link.
+    fn link(&self, _req: &Request<'_>, _ino: u64, _newparent: u64, _newname: &OsStr, reply: ReplyEntry) {
+        reply.error(ENOSYS);
+    }
+
+    /// Open a file.
+    fn open(&self, _req: &Request<'_>, _ino: u64, _flags: u32, reply: ReplyOpen) {
+        reply.opened(0, 0);
+    }
+
+    /// Read data.
+    fn read(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _size: u32, reply: ReplyData) {
+        reply.error(ENOSYS);
+    }
+
+    /// Write data.
+    #[allow(clippy::too_many_arguments)]
+    fn write(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _data: &[u8], _flags: u32, reply: ReplyWrite) {
+        reply.error(ENOSYS);
+    }
+
+    /// Write data from a pipe (Linux only).
+    #[cfg(target_os = "linux")]
+    #[allow(clippy::too_many_arguments)]
+    fn write_pipe(&self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, mut data: PipeData<'_>, flags: u32, reply: ReplyWrite) {
+        match data.read() {
+            Ok(data) => self.write(req, ino, fh, offset, &data, flags, reply),
+            Err(err) => reply.error(err.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
+    /// Flush method.
+    fn flush(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Release an open file.
+    #[allow(clippy::too_many_arguments)]
+    fn release(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _flags: u32, _lock_owner: u64, _flush: bool, reply: ReplyEmpty) {
+        reply.ok();
+    }
+
+    /// Synchronize file contents.
+    fn fsync(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Open a directory.
+    fn opendir(&self, _req: &Request<'_>, _ino: u64, _flags: u32, reply: ReplyOpen) {
+        reply.opened(0, 0);
+    }
+
+    /// Read directory.
+    fn readdir(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, reply: ReplyDirectory) {
+        reply.error(ENOSYS);
+    }
+
+    /// Read directory with attributes.
+    fn readdirplus(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, reply: ReplyDirectoryPlus) {
+        reply.error(ENOSYS);
+    }
+
+    /// Release an open directory.
+    fn releasedir(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _flags: u32, reply: ReplyEmpty) {
+        reply.ok();
+    }
+
+    /// Synchronize directory contents.
+    fn fsyncdir(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Get file system statistics.
+    fn statfs(&self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        reply.statfs(0, 0, 0, 0, 0, 512, 255, 0);
+    }
+
+    /// Set an extended attribute.
+    #[allow(clippy::too_many_arguments)]
+    fn setxattr(&self, _req: &Request<'_>, _ino: u64, _name: &OsStr, _value: &[u8], _flags: u32, _position: u32, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Get an extended attribute.
+    fn getxattr(&self, _req: &Request<'_>, _ino: u64, _name: &OsStr, _size: u32, reply: ReplyXattr) {
+        reply.error(ENOSYS);
+    }
+
+    /// List extended attribute names.
+    fn listxattr(&self, _req: &Request<'_>, _ino: u64, _size: u32, reply: ReplyXattr) {
+        reply.error(ENOSYS);
+    }
+
+    /// Remove an extended attribute.
+    fn removexattr(&self, _req: &Request<'_>, _ino: u64, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Check file access permissions.
+    fn access(&self, _req: &Request<'_>, _ino: u64, _mask: u32, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Create and open a file.
+    fn create(&self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _mode: u32, _flags: u32, reply: ReplyCreate) {
+        reply.error(ENOSYS);
+    }
+
+    /// Test for a POSIX file lock.
+    #[allow(clippy::too_many_arguments)]
+    fn getlk(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _lock_owner: u64, _start: u64, _end: u64, _typ: u32, _pid: u32, reply: ReplyLock) {
+        reply.error(ENOSYS);
+    }
+
+    /// Acquire, modify or release a POSIX file lock.
+    #[allow(clippy::too_many_arguments)]
+    fn setlk(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _lock_owner: u64, _start: u64, _end: u64, _typ: u32, _pid: u32, _sleep: bool, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Map block index within file to block index within device.
+    fn bmap(&self, _req: &Request<'_>, _ino: u64, _blocksize: u32, _idx: u64, reply: ReplyBmap) {
+        reply.error(ENOSYS);
+    }
+
+    /// Control device.
+    #[allow(clippy::too_many_arguments)]
+    fn ioctl(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _flags: u32, _cmd: u32, _arg: u64, _in_data: &[u8], _out_size: u32, reply: ReplyIoctl) {
+        reply.error(ENOSYS);
+    }
+
+    /// Poll for IO readiness events.
+    #[allow(clippy::too_many_arguments)]
+    fn poll(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _ph: Option<PollHandle>, _events: u32, _flags: u32, reply: ReplyPoll) {
+        reply.error(ENOSYS);
+    }
+
+    /// Preallocate or deallocate space of a file.
+    #[allow(clippy::too_many_arguments)]
+    fn fallocate(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _length: i64, _mode: FallocateFlags, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// Reposition the file offset to the next data or hole.
+    fn lseek(&self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _whence: i32, reply: ReplyLseek) {
+        reply.error(ENOSYS);
+    }
+
+    /// Copy a range of data from one file to another.
+    #[allow(clippy::too_many_arguments)]
+    fn copy_file_range(&self, _req: &Request<'_>, _ino_in: u64, _fh_in: u64, _offset_in: i64, _ino_out: u64, _fh_out: u64, _offset_out: i64, _len: u64, _flags: u64, reply: ReplyWrite) {
+        reply.error(ENOSYS);
+    }
+
+    /// macOS only: Rename the volume.
+    #[cfg(target_os = "macos")]
+    fn setvolname(&self, _req: &Request<'_>, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// macOS only (undocumented)
+    #[cfg(target_os = "macos")]
+    fn exchange(&self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _newparent: u64, _newname: &OsStr, _options: u64, reply: ReplyEmpty) {
+        reply.error(ENOSYS);
+    }
+
+    /// macOS only: Query extended times (bkuptime and crtime).
+    #[cfg(target_os = "macos")]
+    fn getxtimes(&self, _req: &Request<'_>, _ino: u64, reply: ReplyXTimes) {
+        reply.error(ENOSYS);
+    }
+}
+
+impl<FS: SyncFilesystem + ?Sized> Filesystem for Arc<FS> {
+    fn init(&mut self, req: &Request<'_>, config: &mut KernelConfig) -> Result<(), c_int> {
+        SyncFilesystem::init(&**self, req, config)
+    }
+
+    fn destroy(&mut self, req: &Request<'_>) {
+        SyncFilesystem::destroy(&**self, req);
+    }
+
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        SyncFilesystem::lookup(&**self, req, parent, name, reply);
+    }
+
+    fn forget(&mut self, req: &Request<'_>, ino: u64, nlookup: u64) {
+        SyncFilesystem::forget(&**self, req, ino, nlookup);
+    }
+
+    fn batch_forget(&mut self, req: &Request<'_>, nodes: &[(u64, u64)]) {
+        SyncFilesystem::batch_forget(&**self, req, nodes);
+    }
+
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        SyncFilesystem::getattr(&**self, req, ino, reply);
+    }
+
+    fn setattr(&mut self, req: &Request<'_>, ino: u64, mode: Option<u32>, uid: Option<u32>, gid: Option<u32>, size: Option<u64>, atime: Option<SystemTime>, mtime: Option<SystemTime>, fh: Option<u64>, crtime: Option<SystemTime>, chgtime: Option<SystemTime>, bkuptime: Option<SystemTime>, flags: Option<u32>, reply: ReplyAttr) {
+        SyncFilesystem::setattr(&**self, req, ino, mode, uid, gid, size, atime, mtime, fh, crtime, chgtime, bkuptime, flags, reply);
+    }
+
+    fn readlink(&mut self, req: &Request<'_>, ino: u64, reply: ReplyData) {
+        SyncFilesystem::readlink(&**self, req, ino, reply);
+    }
+
+    fn mknod(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, rdev: u32, reply: ReplyEntry) {
+        SyncFilesystem::mknod(&**self, req, parent, name, mode, rdev, reply);
+    }
+
+    fn mkdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, reply: ReplyEntry) {
+        SyncFilesystem::mkdir(&**self, req, parent, name, mode, reply);
+    }
+
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        SyncFilesystem::unlink(&**self, req, parent, name, reply);
+    }
+
+    fn rmdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        SyncFilesystem::rmdir(&**self, req, parent, name, reply);
+    }
+
+    fn symlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, link: &Path, reply: ReplyEntry) {
+        SyncFilesystem::symlink(&**self, req, parent, name, link, reply);
+    }
+
+    fn rename(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr, flags: RenameFlags, reply: ReplyEmpty) {
+        SyncFilesystem::rename(&**self, req, parent, name, newparent, newname, flags, reply);
+    }
+
+    fn link(&mut self, req: &Request<'_>, ino: u64, newparent: u64, newname: &OsStr, reply: ReplyEntry) {
+        SyncFilesystem::link(&**self, req, ino, newparent, newname, reply);
+    }
+
+    fn open(&mut self, req: &Request<'_>, ino: u64, flags: u32, reply: ReplyOpen) {
+        SyncFilesystem::open(&**self, req, ino, flags, reply);
+    }
+
+    fn read(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, size: u32, reply: ReplyData) {
+        SyncFilesystem::read(&**self, req, ino, fh, offset, size, reply);
+    }
+
+    fn write(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, data: &[u8], flags: u32, reply: ReplyWrite) {
+        SyncFilesystem::write(&**self, req, ino, fh, offset, data, flags, reply);
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_pipe(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, data: PipeData<'_>, flags: u32, reply: ReplyWrite) {
+        SyncFilesystem::write_pipe(&**self, req, ino, fh, offset, data, flags, reply);
+    }
+
+    fn flush(&mut self, req: &Request<'_>, ino: u64, fh: u64, lock_owner: u64, reply: ReplyEmpty) {
+        SyncFilesystem::flush(&**self, req, ino, fh, lock_owner, reply);
+    }
+
+    fn release(&mut self, req: &Request<'_>, ino: u64, fh: u64, flags: u32, lock_owner: u64, flush: bool, reply: ReplyEmpty) {
+        SyncFilesystem::release(&**self, req, ino, fh, flags, lock_owner, flush, reply);
+    }
+
+    fn fsync(&mut self, req: &Request<'_>, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
+        SyncFilesystem::fsync(&**self, req, ino, fh, datasync, reply);
+    }
+
+    fn opendir(&mut self, req: &Request<'_>, ino: u64, flags: u32, reply: ReplyOpen) {
+        SyncFilesystem::opendir(&**self, req, ino, flags, reply);
+    }
+
+    fn readdir(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, reply: ReplyDirectory) {
+        SyncFilesystem::readdir(&**self, req, ino, fh, offset, reply);
+    }
+
+    fn readdirplus(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, reply: ReplyDirectoryPlus) {
+        SyncFilesystem::readdirplus(&**self, req, ino, fh, offset, reply);
+    }
+
+    fn releasedir(&mut self, req: &Request<'_>, ino: u64, fh: u64, flags: u32, reply: ReplyEmpty) {
+        SyncFilesystem::releasedir(&**self, req, ino, fh, flags, reply);
+    }
+
+    fn fsyncdir(&mut self, req: &Request<'_>, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
+        SyncFilesystem::fsyncdir(&**self, req, ino, fh, datasync, reply);
+    }
+
+    fn statfs(&mut self, req: &Request<'_>, ino: u64, reply: ReplyStatfs) {
+        SyncFilesystem::statfs(&**self, req, ino, reply);
+    }
+
+    fn setxattr(&mut self, req: &Request<'_>, ino: u64, name: &OsStr, value: &[u8], flags: u32, position: u32, reply: ReplyEmpty) {
+        SyncFilesystem::setxattr(&**self, req, ino, name, value, flags, position, reply);
+    }
+
+    fn getxattr(&mut self, req: &Request<'_>, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        SyncFilesystem::getxattr(&**self, req, ino, name, size, reply);
+    }
+
+    fn listxattr(&mut self, req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        SyncFilesystem::listxattr(&**self, req, ino, size, reply);
+    }
+
+    fn removexattr(&mut self, req: &Request<'_>, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        SyncFilesystem::removexattr(&**self, req, ino, name, reply);
+    }
+
+    fn access(&mut self, req: &Request<'_>, ino: u64, mask: u32, reply: ReplyEmpty) {
+        SyncFilesystem::access(&**self, req, ino, mask, reply);
+    }
+
+    fn create(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, flags: u32, reply: ReplyCreate) {
+        SyncFilesystem::create(&**self, req, parent, name, mode, flags, reply);
+    }
+
+    fn getlk(&mut self, req: &Request<'_>, ino: u64, fh: u64, lock_owner: u64, start: u64, end: u64, typ: u32, pid: u32, reply: ReplyLock) {
+        SyncFilesystem::getlk(&**self, req, ino, fh, lock_owner, start, end, typ, pid, reply);
+    }
+
+    fn setlk(&mut self, req: &Request<'_>, ino: u64, fh: u64, lock_owner: u64, start: u64, end: u64, typ: u32, pid: u32, sleep: bool, reply: ReplyEmpty) {
+        SyncFilesystem::setlk(&**self, req, ino, fh, lock_owner, start, end, typ, pid, sleep, reply);
+    }
+
+    fn bmap(&mut self, req: &Request<'_>, ino: u64, blocksize: u32, idx: u64, reply: ReplyBmap) {
+        SyncFilesystem::bmap(&**self, req, ino, blocksize, idx, reply);
+    }
+
+    fn ioctl(&mut self, req: &Request<'_>, ino: u64, fh: u64, flags: u32, cmd: u32, arg: u64, in_data: &[u8], out_size: u32, reply: ReplyIoctl) {
+        SyncFilesystem::ioctl(&**self, req, ino, fh, flags, cmd, arg, in_data, out_size, reply);
+    }
+
+    fn poll(&mut self, req: &Request<'_>, ino: u64, fh: u64, ph: Option<PollHandle>, events: u32, flags: u32, reply: ReplyPoll) {
+        SyncFilesystem::poll(&**self, req, ino, fh, ph, events, flags, reply);
+    }
+
+    fn fallocate(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, length: i64, mode: FallocateFlags, reply: ReplyEmpty) {
+        SyncFilesystem::fallocate(&**self, req, ino, fh, offset, length, mode, reply);
+    }
+
+    fn lseek(&mut self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, whence: i32, reply: ReplyLseek) {
+        SyncFilesystem::lseek(&**self, req, ino, fh, offset, whence, reply);
+    }
+
+    fn copy_file_range(&mut self, req: &Request<'_>, ino_in: u64, fh_in: u64, offset_in: i64, ino_out: u64, fh_out: u64, offset_out: i64, len: u64, flags: u64, reply: ReplyWrite) {
+        SyncFilesystem::copy_file_range(&**self, req, ino_in, fh_in, offset_in, ino_out, fh_out, offset_out, len, flags, reply);
+    }
+
+    #[cfg(target_os = "macos")]
+    fn setvolname(&mut self, req: &Request<'_>, name: &OsStr, reply: ReplyEmpty) {
+        SyncFilesystem::setvolname(&**self, req, name, reply);
+    }
+
+    #[cfg(target_os = "macos")]
+    fn exchange(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr, options: u64, reply: ReplyEmpty) {
+        SyncFilesystem::exchange(&**self, req, parent, name, newparent, newname, options, reply);
+    }
+
+    #[cfg(target_os = "macos")]
+    fn getxtimes(&mut self, req: &Request<'_>, ino: u64, reply: ReplyXTimes) {
+        SyncFilesystem::getxtimes(&**self, req, ino, reply);
+    }
+}
@@ -0,0 +1,14 @@
+//! Signal handling of shutdown tokens
+//!
+//! Installing signal handlers affects the whole process, so this runs as its own test binary
+//! instead of alongside the unit tests.
+
+use fuse::ShutdownToken;
+
+#[test]
+fn shutdown_on_signal() {
+    let token = ShutdownToken::new().unwrap();
+    token.trigger_on_signals().unwrap();
+    unsafe { libc::raise(libc::SIGTERM); }
+    assert!(token.is_shutdown());
+}